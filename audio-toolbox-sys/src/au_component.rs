@@ -69,7 +69,7 @@ pub const kAudioUnitSubType_MatrixMixer: u32 = 1836608888;
 pub const kAudioUnitSubType_SpatialMixer: u32 = 862217581;
 
 pub const kAudioUnitSubType_StereoMixer: u32 = 1936554098;
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitSubType_3DMixer: u32 = 862219640;
 
 pub const kAudioUnitSubType_SphericalHeadPanner: u32 = 1936746610;
@@ -394,9 +394,9 @@ pub type AudioUnitSetParameterProc = extern fn(
 
 //================================================================================================
 
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitErr_IllegalInstrument: OSStatus = -10873;
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitErr_InstrumentTypeNotFound: OSStatus = -10872;
 
 /*
@@ -144,12 +144,12 @@ impl Default for AURenderCallbackStruct {
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
-struct Struct_AUPreset {
+pub struct AUPreset {
     pub presetNumber: i32,
     pub presetName: CFStringRef,
 }
 
-impl Default for Struct_AUPreset {
+impl Default for AUPreset {
     fn default() -> Self {
         unsafe { mem::zeroed() }
     }
@@ -529,7 +529,7 @@ pub const kOfflinePreflight_Optional: u32 = 1;
 pub const kOfflinePreflight_Required: u32 = 2;
 
 // range (3060->3999)
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_DistanceAttenuationData: AudioUnitPropertyID = 3600;
 
 #[repr(C)]
@@ -645,7 +645,7 @@ pub const kAUVoiceIOProperty_BypassVoiceProcessing: AudioUnitPropertyID = 2100;
 pub const kAUVoiceIOProperty_VoiceProcessingEnableAGC: AudioUnitPropertyID = 2101;
 pub const kAUVoiceIOProperty_MuteOutput: AudioUnitPropertyID = 2104;
 
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 pub const kAUVoiceIOProperty_VoiceProcessingQuality: AudioUnitPropertyID = 2103;
 
 pub const kAUNBandEQProperty_NumberOfBands: AudioUnitPropertyID = 2200;
@@ -667,7 +667,7 @@ pub const kAudioUnitProperty_InputAnchorTimeStamp: AudioUnitPropertyID = 3016;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
-struct AudioUnitMeterClipping {
+pub struct AudioUnitMeterClipping {
     pub peakValueSinceLastCall: c_float,
     pub sawInfinity: Boolean,
     pub sawNotANumber: Boolean,
@@ -735,17 +735,17 @@ pub const kSpatialMixerRenderingFlags_InterAuralDelay: u32 = 1;
 pub const kSpatialMixerRenderingFlags_DistanceAttenuation: u32 = (1 << 2);
 
 // Deprecated in 10.11
-#[cfg(feature = "deprecate")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_3DMixerDistanceParams: AudioUnitPropertyID = 3010;
-#[cfg(feature = "deprecate")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_3DMixerAttenuationCurve: AudioUnitPropertyID = 3013;
-#[cfg(feature = "deprecate")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_DopplerShift: AudioUnitPropertyID = 3002;
-#[cfg(feature = "deprecate")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_3DMixerRenderingFlags: AudioUnitPropertyID = 3003;
-#[cfg(feature = "deprecate")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_3DMixerDistanceAtten: AudioUnitPropertyID = 3004;
-#[cfg(feature = "deprecate")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_ReverbPreset: AudioUnitPropertyID = 3012;
 
 pub type AU3DMixerRenderingFlags = u32;
@@ -917,7 +917,7 @@ pub const kAudioUnitParameterFlag_Group: u32 = (1 << 3);
 pub const kAudioUnitParameterFlag_HasName: u32 =
     kAudioUnitParameterFlag_ValuesHaveStrings;
 
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 pub const kAudioUnitProperty_SetInputCallback: AudioUnitPropertyID = 7;
 pub const kAudioUnitProperty_SRCAlgorithm: AudioUnitPropertyID = 9;
 pub const kAudioUnitProperty_MIDIControlMapping: AudioUnitPropertyID = 17;
@@ -938,7 +938,8 @@ pub const kAudioUnitSRCAlgorithm_MediumQuality: u32 = 1668510307;
 
 // Deprecated. See AudioUnitParameterStringFromValue for equivalent
 // structure, but with clearer field names
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
+#[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct AudioUnitParameterValueName {
     pub inParamID: AudioUnitParameterID,
@@ -946,7 +947,7 @@ pub struct AudioUnitParameterValueName {
     pub outName: CFStringRef,
 }
 
-#[cfg(feature = "deprecated")]
+#[cfg(feature = "with-deprecated")]
 impl Default for AudioUnitParameterValueName {
     fn default() -> Self {
         unsafe { mem::zeroed() }
@@ -0,0 +1,17 @@
+use {AudioUnitParameter, AudioUnitParameterID, OSStatus};
+use std::os::raw::c_void;
+
+pub enum OpaqueAUListener {}
+pub type AUParameterListenerRef = *mut OpaqueAUListener;
+
+/// Passed as `mParameterID` to say that any or all of a unit's parameters
+/// may have changed.
+pub const kAUParameterListener_AnyParameter: AudioUnitParameterID = 0xFFFF_FFFF;
+
+extern {
+    pub fn AUParameterListenerNotify(
+        inSendingListener: AUParameterListenerRef,
+        inSendingObject: *mut c_void,
+        inParameter: *const AudioUnitParameter,
+    ) -> OSStatus;
+}
@@ -0,0 +1,102 @@
+//! The small part of Core Foundation the Audio Toolbox APIs hand back.
+
+use super::*;
+use std::os::raw::{c_char, c_void};
+
+pub type CFTypeRef = *const c_void;
+pub type CFPropertyListRef = CFTypeRef;
+pub type CFIndex = isize;
+
+pub enum CFAllocator {}
+pub type CFAllocatorRef = *const CFAllocator;
+
+pub enum CFArray {}
+pub type CFArrayRef = *const CFArray;
+
+pub enum CFData {}
+pub type CFDataRef = *const CFData;
+
+pub enum CFDictionary {}
+pub type CFDictionaryRef = *const CFDictionary;
+pub type CFMutableDictionaryRef = *mut CFDictionary;
+
+pub enum CFError {}
+pub type CFErrorRef = *const CFError;
+
+pub enum CFString {}
+pub type CFStringRef = *const CFString;
+
+pub enum CFURL {}
+pub type CFURLRef = *const CFURL;
+
+pub type CFStringEncoding = u32;
+pub const kCFStringEncodingUTF8: CFStringEncoding = 0x0800_0100;
+
+pub type CFOptionFlags = usize;
+
+pub type CFPropertyListFormat = CFIndex;
+pub const kCFPropertyListOpenStepFormat: CFPropertyListFormat = 1;
+pub const kCFPropertyListXMLFormat_v1_0: CFPropertyListFormat = 100;
+pub const kCFPropertyListBinaryFormat_v1_0: CFPropertyListFormat = 200;
+
+pub type CFPropertyListMutabilityOptions = CFOptionFlags;
+pub const kCFPropertyListImmutable: CFPropertyListMutabilityOptions = 0;
+pub const kCFPropertyListMutableContainers: CFPropertyListMutabilityOptions = 1;
+pub const kCFPropertyListMutableContainersAndLeaves: CFPropertyListMutabilityOptions = 2;
+
+extern {
+    pub fn CFRelease(cf: CFTypeRef);
+
+    pub fn CFArrayGetCount(theArray: CFArrayRef) -> CFIndex;
+    pub fn CFArrayGetValueAtIndex(theArray: CFArrayRef, idx: CFIndex) -> *const c_void;
+
+    pub fn CFDataCreate(
+        allocator: CFAllocatorRef,
+        bytes: *const u8,
+        length: CFIndex,
+    ) -> CFDataRef;
+    pub fn CFDataGetLength(theData: CFDataRef) -> CFIndex;
+    pub fn CFDataGetBytePtr(theData: CFDataRef) -> *const u8;
+
+    pub fn CFDictionaryCreateMutableCopy(
+        allocator: CFAllocatorRef,
+        capacity: CFIndex,
+        theDict: CFDictionaryRef,
+    ) -> CFMutableDictionaryRef;
+    pub fn CFDictionaryRemoveValue(theDict: CFMutableDictionaryRef, key: *const c_void);
+
+    pub fn CFPropertyListCreateData(
+        allocator: CFAllocatorRef,
+        propertyList: CFPropertyListRef,
+        format: CFPropertyListFormat,
+        options: CFOptionFlags,
+        error: *mut CFErrorRef,
+    ) -> CFDataRef;
+    pub fn CFPropertyListCreateWithData(
+        allocator: CFAllocatorRef,
+        data: CFDataRef,
+        options: CFOptionFlags,
+        format: *mut CFPropertyListFormat,
+        error: *mut CFErrorRef,
+    ) -> CFPropertyListRef;
+
+    pub fn CFStringCreateWithBytes(
+        alloc: CFAllocatorRef,
+        bytes: *const u8,
+        numBytes: CFIndex,
+        encoding: CFStringEncoding,
+        isExternalRepresentation: Boolean,
+    ) -> CFStringRef;
+
+    pub fn CFStringGetLength(theString: CFStringRef) -> CFIndex;
+    pub fn CFStringGetMaximumSizeForEncoding(
+        length: CFIndex,
+        encoding: CFStringEncoding,
+    ) -> CFIndex;
+    pub fn CFStringGetCString(
+        theString: CFStringRef,
+        buffer: *mut c_char,
+        bufferSize: CFIndex,
+        encoding: CFStringEncoding,
+    ) -> Boolean;
+}
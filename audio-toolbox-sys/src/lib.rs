@@ -10,27 +10,100 @@ mod audio_component;
 mod audio_output_unit;
 mod audio_unit_parameters;
 mod audio_unit_properties;
+mod audio_unit_utilities;
+mod core_foundation;
 
 pub use au_component::*;
 pub use audio_component::*;
 pub use audio_output_unit::*;
 pub use audio_unit_parameters::*;
 pub use audio_unit_properties::*;
+pub use audio_unit_utilities::*;
+pub use core_foundation::*;
 
 pub type Boolean = u8;
 pub type OSStatus = i32;
 pub type FourCharCode = u32;
 pub type OSType = FourCharCode;
 
-// Core Foundation types
-pub enum CFDictionary {}
-pub type CFDictionaryRef = *const CFDictionary;
-
-pub enum CFString {}
-pub type CFStringRef = *const CFString;
-
-pub enum CFURL {}
-pub type CFURLRef = *const CFURL;
-
 // Core Audio types
-pub use core_audio_sys::{AudioBufferList, AudioTimeStamp};
+pub use core_audio_sys::{AudioBuffer, AudioBufferList, AudioStreamBasicDescription,
+                         AudioTimeStamp};
+pub use core_audio_sys::{kAudioFormatFlagIsAlignedHigh, kAudioFormatFlagIsBigEndian,
+                         kAudioFormatFlagIsFloat, kAudioFormatFlagIsNonInterleaved,
+                         kAudioFormatFlagIsNonMixable, kAudioFormatFlagIsPacked,
+                         kAudioFormatFlagIsSignedInteger, kAudioFormatLinearPCM,
+                         kAudioTimeStampHostTimeValid, kAudioTimeStampSampleTimeValid};
+
+// Channel layouts
+pub use core_audio_sys::{AudioChannelBitmap, AudioChannelDescription, AudioChannelFlags,
+                         AudioChannelLabel, AudioChannelLayout, AudioChannelLayoutTag};
+pub use core_audio_sys::{kAudioChannelLayoutTag_Ambisonic_B_Format,
+                         kAudioChannelLayoutTag_AudioUnit_6_0,
+                         kAudioChannelLayoutTag_AudioUnit_7_0,
+                         kAudioChannelLayoutTag_AudioUnit_7_0_Front,
+                         kAudioChannelLayoutTag_Binaural, kAudioChannelLayoutTag_Cube,
+                         kAudioChannelLayoutTag_DiscreteInOrder,
+                         kAudioChannelLayoutTag_Emagic_Default_7_1,
+                         kAudioChannelLayoutTag_Hexagonal, kAudioChannelLayoutTag_ITU_2_1,
+                         kAudioChannelLayoutTag_ITU_2_2,
+                         kAudioChannelLayoutTag_MPEG_3_0_A,
+                         kAudioChannelLayoutTag_MPEG_3_0_B,
+                         kAudioChannelLayoutTag_MPEG_4_0_A,
+                         kAudioChannelLayoutTag_MPEG_4_0_B,
+                         kAudioChannelLayoutTag_MPEG_5_0_A,
+                         kAudioChannelLayoutTag_MPEG_5_0_B,
+                         kAudioChannelLayoutTag_MPEG_5_0_C,
+                         kAudioChannelLayoutTag_MPEG_5_0_D,
+                         kAudioChannelLayoutTag_MPEG_5_1_A,
+                         kAudioChannelLayoutTag_MPEG_5_1_B,
+                         kAudioChannelLayoutTag_MPEG_5_1_C,
+                         kAudioChannelLayoutTag_MPEG_5_1_D,
+                         kAudioChannelLayoutTag_MPEG_6_1_A,
+                         kAudioChannelLayoutTag_MPEG_7_1_A,
+                         kAudioChannelLayoutTag_MPEG_7_1_B,
+                         kAudioChannelLayoutTag_MPEG_7_1_C,
+                         kAudioChannelLayoutTag_MatrixStereo,
+                         kAudioChannelLayoutTag_MidSide, kAudioChannelLayoutTag_Mono,
+                         kAudioChannelLayoutTag_Octagonal,
+                         kAudioChannelLayoutTag_Pentagonal,
+                         kAudioChannelLayoutTag_Quadraphonic,
+                         kAudioChannelLayoutTag_SMPTE_DTV, kAudioChannelLayoutTag_Stereo,
+                         kAudioChannelLayoutTag_StereoHeadphones,
+                         kAudioChannelLayoutTag_Unknown,
+                         kAudioChannelLayoutTag_UseChannelBitmap,
+                         kAudioChannelLayoutTag_UseChannelDescriptions,
+                         kAudioChannelLayoutTag_XY};
+pub use core_audio_sys::{kAudioChannelLabel_Center, kAudioChannelLabel_CenterSurround,
+                         kAudioChannelLabel_Discrete, kAudioChannelLabel_LFE2,
+                         kAudioChannelLabel_LFEScreen, kAudioChannelLabel_Left,
+                         kAudioChannelLabel_LeftCenter, kAudioChannelLabel_LeftSurround,
+                         kAudioChannelLabel_LeftSurroundDirect,
+                         kAudioChannelLabel_LeftTotal, kAudioChannelLabel_LeftWide,
+                         kAudioChannelLabel_Mono, kAudioChannelLabel_RearSurroundLeft,
+                         kAudioChannelLabel_RearSurroundRight, kAudioChannelLabel_Right,
+                         kAudioChannelLabel_RightCenter, kAudioChannelLabel_RightSurround,
+                         kAudioChannelLabel_RightSurroundDirect,
+                         kAudioChannelLabel_RightTotal, kAudioChannelLabel_RightWide,
+                         kAudioChannelLabel_TopBackCenter, kAudioChannelLabel_TopBackLeft,
+                         kAudioChannelLabel_TopBackRight,
+                         kAudioChannelLabel_TopCenterSurround, kAudioChannelLabel_Unknown,
+                         kAudioChannelLabel_Unused, kAudioChannelLabel_UseCoordinates,
+                         kAudioChannelLabel_VerticalHeightCenter,
+                         kAudioChannelLabel_VerticalHeightLeft,
+                         kAudioChannelLabel_VerticalHeightRight};
+pub use core_audio_sys::{kAudioChannelBit_Center, kAudioChannelBit_CenterSurround,
+                         kAudioChannelBit_LFEScreen, kAudioChannelBit_Left,
+                         kAudioChannelBit_LeftCenter, kAudioChannelBit_LeftSurround,
+                         kAudioChannelBit_LeftSurroundDirect, kAudioChannelBit_Right,
+                         kAudioChannelBit_RightCenter, kAudioChannelBit_RightSurround,
+                         kAudioChannelBit_RightSurroundDirect,
+                         kAudioChannelBit_TopBackCenter, kAudioChannelBit_TopBackLeft,
+                         kAudioChannelBit_TopBackRight,
+                         kAudioChannelBit_TopCenterSurround,
+                         kAudioChannelBit_VerticalHeightCenter,
+                         kAudioChannelBit_VerticalHeightLeft,
+                         kAudioChannelBit_VerticalHeightRight, kAudioChannelFlags_AllOff,
+                         kAudioChannelFlags_Meters,
+                         kAudioChannelFlags_RectangularCoordinates,
+                         kAudioChannelFlags_SphericalCoordinates};
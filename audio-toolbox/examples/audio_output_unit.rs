@@ -1,7 +1,8 @@
 extern crate audio_toolbox;
 extern crate core_audio;
 
-use audio_toolbox::{AudioOutputUnit, AudioUnitScope, AudioUnitSubType};
+use audio_toolbox::{AudioComponentQuery, AudioOutputUnit, AudioUnitScope,
+                    AudioUnitSubType};
 use core_audio::AudioDevice;
 
 macro_rules! p {
@@ -32,13 +33,8 @@ fn main() {
     });
     assert!(unit.is_ok());
 
-    let unit = AudioOutputUnit::new_instance(|c| {
-        if let Ok(desc) = c.description() {
-            desc.sub_kind() == AudioUnitSubType::HALOutput
-        } else {
-            false
-        }
-    }).unwrap();
+    let query = AudioComponentQuery::new().sub_kind(AudioUnitSubType::HALOutput);
+    let unit = AudioOutputUnit::find_instance(query).unwrap();
     assert!(unit.is_some());
     let unit = unit.unwrap();
 
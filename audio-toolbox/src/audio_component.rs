@@ -1,20 +1,11 @@
-use {AudioUnitManufacturer, AudioUnitSubType, AudioUnitType};
-use audio_toolbox_sys as ffi;
+use {AudioUnitManufacturer, AudioUnitSubType, AudioUnitType, FourCC, Result};
+use ffi;
 use call;
-use core_audio::Result;
 use std::ptr;
 use util::component_instance_dispose;
 
-fn format_ostype(fcc: ffi::OSType) -> String {
-    let a: char = (fcc >> 24) as u8 as char;
-    let b: char = (fcc >> 16) as u8 as char;
-    let c: char = (fcc >> 8) as u8 as char;
-    let d: char = fcc as u8 as char;
-
-    format!("'{}{}{}{}' (0x{:08x})", a, b, c, d, fcc)
-}
-
 bitflags! {
+    #[derive(Default)]
     pub struct AudioComponentFlags: ffi::AudioComponentFlags {
         const UNSEARCHABLE = ffi::kAudioComponentFlag_Unsearchable;
     }
@@ -27,114 +18,50 @@ ffi_type_stack! {
 }
 
 impl AudioComponentDescription {
-    pub fn new(
-        kind: ffi::OSType,
-        sub_kind: ffi::OSType,
-        manufacturer: ffi::OSType,
-    ) -> Self {
+    /// A description matching components by type, subtype and
+    /// manufacturer, given as the enums, `FourCC`s or raw `OSType`s. Zero
+    /// matches any value.
+    pub fn new<K, S, M>(kind: K, sub_kind: S, manufacturer: M) -> Self
+    where
+        K: Into<FourCC>,
+        S: Into<FourCC>,
+        M: Into<FourCC>,
+    {
         AudioComponentDescription(ffi::AudioComponentDescription {
-            componentType: kind,
-            componentSubType: sub_kind,
-            componentManufacturer: manufacturer,
+            componentType: kind.into().as_u32(),
+            componentSubType: sub_kind.into().as_u32(),
+            componentManufacturer: manufacturer.into().as_u32(),
             ..Default::default()
         })
     }
 }
 
 impl AudioComponentDescriptionRef {
-    pub fn kind(&self) -> AudioUnitType {
-        use AudioUnitType::*;
+    pub fn kind_code(&self) -> FourCC {
         let desc: &ffi::AudioComponentDescription = unsafe { &*self.as_ptr() };
-        match desc.componentType {
-            ffi::kAudioUnitType_Output => Output,
-            ffi::kAudioUnitType_MusicDevice => MusicDevice,
-            ffi::kAudioUnitType_MusicEffect => MusicEffect,
-            ffi::kAudioUnitType_FormatConverter => FormatConverter,
-            ffi::kAudioUnitType_Effect => Effect,
-            ffi::kAudioUnitType_Mixer => Mixer,
-            ffi::kAudioUnitType_Panner => Panner,
-            ffi::kAudioUnitType_Generator => Generator,
-            ffi::kAudioUnitType_OfflineEffect => OfflineEffect,
-            ffi::kAudioUnitType_MIDIProcessor => MIDIProcessor,
-            t => panic!("Unknown AudioUnitType {}", format_ostype(t)),
-        }
+        FourCC(desc.componentType)
     }
 
-    pub fn sub_kind(&self) -> AudioUnitSubType {
-        use AudioUnitSubType::*;
+    pub fn sub_kind_code(&self) -> FourCC {
         let desc: &ffi::AudioComponentDescription = unsafe { &*self.as_ptr() };
-        match desc.componentSubType {
-            ffi::kAudioUnitSubType_GenericOutput => GenericOutput,
-            ffi::kAudioUnitSubType_VoiceProcessingIO => VoiceProcessingIO,
-
-            ffi::kAudioUnitSubType_HALOutput => HALOutput,
-            ffi::kAudioUnitSubType_DefaultOutput => DefaultOutput,
-            ffi::kAudioUnitSubType_SystemOutput => SystemOutput,
-
-            ffi::kAudioUnitSubType_DLSSynth => DLSSynth,
-            ffi::kAudioUnitSubType_Sampler => Sampler,
-            ffi::kAudioUnitSubType_MIDISynth => MIDISynth,
-
-            ffi::kAudioUnitSubType_AUConverter => AUConverter,
-            ffi::kAudioUnitSubType_Varispeed => Varispeed,
-            ffi::kAudioUnitSubType_DeferredRenderer => DeferredRenderer,
-            ffi::kAudioUnitSubType_Splitter => Splitter,
-            ffi::kAudioUnitSubType_MultiSplitter => MultiSplitter,
-            ffi::kAudioUnitSubType_Merger => Merger,
-            ffi::kAudioUnitSubType_NewTimePitch => NewTimePitch,
-            ffi::kAudioUnitSubType_AUiPodTimeOther => AUiPodTimeOther,
-            ffi::kAudioUnitSubType_RoundTripAAC => RoundTripAAC,
-
-            // This is same as kAudioUnitSubType_Pitch
-            //ffi::kAudioUnitSubType_TimePitch => TimePitch,
-            ffi::kAudioUnitSubType_PeakLimiter => PeakLimiter,
-            ffi::kAudioUnitSubType_DynamicsProcessor => DynamicsProcessor,
-            ffi::kAudioUnitSubType_LowPassFilter => LowPassFilter,
-            ffi::kAudioUnitSubType_HighPassFilter => HighPassFilter,
-            ffi::kAudioUnitSubType_BandPassFilter => BandPassFilter,
-            ffi::kAudioUnitSubType_HighShelfFilter => HighShelfFilter,
-            ffi::kAudioUnitSubType_LowShelfFilter => LowShelfFilter,
-            ffi::kAudioUnitSubType_ParametricEQ => ParametricEQ,
-            ffi::kAudioUnitSubType_Distortion => Distortion,
-            ffi::kAudioUnitSubType_Delay => Delay,
-            ffi::kAudioUnitSubType_SampleDelay => SampleDelay,
-            ffi::kAudioUnitSubType_NBandEQ => NBandEQ,
-
-            ffi::kAudioUnitSubType_GraphicEQ => GraphicEQ,
-            ffi::kAudioUnitSubType_MultiBandCompressor => MultiBandCompressor,
-            ffi::kAudioUnitSubType_MatrixReverb => MatrixReverb,
-            ffi::kAudioUnitSubType_Pitch => Pitch,
-            ffi::kAudioUnitSubType_AUFilter => AUFilter,
-            ffi::kAudioUnitSubType_NetSend => NetSend,
-            ffi::kAudioUnitSubType_RogerBeep => RogerBeep,
-
-            ffi::kAudioUnitSubType_MultiChannelMixer => MultiChannelMixer,
-            ffi::kAudioUnitSubType_MatrixMixer => MatrixMixer,
-            ffi::kAudioUnitSubType_SpatialMixer => SpatialMixer,
-
-            ffi::kAudioUnitSubType_StereoMixer => StereoMixer,
-            #[cfg(feature = "deprecated")]
-            ffi::kAudioUnitSubType_3DMixer => _3DMixer,
-
-            ffi::kAudioUnitSubType_SphericalHeadPanner => SphericalHeadPanner,
-            ffi::kAudioUnitSubType_VectorPanner => VectorPanner,
-            ffi::kAudioUnitSubType_SoundFieldPanner => SoundFieldPanner,
-            ffi::kAudioUnitSubType_HRTFPanner => HRTFPanner,
-
-            ffi::kAudioUnitSubType_NetReceive => NetReceive,
-            ffi::kAudioUnitSubType_ScheduledSoundPlayer => ScheduledSoundPlayer,
-            ffi::kAudioUnitSubType_AudioFilePlayer => AudioFilePlayer,
-            st => panic!("Unknown AudioUnitSubType {}", format_ostype(st)),
-        }
+        FourCC(desc.componentSubType)
     }
 
-    pub fn manufacturer(&self) -> AudioUnitManufacturer {
-        use AudioUnitManufacturer::*;
+    pub fn manufacturer_code(&self) -> FourCC {
         let desc: &ffi::AudioComponentDescription = unsafe { &*self.as_ptr() };
-        match desc.componentManufacturer {
-            ffi::kAudioUnitManufacturer_Apple => Apple,
-            m => panic!("Unknown AudioUnitManufacture {:?}", format_ostype(m)),
-        }
+        FourCC(desc.componentManufacturer)
+    }
+
+    pub fn kind(&self) -> AudioUnitType {
+        self.kind_code().into()
+    }
+
+    pub fn sub_kind(&self) -> AudioUnitSubType {
+        self.sub_kind_code().into()
+    }
+
+    pub fn manufacturer(&self) -> AudioUnitManufacturer {
+        self.manufacturer_code().into()
     }
 
     pub fn flags(&self) -> u32 {
@@ -145,6 +72,125 @@ impl AudioComponentDescriptionRef {
     pub fn count(&self) -> usize {
         unsafe { ffi::AudioComponentCount(self.as_ptr()) as _ }
     }
+
+    /// Whether a component described by `component` is one this description
+    /// would find; see `component_description_matches`.
+    pub fn matches(&self, component: &AudioComponentDescriptionRef) -> bool {
+        unsafe { component_description_matches(&*self.as_ptr(), &*component.as_ptr()) }
+    }
+}
+
+/// Whether `AudioComponentFindNext` finds a component described by
+/// `component` when searching for `query`.
+///
+/// Zero type, subtype and manufacturer fields in `query` match any value,
+/// and the component's flags must equal `query`'s under its
+/// `componentFlagsMask`. Unsearchable components are only found when all
+/// three fields are given.
+pub fn component_description_matches(
+    query: &ffi::AudioComponentDescription,
+    component: &ffi::AudioComponentDescription,
+) -> bool {
+    let field = |wanted, ours| wanted == 0 || wanted == ours;
+    let wildcard = query.componentType == 0 || query.componentSubType == 0 ||
+        query.componentManufacturer == 0;
+    if wildcard && component.componentFlags & ffi::kAudioComponentFlag_Unsearchable != 0 {
+        return false;
+    }
+    field(query.componentType, component.componentType) &&
+        field(query.componentSubType, component.componentSubType) &&
+        field(query.componentManufacturer, component.componentManufacturer) &&
+        (component.componentFlags & query.componentFlagsMask) ==
+            (query.componentFlags & query.componentFlagsMask)
+}
+
+/// Builds the `AudioComponentDescription` for a component search.
+///
+/// ```ignore
+/// let query = AudioComponentQuery::new()
+///     .kind(AudioUnitType::Effect)
+///     .manufacturer(b"appl");
+/// let delay = query.sub_kind(AudioUnitSubType::Delay).first();
+/// ```
+///
+/// Fields that aren't set match any component.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AudioComponentQuery {
+    kind: FourCC,
+    sub_kind: FourCC,
+    manufacturer: FourCC,
+    flags: AudioComponentFlags,
+    flags_mask: AudioComponentFlags,
+}
+
+impl AudioComponentQuery {
+    pub fn new() -> AudioComponentQuery {
+        AudioComponentQuery::default()
+    }
+
+    pub fn kind<K: Into<FourCC>>(mut self, kind: K) -> Self {
+        self.kind = kind.into();
+        self
+    }
+
+    pub fn sub_kind<S: Into<FourCC>>(mut self, sub_kind: S) -> Self {
+        self.sub_kind = sub_kind.into();
+        self
+    }
+
+    pub fn manufacturer<M: Into<FourCC>>(mut self, manufacturer: M) -> Self {
+        self.manufacturer = manufacturer.into();
+        self
+    }
+
+    /// Only match components with all of `flags` set.
+    pub fn flags(mut self, flags: AudioComponentFlags) -> Self {
+        self.flags.insert(flags);
+        self.flags_mask.insert(flags);
+        self
+    }
+
+    /// Only match components with none of `flags` set.
+    pub fn without_flags(mut self, flags: AudioComponentFlags) -> Self {
+        self.flags.remove(flags);
+        self.flags_mask.insert(flags);
+        self
+    }
+
+    pub fn description(&self) -> AudioComponentDescription {
+        AudioComponentDescription(ffi::AudioComponentDescription {
+            componentType: self.kind.as_u32(),
+            componentSubType: self.sub_kind.as_u32(),
+            componentManufacturer: self.manufacturer.as_u32(),
+            componentFlags: self.flags.bits(),
+            componentFlagsMask: self.flags_mask.bits(),
+        })
+    }
+
+    /// Whether a component described by `component` matches the query.
+    pub fn matches(&self, component: &AudioComponentDescriptionRef) -> bool {
+        self.description().matches(component)
+    }
+
+    /// The first matching component.
+    pub fn first(&self) -> Option<AudioComponent> {
+        AudioComponent::iter(&self.description()).next()
+    }
+
+    /// Every matching component, in the order AudioToolbox finds them.
+    pub fn components(&self) -> Vec<AudioComponent> {
+        AudioComponent::iter(&self.description()).collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.description().count()
+    }
+}
+
+impl ::std::convert::From<AudioComponentQuery> for AudioComponentDescription {
+    fn from(query: AudioComponentQuery) -> Self {
+        query.description()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -174,12 +220,8 @@ impl AudioComponent {
         }
     }
 
-    pub fn version(&self) -> Result<u32> {
-        let mut version = 0u32;
-        unsafe {
-            call::cvt_r(ffi::AudioComponentGetVersion(self.0, &mut version))?;
-        }
-        Ok(version)
+    pub fn as_ptr(&self) -> ffi::AudioComponent {
+        self.0
     }
 }
 
@@ -224,3 +266,61 @@ ffi_type_heap! {
     pub struct AudioComponentInstance;
     pub struct AudioComponentInstanceRef;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(flags: u32) -> ffi::AudioComponentDescription {
+        ffi::AudioComponentDescription {
+            componentType: ffi::kAudioUnitType_Effect,
+            componentSubType: ffi::kAudioUnitSubType_Delay,
+            componentManufacturer: ffi::kAudioUnitManufacturer_Apple,
+            componentFlags: flags,
+            componentFlagsMask: 0,
+        }
+    }
+
+    fn matches(
+        query: AudioComponentQuery,
+        component: &ffi::AudioComponentDescription,
+    ) -> bool {
+        component_description_matches(&query.description().0, component)
+    }
+
+    #[test]
+    fn unset_fields_match_anything() {
+        let delay = component(0);
+        assert!(matches(AudioComponentQuery::new(), &delay));
+        assert!(matches(AudioComponentQuery::new().kind(AudioUnitType::Effect), &delay));
+        assert!(matches(AudioComponentQuery::new().manufacturer(b"appl"), &delay));
+        assert!(!matches(AudioComponentQuery::new().kind(AudioUnitType::Mixer), &delay));
+        assert!(!matches(
+            AudioComponentQuery::new().sub_kind(AudioUnitSubType::PeakLimiter),
+            &delay
+        ));
+    }
+
+    #[test]
+    fn unsearchable_needs_full_description() {
+        let hidden = component(ffi::kAudioComponentFlag_Unsearchable);
+        let query = AudioComponentQuery::new().kind(AudioUnitType::Effect);
+        assert!(!matches(query, &hidden));
+        let query = query.sub_kind(AudioUnitSubType::Delay);
+        assert!(matches(query.manufacturer(AudioUnitManufacturer::Apple), &hidden));
+    }
+
+    #[test]
+    fn flags_are_compared_under_mask() {
+        let query = AudioComponentQuery::new()
+            .kind(AudioUnitType::Effect)
+            .sub_kind(AudioUnitSubType::Delay)
+            .manufacturer(AudioUnitManufacturer::Apple);
+        let hidden = component(ffi::kAudioComponentFlag_Unsearchable);
+        let unsearchable = AudioComponentFlags::UNSEARCHABLE;
+        assert!(matches(query.flags(unsearchable), &hidden));
+        assert!(!matches(query.flags(unsearchable), &component(0)));
+        assert!(!matches(query.without_flags(unsearchable), &hidden));
+        assert!(matches(query.without_flags(unsearchable), &component(0)));
+    }
+}
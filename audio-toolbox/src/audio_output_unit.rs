@@ -1,10 +1,16 @@
-use {AudioComponent, AudioComponentDescription, AudioComponentInstance, AudioDevice,
-     AudioTimeStampRef, AudioUnitElement, AudioUnitParameter, AudioUnitProperty,
-     AudioUnitRef, AudioUnitScope, Result};
+use {AudioComponent, AudioComponentInstance, AudioComponentQuery, AudioDevice,
+     AudioTimeStampRef, AudioUnitElement, AudioUnitManufacturer, AudioUnitParameter,
+     AudioUnitProperty, AudioUnitRef, AudioUnitRenderActionFlags, AudioUnitScope,
+     AudioUnitType, Result};
 use AudioUnitScope::{Global, Input};
-use audio_toolbox_sys as ffi;
+use backend::{self, AudioUnitBackend};
+use ffi;
 use call;
-use std::{mem, ops};
+use panic;
+use props;
+use std::{mem, ops, ptr, result};
+use std::os::raw::c_void;
+use std::sync::Arc;
 use util::component_instance_dispose;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -24,6 +30,52 @@ ffi_type_heap! {
     pub struct AudioOutputUnitRef;
 }
 
+/// An input callback. Returning an error reports that status to the unit.
+pub type AudioOutputUnitInputCB = FnMut(
+    &AudioOutputUnitRef,
+    &AudioUnitRenderActionFlags,
+    &AudioTimeStampRef,
+    u32,
+    u32,
+) -> result::Result<(), ffi::OSStatus>
+    + Send;
+
+struct InputCallbackThunk {
+    unit: ffi::AudioUnit,
+    cb: Box<AudioOutputUnitInputCB>,
+}
+
+/// An input callback installed by `set_input_callback`. Dropping the guard
+/// removes the callback from the unit and frees the closure, after waiting
+/// out a call already under way on the I/O thread. If the unit refuses to
+/// let go of the callback it may still call it, so the closure is leaked
+/// instead; use `remove_input_callback` to find out whether that happened.
+#[must_use]
+pub struct InputCallbackGuard<'a> {
+    unit: &'a AudioOutputUnitRef,
+    thunk: *mut InputCallbackThunk,
+}
+
+impl<'a> InputCallbackGuard<'a> {
+    /// Clear the unit's input callback, freeing the closure if the unit let
+    /// go of it.
+    fn unregister(&self) -> Result<()> {
+        let callback = ffi::AURenderCallbackStruct {
+            inputProc: None,
+            inputProcRefCon: ptr::null_mut(),
+        };
+        self.unit.set_input_callback_struct(&callback)?;
+        unsafe { drop(Box::from_raw(self.thunk)) };
+        Ok(())
+    }
+}
+
+impl<'a> Drop for InputCallbackGuard<'a> {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+    }
+}
+
 impl AudioOutputUnit {
     // Properties
     pub const CURRENT_DEVICE: AudioUnitProperty =
@@ -41,20 +93,53 @@ impl AudioOutputUnit {
     // Parameters
     pub const VOLUME: AudioUnitParameter = ffi::kHALOutputParam_Volume;
 
+    // Elements
+    /// The element that plays to the device.
+    pub const OUTPUT_ELEMENT: AudioUnitElement = 0;
+    /// The element that records from the device.
+    pub const INPUT_ELEMENT: AudioUnitElement = 1;
+
+    /// The element that carries device I/O in `scope`: the input element's
+    /// input scope or the output element's output scope.
+    pub fn io_element(scope: AudioUnitScope) -> AudioUnitElement {
+        if scope == Input {
+            AudioOutputUnit::INPUT_ELEMENT
+        } else {
+            AudioOutputUnit::OUTPUT_ELEMENT
+        }
+    }
+
+    /// Create an instance of the first of Apple's output units that `f`
+    /// accepts.
     pub fn new_instance<F>(f: F) -> Result<Option<AudioOutputUnit>>
     where
         F: Fn(&AudioComponent) -> bool,
     {
-        let desc = AudioComponentDescription::new(
-            ffi::kAudioUnitType_Output,
-            0,
-            ffi::kAudioUnitManufacturer_Apple,
-        );
-        match AudioComponent::iter(desc.as_ref()).find(|c| f(c)) {
+        let query = AudioComponentQuery::new().manufacturer(AudioUnitManufacturer::Apple);
+        let desc = query.kind(AudioUnitType::Output).description();
+        match AudioComponent::iter(&desc).find(|c| f(c)) {
             Some(c) => c.new_instance().map(|ci| Some(ci.into())),
             None => Ok(None),
         }
     }
+
+    /// Create an instance of the first output unit that `query` matches.
+    /// The query's type is always `AudioUnitType::Output`.
+    pub fn find_instance(query: AudioComponentQuery) -> Result<Option<AudioOutputUnit>> {
+        match query.kind(AudioUnitType::Output).first() {
+            Some(c) => c.new_instance().map(|ci| Some(ci.into())),
+            None => Ok(None),
+        }
+    }
+
+    /// Create an output unit whose calls are all handled by `backend`
+    /// instead of AudioToolbox.
+    pub fn with_backend<B>(backend: B) -> AudioOutputUnit
+    where
+        B: AudioUnitBackend + 'static,
+    {
+        unsafe { AudioOutputUnit::from_ptr(backend::install(Arc::new(backend))) }
+    }
 }
 
 impl ::std::convert::From<AudioComponentInstance> for AudioOutputUnit {
@@ -68,14 +153,14 @@ impl ::std::convert::From<AudioComponentInstance> for AudioOutputUnit {
 impl AudioOutputUnitRef {
     pub fn start(&self) -> Result<()> {
         unsafe {
-            call::cvt_r(ffi::AudioOutputUnitStart(self.as_ptr()))?;
+            call::cvt_r(backend::get(self.as_ptr()).output_unit_start(self.as_ptr()))?;
         }
         Ok(())
     }
 
     pub fn stop(&self) -> Result<()> {
         unsafe {
-            call::cvt_r(ffi::AudioOutputUnitStop(self.as_ptr()))?;
+            call::cvt_r(backend::get(self.as_ptr()).output_unit_stop(self.as_ptr()))?;
         }
         Ok(())
     }
@@ -85,7 +170,7 @@ impl AudioOutputUnitRef {
     }
 
     pub fn is_running(&self) -> Result<bool> {
-        Ok(self.get_property::<u32>(AudioOutputUnit::IS_RUNNING, Global, 0)? != 0)
+        Ok(self.get(props::IsRunning, Global, 0)? != 0)
     }
 
     pub fn channel_map(
@@ -93,40 +178,29 @@ impl AudioOutputUnitRef {
         scope: AudioUnitScope,
         element: AudioUnitElement,
     ) -> Result<Vec<i32>> {
-        self.get_property_array(AudioOutputUnit::CHANNEL_MAP, scope, element)
+        self.get(props::ChannelMap, scope, element)
     }
 
     pub fn enable_io(&self, scope: AudioUnitScope) -> Result<bool> {
-        let element = if scope == Input { 1 } else { 0 };
-        Ok(
-            try!(self.get_property::<u32>(AudioOutputUnit::ENABLE_IO, scope, element))
-                != 0,
-        )
+        let element = AudioOutputUnit::io_element(scope);
+        Ok(self.get(props::EnableIO, scope, element)? != 0)
     }
 
-    // pub fn input_callback(&self) -> AURenderCallbackStruct {
-    //     self.get_property(AudioOutputUnit::SET_INPUT_CALLBACK, Global, 0)
-    // }
-
     pub fn has_io(&self, scope: AudioUnitScope) -> Result<bool> {
-        let element = if scope == Input { 1 } else { 0 };
-        Ok(try!(self.get_property::<u32>(AudioOutputUnit::HAS_IO, scope, element)) != 0)
+        let element = AudioOutputUnit::io_element(scope);
+        Ok(self.get(props::HasIO, scope, element)? != 0)
     }
 
     pub fn start_timestamps_at_zero(&self) -> Result<bool> {
-        Ok(try!(self.get_property::<u32>(
-            AudioOutputUnit::START_TIMESTAMPS_AT_ZERO,
-            Global,
-            0,
-        )) != 0)
+        Ok(self.get(props::StartTimestampsAtZero, Global, 0)? != 0)
     }
 
-    pub fn set_current_device(&mut self, device: &AudioDevice) -> Result<()> {
+    pub fn set_current_device(&self, device: &AudioDevice) -> Result<()> {
         self.set_property(AudioOutputUnit::CURRENT_DEVICE, Global, 0, device)
     }
 
     pub fn set_channel_map(
-        &mut self,
+        &self,
         scope: AudioUnitScope,
         element: AudioUnitElement,
         data: &[i32],
@@ -134,31 +208,89 @@ impl AudioOutputUnitRef {
         self.set_property_array(AudioOutputUnit::CHANNEL_MAP, scope, element, data)
     }
 
-    pub fn set_enable_io(&mut self, scope: AudioUnitScope, enable: bool) -> Result<()> {
-        let element = if scope == Input { 1 } else { 0 };
+    pub fn set_enable_io(&self, scope: AudioUnitScope, enable: bool) -> Result<()> {
+        let element = AudioOutputUnit::io_element(scope);
         let data = if enable { 1u32 } else { 0u32 };
-        self.set_property(AudioOutputUnit::ENABLE_IO, scope, element, &data)
+        self.set(props::EnableIO, scope, element, &data)
+    }
+
+    /// Call `cb` whenever the device has captured input. It is passed the
+    /// unit, the action flags, time stamp, bus and frame count, and fetches
+    /// the samples by rendering that bus of the unit into a buffer list of
+    /// its own. The callback stays installed until the returned guard is
+    /// dropped.
+    pub fn set_input_callback<CB>(&self, cb: CB) -> Result<InputCallbackGuard>
+    where
+        CB: FnMut(
+            &AudioOutputUnitRef,
+            &AudioUnitRenderActionFlags,
+            &AudioTimeStampRef,
+            u32,
+            u32,
+        ) -> result::Result<(), ffi::OSStatus>
+            + Send
+            + 'static,
+    {
+        let thunk = Box::into_raw(Box::new(InputCallbackThunk {
+            unit: self.as_ptr(),
+            cb: Box::new(cb),
+        }));
+        let callback = ffi::AURenderCallbackStruct {
+            inputProc: Some(audio_output_unit_input_cb),
+            inputProcRefCon: thunk as *mut _,
+        };
+        if let Err(e) = self.set_input_callback_struct(&callback) {
+            unsafe { drop(Box::from_raw(thunk)) };
+            return Err(e);
+        }
+        Ok(InputCallbackGuard { unit: self, thunk })
     }
 
-    pub fn set_start_time(&mut self, start_time: &AudioTimeStampRef) -> Result<()> {
+    /// Remove the input callback now rather than when its guard is dropped,
+    /// to see whether that succeeded.
+    pub fn remove_input_callback(&self, guard: InputCallbackGuard) -> Result<()> {
+        debug_assert_eq!(guard.unit.as_ptr(), self.as_ptr());
+        let result = guard.unregister();
+        mem::forget(guard);
+        result
+    }
+
+    fn set_input_callback_struct(
+        &self,
+        callback: &ffi::AURenderCallbackStruct,
+    ) -> Result<()> {
+        unsafe {
+            call::cvt_r(backend::get(self.as_ptr()).set_property(
+                self.as_ptr(),
+                AudioOutputUnit::SET_INPUT_CALLBACK,
+                ffi::kAudioUnitScope_Global,
+                0,
+                callback as *const _ as *const _,
+                mem::size_of::<ffi::AURenderCallbackStruct>() as u32,
+            ))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_start_time(&self, start_time: &AudioTimeStampRef) -> Result<()> {
         let timestamp = unsafe { *start_time.as_ptr() };
         let data = ffi::AudioOutputUnitStartAtTimeParams {
             mTimestamp: timestamp,
             mFlags: 0,
         };
-        self.set_property(AudioOutputUnit::START_TIME, Global, 0, &data)
+        self.set(props::StartTime, Global, 0, &data)
     }
 
-    pub fn set_start_timestamps_at_zero(&mut self, enable: bool) -> Result<()> {
+    pub fn set_start_timestamps_at_zero(&self, enable: bool) -> Result<()> {
         let data: u32 = if enable { 1 } else { 0 };
-        self.set_property(AudioOutputUnit::START_TIMESTAMPS_AT_ZERO, Global, 0, &data)
+        self.set(props::StartTimestampsAtZero, Global, 0, &data)
     }
 
     pub fn volume(&self) -> Result<f32> {
         self.get_parameter(AudioOutputUnit::VOLUME, Global, 0)
     }
 
-    pub fn set_volume(&mut self, volume: f32) -> Result<()> {
+    pub fn set_volume(&self, volume: f32) -> Result<()> {
         self.set_parameter(AudioOutputUnit::VOLUME, Global, 0, volume)
     }
 }
@@ -176,3 +308,152 @@ impl ops::DerefMut for AudioOutputUnitRef {
         unsafe { &mut *(self as *mut _ as *mut _) }
     }
 }
+
+extern fn audio_output_unit_input_cb(
+    ref_con: *mut c_void,
+    action: *mut ffi::AudioUnitRenderActionFlags,
+    time_stamp: *const ffi::AudioTimeStamp,
+    bus_number: u32,
+    number_frames: u32,
+    _data: *mut ffi::AudioBufferList,
+) -> ffi::OSStatus {
+    let status = panic::wrap(|| unsafe {
+        let payload = &mut *(ref_con as *mut InputCallbackThunk);
+        let unit = AudioOutputUnitRef::from_ptr(payload.unit);
+        let action = AudioUnitRenderActionFlags::from_bits_truncate(*action);
+        let time_stamp = AudioTimeStampRef::from_ptr(time_stamp as _);
+        let result = (payload.cb)(unit, &action, time_stamp, bus_number, number_frames);
+        result.err().unwrap_or(0)
+    });
+    status.unwrap_or_else(panic::status)
+}
+
+#[cfg(all(test, any(feature = "sim", not(target_vendor = "apple"))))]
+mod tests {
+    use super::*;
+    use {AudioBufferListRef, AudioUnitSubType, StreamFormat};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const FRAMES: u32 = 512;
+
+    /// A mono buffer list and the samples it points at, allocated before
+    /// rendering starts.
+    struct Capture {
+        samples: Vec<f32>,
+        list: ffi::AudioBufferList,
+    }
+
+    // The list only points into `samples`, which moves with it.
+    unsafe impl Send for Capture {}
+
+    impl Capture {
+        fn new() -> Capture {
+            let mut samples = vec![1.0f32; FRAMES as usize];
+            let list = ffi::AudioBufferList {
+                mNumberBuffers: 1,
+                mBuffers: [ffi::AudioBuffer {
+                    mNumberChannels: 1,
+                    mDataByteSize: FRAMES * 4,
+                    mData: samples.as_mut_ptr() as *mut _,
+                }],
+            };
+            Capture { samples, list }
+        }
+    }
+
+    fn hal_input() -> AudioOutputUnit {
+        let query = AudioComponentQuery::new().sub_kind(AudioUnitSubType::HALOutput);
+        let unit = AudioOutputUnit::find_instance(query).unwrap().unwrap();
+        unit.set_enable_io(Input, true).unwrap();
+        unit.set_enable_io(AudioUnitScope::Output, false).unwrap();
+        let mono = StreamFormat::float32(44100.0, 1).unwrap();
+        let element = AudioOutputUnit::INPUT_ELEMENT;
+        unit.set_stream_format(AudioUnitScope::Output, element, &mono).unwrap();
+        unit.initialize().unwrap();
+        unit
+    }
+
+    #[test]
+    fn input_callbacks_render_into_their_own_buffers() {
+        let unit = hal_input();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = seen.clone();
+        let mut capture = Capture::new();
+        let guard = unit.set_input_callback(move |unit, flags, time_stamp, bus, frames| {
+            assert!(frames <= FRAMES);
+            capture.list.mBuffers[0].mDataByteSize = frames * 4;
+            let mut flags = *flags;
+            let result = unsafe {
+                let data = AudioBufferListRef::from_ptr_mut(&mut capture.list);
+                unit.render(&mut flags, time_stamp, bus, frames, data)
+            };
+            let rendered = capture.samples[..frames as usize].iter().all(|&s| s == 0.0);
+            record.lock().unwrap().push((bus, frames, result.is_ok() && rendered));
+            Ok(())
+        }).unwrap();
+
+        unit.start().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while seen.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        unit.stop().unwrap();
+        unit.remove_input_callback(guard).unwrap();
+
+        // The closure, and its handle on `seen`, are gone.
+        assert_eq!(Arc::strong_count(&seen), 1);
+        let seen = seen.lock().unwrap();
+        assert!(!seen.is_empty());
+        for &(bus, frames, rendered) in seen.iter() {
+            assert_eq!(bus, AudioOutputUnit::INPUT_ELEMENT);
+            assert!(frames > 0 && rendered);
+        }
+    }
+
+    #[test]
+    fn dropping_the_guard_frees_the_callback() {
+        let unit = hal_input();
+        let seen = Arc::new(Mutex::new(0));
+        let count = seen.clone();
+        {
+            let _guard = unit.set_input_callback(move |_, _, _, _, _| {
+                *count.lock().unwrap() += 1;
+                Ok(())
+            }).unwrap();
+            // Setters still work while the guard borrows the unit.
+            unit.set_start_timestamps_at_zero(true).unwrap();
+            assert_eq!(Arc::strong_count(&seen), 2);
+        }
+        assert_eq!(Arc::strong_count(&seen), 1);
+    }
+
+    #[test]
+    fn the_guard_can_be_dropped_while_running() {
+        let unit = hal_input();
+        unit.start().unwrap();
+        for _ in 0..10 {
+            let seen = Arc::new(Mutex::new(0));
+            let count = seen.clone();
+            let guard = unit.set_input_callback(move |_, _, _, _, _| {
+                // Linger, so the guard is likely dropped mid-call.
+                thread::sleep(Duration::from_millis(2));
+                *count.lock().unwrap() += 1;
+                Ok(())
+            }).unwrap();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while *seen.lock().unwrap() == 0 && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            drop(guard);
+            // Nothing runs the closure, or holds `seen`, once it's gone.
+            assert_eq!(Arc::strong_count(&seen), 1);
+            let calls = *seen.lock().unwrap();
+            assert!(calls > 0);
+            thread::sleep(Duration::from_millis(30));
+            assert_eq!(*seen.lock().unwrap(), calls);
+        }
+        unit.stop().unwrap();
+    }
+}
@@ -1,91 +1,155 @@
-use {AudioBufferListRef, AudioTimeStampRef, Result};
-use audio_toolbox_sys as ffi;
+use {AudioBufferListRef, AudioTimeStampRef, Error, FourCC, Result, StreamFormat};
+use backend::{self, AudioUnitBackend};
+use ffi;
 use call;
 use panic;
-use std::mem;
+use props::{self, Property, PropertyValue, Queryable, Readable, Writable};
+use std::{cmp, mem, ptr, result};
+use std::time::Duration;
 use std::os::raw::c_void;
-use util::component_instance_dispose;
+use std::sync::Arc;
+use util::{self, component_instance_dispose};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum AudioUnitType {
-    Output,
-    MusicDevice,
-    MusicEffect,
-    FormatConverter,
-    Effect,
-    Mixer,
-    Panner,
-    Generator,
-    OfflineEffect,
-    MIDIProcessor,
+/// Define an enum of the four character codes AudioToolbox names, with an
+/// `Other` variant for the rest, and its conversions to and from the raw
+/// code. Aliases share an earlier variant's code, so they are only encoded;
+/// the code decodes as the earlier variant.
+macro_rules! four_cc_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[cfg($cfg:meta)])* $variant:ident = $code:ident,)*
+        }
+        $(aliases { $($alias:ident = $alias_code:ident,)* })*
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[cfg($cfg)])* $variant,)*
+            $($($alias,)*)*
+            /// A code without a variant of its own, such as a third-party
+            /// component's.
+            Other(FourCC),
+        }
+
+        impl ::std::convert::From<FourCC> for $name {
+            fn from(code: FourCC) -> Self {
+                match code.0 {
+                    $($(#[cfg($cfg)])* ffi::$code => $name::$variant,)*
+                    _ => $name::Other(code),
+                }
+            }
+        }
+
+        impl ::std::convert::From<$name> for FourCC {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($(#[cfg($cfg)])* $name::$variant => FourCC(ffi::$code),)*
+                    $($($name::$alias => FourCC(ffi::$alias_code),)*)*
+                    $name::Other(code) => code,
+                }
+            }
+        }
+
+        impl ::std::convert::From<ffi::OSType> for $name {
+            fn from(code: ffi::OSType) -> Self {
+                FourCC(code).into()
+            }
+        }
+
+        impl ::std::convert::From<$name> for ffi::OSType {
+            fn from(value: $name) -> Self {
+                FourCC::from(value).0
+            }
+        }
+    };
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum AudioUnitManufacturer {
-    Apple,
+four_cc_enum! {
+    pub enum AudioUnitType {
+        Output = kAudioUnitType_Output,
+        MusicDevice = kAudioUnitType_MusicDevice,
+        MusicEffect = kAudioUnitType_MusicEffect,
+        FormatConverter = kAudioUnitType_FormatConverter,
+        Effect = kAudioUnitType_Effect,
+        Mixer = kAudioUnitType_Mixer,
+        Panner = kAudioUnitType_Panner,
+        Generator = kAudioUnitType_Generator,
+        OfflineEffect = kAudioUnitType_OfflineEffect,
+        MIDIProcessor = kAudioUnitType_MIDIProcessor,
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum AudioUnitSubType {
-    GenericOutput,
-    VoiceProcessingIO,
-    //
-    HALOutput,
-    DefaultOutput,
-    SystemOutput,
-    //
-    DLSSynth,
-    Sampler,
-    MIDISynth,
-    //
-    AUConverter,
-    Varispeed,
-    DeferredRenderer,
-    Splitter,
-    MultiSplitter,
-    Merger,
-    NewTimePitch,
-    AUiPodTimeOther,
-    RoundTripAAC,
-    //
-    TimePitch,
-    //
-    PeakLimiter,
-    DynamicsProcessor,
-    LowPassFilter,
-    HighPassFilter,
-    BandPassFilter,
-    HighShelfFilter,
-    LowShelfFilter,
-    ParametricEQ,
-    Distortion,
-    Delay,
-    SampleDelay,
-    NBandEQ,
-    //
-    GraphicEQ,
-    MultiBandCompressor,
-    MatrixReverb,
-    Pitch,
-    AUFilter,
-    NetSend,
-    RogerBeep,
-    //
-    MultiChannelMixer,
-    MatrixMixer,
-    SpatialMixer,
-    //
-    StereoMixer,
-    #[cfg(feature = "deprecate")] _3DMixer,
-    //
-    SphericalHeadPanner,
-    VectorPanner,
-    SoundFieldPanner,
-    HRTFPanner,
-    //
-    NetReceive,
-    ScheduledSoundPlayer,
-    AudioFilePlayer,
+four_cc_enum! {
+    pub enum AudioUnitManufacturer {
+        Apple = kAudioUnitManufacturer_Apple,
+    }
+}
+
+four_cc_enum! {
+    pub enum AudioUnitSubType {
+        GenericOutput = kAudioUnitSubType_GenericOutput,
+        VoiceProcessingIO = kAudioUnitSubType_VoiceProcessingIO,
+        //
+        HALOutput = kAudioUnitSubType_HALOutput,
+        DefaultOutput = kAudioUnitSubType_DefaultOutput,
+        SystemOutput = kAudioUnitSubType_SystemOutput,
+        //
+        DLSSynth = kAudioUnitSubType_DLSSynth,
+        Sampler = kAudioUnitSubType_Sampler,
+        MIDISynth = kAudioUnitSubType_MIDISynth,
+        //
+        AUConverter = kAudioUnitSubType_AUConverter,
+        Varispeed = kAudioUnitSubType_Varispeed,
+        DeferredRenderer = kAudioUnitSubType_DeferredRenderer,
+        Splitter = kAudioUnitSubType_Splitter,
+        MultiSplitter = kAudioUnitSubType_MultiSplitter,
+        Merger = kAudioUnitSubType_Merger,
+        NewTimePitch = kAudioUnitSubType_NewTimePitch,
+        AUiPodTimeOther = kAudioUnitSubType_AUiPodTimeOther,
+        RoundTripAAC = kAudioUnitSubType_RoundTripAAC,
+        //
+        PeakLimiter = kAudioUnitSubType_PeakLimiter,
+        DynamicsProcessor = kAudioUnitSubType_DynamicsProcessor,
+        LowPassFilter = kAudioUnitSubType_LowPassFilter,
+        HighPassFilter = kAudioUnitSubType_HighPassFilter,
+        BandPassFilter = kAudioUnitSubType_BandPassFilter,
+        HighShelfFilter = kAudioUnitSubType_HighShelfFilter,
+        LowShelfFilter = kAudioUnitSubType_LowShelfFilter,
+        ParametricEQ = kAudioUnitSubType_ParametricEQ,
+        Distortion = kAudioUnitSubType_Distortion,
+        Delay = kAudioUnitSubType_Delay,
+        SampleDelay = kAudioUnitSubType_SampleDelay,
+        NBandEQ = kAudioUnitSubType_NBandEQ,
+        //
+        GraphicEQ = kAudioUnitSubType_GraphicEQ,
+        MultiBandCompressor = kAudioUnitSubType_MultiBandCompressor,
+        MatrixReverb = kAudioUnitSubType_MatrixReverb,
+        Pitch = kAudioUnitSubType_Pitch,
+        AUFilter = kAudioUnitSubType_AUFilter,
+        NetSend = kAudioUnitSubType_NetSend,
+        RogerBeep = kAudioUnitSubType_RogerBeep,
+        //
+        MultiChannelMixer = kAudioUnitSubType_MultiChannelMixer,
+        MatrixMixer = kAudioUnitSubType_MatrixMixer,
+        SpatialMixer = kAudioUnitSubType_SpatialMixer,
+        //
+        StereoMixer = kAudioUnitSubType_StereoMixer,
+        #[cfg(feature = "deprecated")]
+        _3DMixer = kAudioUnitSubType_3DMixer,
+        //
+        SphericalHeadPanner = kAudioUnitSubType_SphericalHeadPanner,
+        VectorPanner = kAudioUnitSubType_VectorPanner,
+        SoundFieldPanner = kAudioUnitSubType_SoundFieldPanner,
+        HRTFPanner = kAudioUnitSubType_HRTFPanner,
+        //
+        NetReceive = kAudioUnitSubType_NetReceive,
+        ScheduledSoundPlayer = kAudioUnitSubType_ScheduledSoundPlayer,
+        AudioFilePlayer = kAudioUnitSubType_AudioFilePlayer,
+    }
+    aliases {
+        TimePitch = kAudioUnitSubType_TimePitch,
+    }
 }
 
 bitflags! {
@@ -119,6 +183,7 @@ pub type AudioUnitProperty = ffi::AudioUnitPropertyID;
 pub type AudioUnitElement = u32;
 pub type AudioUnitParameter = ffi::AudioUnitParameterID;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AudioUnitParameterEventData {
     Ramp {
         start_buffer_offset: i32,
@@ -132,6 +197,7 @@ pub enum AudioUnitParameterEventData {
     },
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct AudioUnitParameterEvent {
     pub scope: AudioUnitScope,
     pub element: AudioUnitElement,
@@ -139,6 +205,49 @@ pub struct AudioUnitParameterEvent {
     pub event: AudioUnitParameterEventData,
 }
 
+impl<'a> ::std::convert::From<&'a AudioUnitParameterEvent>
+    for ffi::AudioUnitParameterEvent
+{
+    fn from(event: &'a AudioUnitParameterEvent) -> Self {
+        let mut raw = ffi::AudioUnitParameterEvent::default();
+        raw.scope = event.scope.into();
+        raw.element = event.element;
+        raw.parameter = event.parameter;
+        unsafe {
+            match event.event {
+                AudioUnitParameterEventData::Ramp {
+                    start_buffer_offset,
+                    duration_in_frames,
+                    start_value,
+                    end_value,
+                } => {
+                    raw.eventType = ffi::kParameterEvent_Ramped;
+                    *raw.ramp() = ffi::AudioUnitParameterEventRamp {
+                        startBufferOffset: start_buffer_offset,
+                        durationInFrames: duration_in_frames,
+                        startValue: start_value,
+                        endValue: end_value,
+                    };
+                },
+                AudioUnitParameterEventData::Immediate {
+                    buffer_offset,
+                    value,
+                } => {
+                    raw.eventType = ffi::kParameterEvent_Immediate;
+                    *raw.immediate() = ffi::AudioUnitParameterEventImmediate {
+                        bufferOffset: buffer_offset,
+                        value,
+                    };
+                },
+            }
+        }
+        raw
+    }
+}
+
+/// Events `schedule_parameters` converts on the stack at a time.
+const SCHEDULE_BATCH: usize = 16;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AudioUnitScope {
     Global,
@@ -190,21 +299,125 @@ pub type AudioUnitPropertyListenerCB =
         + Send
         + 'static;
 
-pub type AudioUnitRenderCB =
-    FnMut(&AudioUnitRenderActionFlags, &AudioTimeStampRef, u32, u32, &AudioBufferListRef)
-        + Send;
+/// A render callback or notification. Returning an error fails the render
+/// with that status.
+pub type AudioUnitRenderCB = FnMut(
+    &AudioUnitRenderActionFlags,
+    &AudioTimeStampRef,
+    u32,
+    u32,
+    &AudioBufferListRef,
+) -> result::Result<(), ffi::OSStatus>
+    + Send;
 
 struct CallbackThunk<T: ?Sized> {
     cb: Box<T>,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct CallbackHandle<T: ?Sized> {
-    thunk: *mut CallbackThunk<T>,
+/// A property listener added by `add_property_listener`. Dropping the guard
+/// removes the listener and frees the closure. The unit doesn't return from
+/// removing a listener while another thread is still calling it.
+#[must_use]
+pub struct PropertyListenerGuard<'a> {
+    unit: &'a AudioUnitRef,
+    id: AudioUnitProperty,
+    thunk: *mut CallbackThunk<AudioUnitPropertyListenerCB>,
+}
+
+impl<'a> PropertyListenerGuard<'a> {
+    pub fn property(&self) -> AudioUnitProperty {
+        self.id
+    }
+
+    /// Remove the listener, freeing the closure if the unit let go of it.
+    fn unregister(&self) -> Result<()> {
+        let cb: ffi::AudioUnitPropertyListenerProc = audio_unit_property_listener;
+        unsafe {
+            call::cvt_r(self.unit.backend().remove_property_listener_with_user_data(
+                self.unit.as_ptr(),
+                self.id,
+                cb,
+                self.thunk as *mut _,
+            ))?;
+            drop(Box::from_raw(self.thunk));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for PropertyListenerGuard<'a> {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+    }
+}
+
+/// A render notification added by `add_render_notify`. Dropping the guard
+/// removes the notification and frees the closure once any render already
+/// calling it has finished.
+#[must_use]
+pub struct RenderNotifyGuard<'a> {
+    unit: &'a AudioUnitRef,
+    thunk: *mut CallbackThunk<AudioUnitRenderCB>,
 }
 
-pub type AudioUnitRenderHandle = CallbackHandle<AudioUnitRenderCB>;
-pub type AudioUnitPropertyListenerHandle = CallbackHandle<AudioUnitPropertyListenerCB>;
+impl<'a> RenderNotifyGuard<'a> {
+    /// Remove the notification, freeing the closure if the unit let go of
+    /// it.
+    fn unregister(&self) -> Result<()> {
+        let cb: ffi::AURenderCallback = audio_unit_render_cb;
+        unsafe {
+            call::cvt_r(self.unit.backend().remove_render_notify(
+                self.unit.as_ptr(),
+                cb,
+                self.thunk as *mut _,
+            ))?;
+            drop(Box::from_raw(self.thunk));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for RenderNotifyGuard<'a> {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+    }
+}
+
+/// A render callback installed on an input bus by `set_render_callback`.
+/// Dropping the guard removes the callback from the bus and, once any render
+/// pulling from it has returned, frees the closure. If the unit refuses to
+/// let go of the callback it may still call it, so the closure is leaked
+/// instead; use `remove_render_callback` to find out whether that happened.
+#[must_use]
+pub struct RenderCallbackGuard<'a> {
+    unit: &'a AudioUnitRef,
+    bus: AudioUnitElement,
+    thunk: *mut CallbackThunk<AudioUnitRenderCB>,
+}
+
+impl<'a> RenderCallbackGuard<'a> {
+    pub fn bus(&self) -> AudioUnitElement {
+        self.bus
+    }
+
+    /// Clear the bus's callback, freeing the closure if the unit let go of
+    /// it.
+    fn unregister(&self) -> Result<()> {
+        let callback = ffi::AURenderCallbackStruct {
+            inputProc: None,
+            inputProcRefCon: ptr::null_mut(),
+        };
+        self.unit.set_render_callback_struct(self.bus, &callback)?;
+        unsafe { drop(Box::from_raw(self.thunk)) };
+        Ok(())
+    }
+}
+
+impl<'a> Drop for RenderCallbackGuard<'a> {
+    fn drop(&mut self) {
+        let _ = self.unregister();
+    }
+}
 
 ffi_type_heap! {
     type CType = ffi::ComponentInstanceRecord;
@@ -213,14 +426,29 @@ ffi_type_heap! {
     pub struct AudioUnitRef;
 }
 
+impl AudioUnit {
+    /// Create a unit whose calls are all handled by `backend` instead of
+    /// AudioToolbox.
+    pub fn with_backend<B>(backend: B) -> AudioUnit
+    where
+        B: AudioUnitBackend + 'static,
+    {
+        unsafe { AudioUnit::from_ptr(backend::install(Arc::new(backend))) }
+    }
+}
+
 impl AudioUnitRef {
+    fn backend(&self) -> backend::Backend {
+        backend::get(self.as_ptr())
+    }
+
     pub fn initialize(&self) -> Result<()> {
-        unsafe { call::cvt_r(ffi::AudioUnitInitialize(self.as_ptr()))? }
+        unsafe { call::cvt_r(self.backend().initialize(self.as_ptr()))? }
         Ok(())
     }
 
     pub fn uninitialize(&self) -> Result<()> {
-        unsafe { call::cvt_r(ffi::AudioUnitUninitialize(self.as_ptr()))? }
+        unsafe { call::cvt_r(self.backend().uninitialize(self.as_ptr()))? }
         Ok(())
     }
 
@@ -233,7 +461,7 @@ impl AudioUnitRef {
         let mut data_size: u32 = 0;
         let mut writable: ffi::Boolean = 0;
         unsafe {
-            call::cvt_r(ffi::AudioUnitGetPropertyInfo(
+            call::cvt_r(self.backend().get_property_info(
                 self.as_ptr(),
                 id,
                 scope.into(),
@@ -245,6 +473,54 @@ impl AudioUnitRef {
         Ok((data_size, writable != 0))
     }
 
+    /// Read a property through its typed descriptor in `props`.
+    pub fn get<P>(
+        &self,
+        _property: P,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<P::Value>
+    where
+        P: Readable,
+    {
+        check_scope::<P>(scope)?;
+        P::Value::get(self, P::ID, scope, element)
+    }
+
+    /// Write a property through its typed descriptor in `props`.
+    ///
+    /// Like every setter this takes `&self`: AudioToolbox serializes
+    /// property access itself, and callback guards borrow the unit while
+    /// they live.
+    pub fn set<P>(
+        &self,
+        _property: P,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        value: &P::Value,
+    ) -> Result<()>
+    where
+        P: Writable,
+    {
+        check_scope::<P>(scope)?;
+        value.set(self, P::ID, scope, element)
+    }
+
+    /// Complete an in/out property through its typed descriptor in `props`.
+    pub fn query<P>(
+        &self,
+        _property: P,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        value: &mut P::Value,
+    ) -> Result<()>
+    where
+        P: Queryable,
+    {
+        check_scope::<P>(scope)?;
+        value.query(self, P::ID, scope, element)
+    }
+
     pub fn get_property<T>(
         &self,
         id: AudioUnitProperty,
@@ -254,10 +530,12 @@ impl AudioUnitRef {
     where
         T: Sized,
     {
-        let mut data = unsafe { mem::uninitialized() };
-        let mut data_size = mem::size_of::<T>() as u32;
+        let (size, _) = self.get_property_info(id, scope, element)?;
+        check_size(id, mem::size_of::<T>(), size as usize)?;
+        let mut data: T = unsafe { mem::zeroed() };
+        let mut data_size = size;
         unsafe {
-            call::cvt_r(ffi::AudioUnitGetProperty(
+            call::cvt_r(self.backend().get_property(
                 self.as_ptr(),
                 id,
                 scope.into(),
@@ -266,19 +544,87 @@ impl AudioUnitRef {
                 &mut data_size,
             ))?;
         }
+        check_size(id, mem::size_of::<T>(), data_size as usize)?;
         Ok(data)
     }
 
+    /// Read a property whose value the caller partly fills in first, such
+    /// as `kAudioUnitProperty_ParameterStringFromValue`.
+    pub fn query_property<T>(
+        &self,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        data: &mut T,
+    ) -> Result<()>
+    where
+        T: Copy,
+    {
+        let mut data_size = mem::size_of::<T>() as u32;
+        unsafe {
+            call::cvt_r(self.backend().get_property(
+                self.as_ptr(),
+                id,
+                scope.into(),
+                element,
+                data as *mut _ as *mut _,
+                &mut data_size,
+            ))?;
+        }
+        check_size(id, mem::size_of::<T>(), data_size as usize)
+    }
+
+    /// Complete an array the caller sized and partly filled in, such as
+    /// `kAudioUnitProperty_FrequencyResponse`, truncating it to the items
+    /// the unit wrote.
+    pub fn query_property_array<T>(
+        &self,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        data: &mut Vec<T>,
+    ) -> Result<()>
+    where
+        T: Copy,
+    {
+        let item_size = mem::size_of::<T>();
+        let size = mem::size_of_val(&data[..]);
+        if size == 0 {
+            return Ok(());
+        }
+        let mut data_size = size as u32;
+        unsafe {
+            call::cvt_r(self.backend().get_property(
+                self.as_ptr(),
+                id,
+                scope.into(),
+                element,
+                data.as_mut_ptr() as *mut _,
+                &mut data_size,
+            ))?;
+        }
+        let data_size = cmp::min(data_size as usize, size);
+        check_array_size(id, item_size, data_size)?;
+        data.truncate(data_size / item_size);
+        Ok(())
+    }
+
     pub fn get_property_array<T>(
         &self,
         id: AudioUnitProperty,
         scope: AudioUnitScope,
         element: AudioUnitElement,
     ) -> Result<Vec<T>> {
-        let (mut data_size, _) = try!(self.get_property_info(id, scope, element));
-        let mut data = Vec::<T>::with_capacity(data_size as _);
+        let item_size = mem::size_of::<T>();
+        let (size, _) = self.get_property_info(id, scope, element)?;
+        check_array_size(id, item_size, size as usize)?;
+        let mut data = Vec::<T>::with_capacity(size as usize / item_size);
+        if size == 0 {
+            return Ok(data);
+        }
+        let mut data_size = size;
         unsafe {
-            call::cvt_r(ffi::AudioUnitGetProperty(
+            call::cvt_r(self.backend().get_property(
                 self.as_ptr(),
                 id,
                 scope.into(),
@@ -286,24 +632,72 @@ impl AudioUnitRef {
                 data.as_mut_ptr() as *mut _,
                 &mut data_size,
             ))?;
+            check_array_size(id, item_size, data_size.min(size) as usize)?;
+            data.set_len(data_size.min(size) as usize / item_size);
         }
         Ok(data)
     }
 
+    /// Get a property whose C type `T` ends in a variable-length array of
+    /// `E`, declared in `T` as a one element array (`AudioChannelLayout`,
+    /// `AudioBufferList`, ...). Returns the fixed part of `T`, whose array
+    /// holds the first item if there is one, and every item of the array.
+    pub fn get_property_variable<T, E>(
+        &self,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<(T, Vec<E>)>
+    where
+        T: Copy,
+        E: Copy,
+    {
+        let offset = util::trailing_offset::<T, E>();
+        let (size, _) = self.get_property_info(id, scope, element)?;
+        check_trailing_size(id, offset, mem::size_of::<E>(), size as usize)?;
+        let buffer_size = cmp::max(size as usize, mem::size_of::<T>());
+        let mut data = util::property_buffer(buffer_size);
+        let mut data_size = size;
+        unsafe {
+            call::cvt_r(self.backend().get_property(
+                self.as_ptr(),
+                id,
+                scope.into(),
+                element,
+                data.as_mut_ptr() as *mut _,
+                &mut data_size,
+            ))?;
+        }
+        let data_size = data_size.min(size) as usize;
+        check_trailing_size(id, offset, mem::size_of::<E>(), data_size)?;
+        let count = (data_size - offset) / mem::size_of::<E>();
+        unsafe {
+            let base = data.as_ptr() as *const u8;
+            let header = ptr::read_unaligned(base as *const T);
+            let items = (0..count)
+                .map(|i| {
+                    let item = base.offset((offset + i * mem::size_of::<E>()) as isize);
+                    ptr::read_unaligned(item as *const E)
+                })
+                .collect();
+            Ok((header, items))
+        }
+    }
+
     pub fn set_property<T>(
-        &mut self,
+        &self,
         id: AudioUnitProperty,
         scope: AudioUnitScope,
         element: AudioUnitElement,
         data: &T,
     ) -> Result<()> {
         unsafe {
-            call::cvt_r(ffi::AudioUnitSetProperty(
+            call::cvt_r(self.backend().set_property(
                 self.as_ptr(),
                 id,
                 scope.into(),
                 element,
-                &data as *const _ as *const _,
+                data as *const T as *const _,
                 mem::size_of::<T>() as u32,
             ))?;
         }
@@ -311,30 +705,70 @@ impl AudioUnitRef {
     }
 
     pub fn set_property_array<T>(
-        &mut self,
+        &self,
         id: AudioUnitProperty,
         scope: AudioUnitScope,
         element: AudioUnitElement,
         data: &[T],
     ) -> Result<()> {
         unsafe {
-            call::cvt_r(ffi::AudioUnitSetProperty(
+            call::cvt_r(self.backend().set_property(
                 self.as_ptr(),
                 id,
                 scope.into(),
                 element,
                 data.as_ptr() as *const _,
-                data.len() as u32,
+                mem::size_of_val(data) as u32,
             ))?;
         }
         Ok(())
     }
 
+    /// Set a property whose C type `T` ends in a variable-length array of
+    /// `E`. The array in `header` is ignored; `items` are written in its
+    /// place.
+    pub fn set_property_variable<T, E>(
+        &self,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        header: &T,
+        items: &[E],
+    ) -> Result<()>
+    where
+        T: Copy,
+        E: Copy,
+    {
+        let offset = util::trailing_offset::<T, E>();
+        let size = offset + mem::size_of_val(items);
+        let mut data = util::property_buffer(cmp::max(size, mem::size_of::<T>()));
+        unsafe {
+            let base = data.as_mut_ptr() as *mut u8;
+            ptr::copy_nonoverlapping(header as *const T as *const u8, base, offset);
+            ptr::copy_nonoverlapping(
+                items.as_ptr() as *const u8,
+                base.offset(offset as isize),
+                mem::size_of_val(items),
+            );
+            call::cvt_r(self.backend().set_property(
+                self.as_ptr(),
+                id,
+                scope.into(),
+                element,
+                data.as_ptr() as *const _,
+                size as u32,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Call `cb` whenever property `id` changes, until the returned guard is
+    /// dropped.
     pub fn add_property_listener<CB>(
         &self,
         id: AudioUnitProperty,
         cb: CB,
-    ) -> Result<AudioUnitPropertyListenerHandle>
+    ) -> Result<PropertyListenerGuard>
     where
         CB: FnMut(&AudioUnitRef, AudioUnitProperty, AudioUnitScope, AudioUnitElement)
             + Send
@@ -344,35 +778,39 @@ impl AudioUnitRef {
         let thunk = Box::into_raw(Box::new(CallbackThunk { cb }));
         let cb: ffi::AudioUnitPropertyListenerProc = audio_unit_property_listener;
         unsafe {
-            call::cvt_r(ffi::AudioUnitAddPropertyListener(
+            if let Err(e) = call::cvt_r(self.backend().add_property_listener(
                 self.as_ptr(),
                 id,
-                Some(cb),
+                cb,
                 thunk as *mut _,
-            ))?;
+            )) {
+                drop(Box::from_raw(thunk));
+                return Err(e);
+            }
         }
-        Ok(CallbackHandle { thunk })
+        Ok(PropertyListenerGuard {
+            unit: self,
+            id,
+            thunk,
+        })
     }
 
+    /// Remove a listener now rather than when its guard is dropped, to see
+    /// whether that succeeded. If it fails the listener may still be
+    /// called, so its closure is leaked.
     pub fn remove_property_listener_with_user_data(
         &self,
-        id: AudioUnitProperty,
-        handle: AudioUnitPropertyListenerHandle,
+        guard: PropertyListenerGuard,
     ) -> Result<()> {
-        let cb: ffi::AudioUnitPropertyListenerProc = audio_unit_property_listener;
-        unsafe {
-            call::cvt_r(ffi::AudioUnitRemovePropertyListenerWithUserData(
-                self.as_ptr(),
-                id,
-                Some(cb),
-                handle.thunk as *mut _,
-            ))?;
-            drop(Box::from_raw(handle.thunk));
-        }
-        Ok(())
+        debug_assert_eq!(guard.unit.as_ptr(), self.as_ptr());
+        let result = guard.unregister();
+        mem::forget(guard);
+        result
     }
 
-    pub fn add_render_notify<CB>(&self, cb: CB) -> Result<AudioUnitRenderHandle>
+    /// Call `cb` before and after every render, until the returned guard is
+    /// dropped.
+    pub fn add_render_notify<CB>(&self, cb: CB) -> Result<RenderNotifyGuard>
     where
         CB: FnMut(
             &AudioUnitRenderActionFlags,
@@ -380,7 +818,7 @@ impl AudioUnitRef {
             u32,
             u32,
             &AudioBufferListRef,
-        )
+        ) -> result::Result<(), ffi::OSStatus>
             + Send
             + 'static,
     {
@@ -388,26 +826,87 @@ impl AudioUnitRef {
         let thunk = Box::into_raw(Box::new(CallbackThunk { cb }));
         let cb: ffi::AURenderCallback = audio_unit_render_cb;
         unsafe {
-            call::cvt_r(ffi::AudioUnitAddRenderNotify(
+            if let Err(e) = call::cvt_r(self.backend().add_render_notify(
                 self.as_ptr(),
-                Some(cb),
+                cb,
                 thunk as *mut _,
-            ))?;
+            )) {
+                drop(Box::from_raw(thunk));
+                return Err(e);
+            }
         }
-        Ok(CallbackHandle { thunk })
+        Ok(RenderNotifyGuard { unit: self, thunk })
     }
 
-    pub unsafe fn remove_render_notify(
+    /// Remove a render notification now rather than when its guard is
+    /// dropped; see `remove_property_listener_with_user_data`.
+    pub fn remove_render_notify(&self, guard: RenderNotifyGuard) -> Result<()> {
+        debug_assert_eq!(guard.unit.as_ptr(), self.as_ptr());
+        let result = guard.unregister();
+        mem::forget(guard);
+        result
+    }
+
+    /// Have the unit pull its input for `bus` from `cb`, as when feeding
+    /// samples into an effect or an output unit. The callback stays
+    /// installed until the returned guard is dropped.
+    pub fn set_render_callback<CB>(
         &self,
-        handle: AudioUnitRenderHandle,
+        bus: AudioUnitElement,
+        cb: CB,
+    ) -> Result<RenderCallbackGuard>
+    where
+        CB: FnMut(
+            &AudioUnitRenderActionFlags,
+            &AudioTimeStampRef,
+            u32,
+            u32,
+            &AudioBufferListRef,
+        ) -> result::Result<(), ffi::OSStatus>
+            + Send
+            + 'static,
+    {
+        let cb = Box::new(cb) as Box<AudioUnitRenderCB>;
+        let thunk = Box::into_raw(Box::new(CallbackThunk { cb }));
+        let callback = ffi::AURenderCallbackStruct {
+            inputProc: Some(audio_unit_render_cb),
+            inputProcRefCon: thunk as *mut _,
+        };
+        if let Err(e) = self.set_render_callback_struct(bus, &callback) {
+            unsafe { drop(Box::from_raw(thunk)) };
+            return Err(e);
+        }
+        Ok(RenderCallbackGuard {
+            unit: self,
+            bus,
+            thunk,
+        })
+    }
+
+    /// Remove a render callback now rather than when its guard is dropped;
+    /// see `remove_property_listener_with_user_data`.
+    pub fn remove_render_callback(&self, guard: RenderCallbackGuard) -> Result<()> {
+        debug_assert_eq!(guard.unit.as_ptr(), self.as_ptr());
+        let result = guard.unregister();
+        mem::forget(guard);
+        result
+    }
+
+    fn set_render_callback_struct(
+        &self,
+        bus: AudioUnitElement,
+        callback: &ffi::AURenderCallbackStruct,
     ) -> Result<()> {
-        let cb: ffi::AURenderCallback = audio_unit_render_cb;
-        call::cvt_r(ffi::AudioUnitRemoveRenderNotify(
-            self.as_ptr(),
-            Some(cb),
-            handle.thunk as *mut _,
-        ))?;
-        drop(Box::from_raw(handle.thunk));
+        unsafe {
+            call::cvt_r(self.backend().set_property(
+                self.as_ptr(),
+                ffi::kAudioUnitProperty_SetRenderCallback,
+                ffi::kAudioUnitScope_Input,
+                bus,
+                callback as *const _ as *const _,
+                mem::size_of::<ffi::AURenderCallbackStruct>() as u32,
+            ))?;
+        }
         Ok(())
     }
 
@@ -419,7 +918,7 @@ impl AudioUnitRef {
     ) -> Result<f32> {
         let mut data: f32 = 0.0;
         unsafe {
-            call::cvt_r(ffi::AudioUnitGetParameter(
+            call::cvt_r(self.backend().get_parameter(
                 self.as_ptr(),
                 id,
                 scope.into(),
@@ -438,7 +937,7 @@ impl AudioUnitRef {
         data: f32,
     ) -> Result<()> {
         unsafe {
-            call::cvt_r(ffi::AudioUnitSetParameter(
+            call::cvt_r(self.backend().set_parameter(
                 self.as_ptr(),
                 id,
                 scope.into(),
@@ -450,15 +949,23 @@ impl AudioUnitRef {
         Ok(())
     }
 
-    pub fn schedule_parameters(&self, _events: &[AudioUnitParameterEvent]) -> Result<()> {
-        let events = Vec::new();
-        unsafe {
-            call::cvt_r(ffi::AudioUnitScheduleParameters(
-                self.as_ptr(),
-                events.as_ptr() as *const _,
-                events.len() as u32,
-            ))?;
-        };
+    /// Schedule parameter changes for the next render, at sample offsets
+    /// within it. Usually called from a pre-render notification, so the
+    /// events are converted in batches on the stack rather than allocated.
+    pub fn schedule_parameters(&self, events: &[AudioUnitParameterEvent]) -> Result<()> {
+        let mut raw = [ffi::AudioUnitParameterEvent::default(); SCHEDULE_BATCH];
+        for batch in events.chunks(SCHEDULE_BATCH) {
+            for (slot, event) in raw.iter_mut().zip(batch) {
+                *slot = event.into();
+            }
+            unsafe {
+                call::cvt_r(self.backend().schedule_parameters(
+                    self.as_ptr(),
+                    raw.as_ptr(),
+                    batch.len() as u32,
+                ))?;
+            }
+        }
         Ok(())
     }
 
@@ -472,7 +979,7 @@ impl AudioUnitRef {
     ) -> Result<()> {
         let mut new_action: ffi::AudioUnitRenderActionFlags = (*action).into();
         unsafe {
-            call::cvt_r(ffi::AudioUnitRender(
+            call::cvt_r(self.backend().render(
                 self.as_ptr(),
                 &mut new_action,
                 time_stamp.as_ptr(),
@@ -494,7 +1001,7 @@ impl AudioUnitRef {
     ) -> Result<()> {
         let mut new_action = (*action).into();
         unsafe {
-            call::cvt_r(ffi::AudioUnitProcess(
+            call::cvt_r(self.backend().process(
                 self.as_ptr(),
                 &mut new_action,
                 time_stamp.as_ptr(),
@@ -530,37 +1037,85 @@ impl AudioUnitRef {
 
     pub fn reset(&self, scope: AudioUnitScope, element: AudioUnitElement) -> Result<()> {
         unsafe {
-            call::cvt_r(ffi::AudioUnitReset(self.as_ptr(), scope.into(), element))?;
+            call::cvt_r(self.backend().reset(self.as_ptr(), scope.into(), element))?;
         }
         Ok(())
     }
 
+    pub fn stream_format(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<StreamFormat> {
+        self.get(props::StreamFormat, scope, element).map(StreamFormat::from)
+    }
+
+    /// Validate `format` and, if it is consistent, set it on the unit.
+    pub fn set_stream_format(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        format: &StreamFormat,
+    ) -> Result<()> {
+        format.validate()?;
+        self.set(props::StreamFormat, scope, element, &(*format).into())
+    }
+
+    /// How far the unit's output lags its input, which a host compensates for
+    /// by delaying the unit's siblings.
+    pub fn latency(&self) -> Result<Duration> {
+        let seconds = self.get(props::Latency, AudioUnitScope::Global, 0)?;
+        Ok(util::seconds_to_duration(seconds))
+    }
+
+    /// How long the unit keeps producing output, such as a reverb's decay,
+    /// after its input falls silent.
+    pub fn tail_time(&self) -> Result<Duration> {
+        let seconds = self.get(props::TailTime, AudioUnitScope::Global, 0)?;
+        Ok(util::seconds_to_duration(seconds))
+    }
+
+    /// The time between audio passing through the element and it reaching
+    /// or leaving the hardware.
+    pub fn presentation_latency(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<Duration> {
+        let seconds = self.get(props::PresentationLatency, scope, element)?;
+        Ok(util::seconds_to_duration(seconds))
+    }
+
+    /// Tell the unit the presentation latency of whatever is upstream
+    /// (input scope) or downstream (output scope) of the element.
+    pub fn set_presentation_latency(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        latency: Duration,
+    ) -> Result<()> {
+        let seconds = util::duration_to_seconds(latency);
+        self.set(props::PresentationLatency, scope, element, &seconds)
+    }
+
+    /// The number of frames `duration` spans at the unit's output sample
+    /// rate, rounded up so that a tail rendered for that long isn't cut
+    /// short.
+    pub fn duration_to_frames(&self, duration: Duration) -> Result<u64> {
+        let rate = self.get(props::SampleRate, AudioUnitScope::Output, 0)?;
+        Ok(util::duration_to_frames(duration, rate))
+    }
+
     // Properties
-    // kAudioUnitProperty_ClassInfo
     // kAudioUnitProperty_MakeConnection
     // kAudioUnitProperty_SampleRate
-    // kAudioUnitProperty_ParameterList
-    // kAudioUnitProperty_ParameterInfo
     // kAudioUnitProperty_CPULoad
-    // kAudioUnitProperty_StreamFormat
-    // kAudioUnitProperty_ElementCount
-    // kAudioUnitProperty_Latency
-    // kAudioUnitProperty_SupportedNumChannels
     // kAudioUnitProperty_MaximumFramesPerSlice
-    // kAudioUnitProperty_ParameterValueStrings
-    // kAudioUnitProperty_AudioChannelLayout
-    //    pub fn audio_channel_layout(&self) -> AudioChannelLayout {}
-    // kAudioUnitProperty_TailTime
     // kAudioUnitProperty_BypassEffect
     // kAudioUnitProperty_LastRenderError
-    // kAudioUnitProperty_SetRenderCallback
-    // kAudioUnitProperty_FactoryPresets
     // kAudioUnitProperty_RenderQuality
     // kAudioUnitProperty_HostCallbacks
     // kAudioUnitProperty_InPlaceProcessing
-    // kAudioUnitProperty_ElementName
-    // kAudioUnitProperty_SupportedChannelLayoutTags
-    // kAudioUnitProperty_PresentPreset
     // kAudioUnitProperty_DependentParameters
     // kAudioUnitProperty_InputSamplesInOutput
     // kAudioUnitProperty_ShouldAllocateBuffer
@@ -569,11 +1124,8 @@ impl AudioUnitRef {
     // kAudioUnitProperty_NickName
     // kAudioUnitProperty_OfflineRender
     // kAudioUnitProperty_ParameterIDName
-    // kAudioUnitProperty_ParameterStringFromValue
     // kAudioUnitProperty_ParameterClumpName
-    // kAudioUnitProperty_ParameterValueFromString
     // kAudioUnitProperty_ContextName
-    // kAudioUnitProperty_PresentationLatency
     // kAudioUnitProperty_ClassInfoFromDocument
     // kAudioUnitProperty_RequestViewController
     // kAudioUnitProperty_ParametersForOverview
@@ -588,34 +1140,58 @@ impl AudioUnitRef {
     // kAudioUnitProperty_MIDIOutputCallback
 }
 
-/*
-    pub fn set_input_callback<CB>(&mut self, cb: CB) -> Result<AudioUnitRenderHandle>
-    where
-        CB: FnMut(&AudioUnitRenderActionFlags,
-              &AudioTimeStamp,
-              u32,
-              u32,
-              &mut AudioBufferList)
-            + Send
-            + 'static,
-    {
-        let thunk = Box::into_raw(Box::new(
-            CallbackThunk { cb: Box::new(cb) as Box<AudioUnitRenderCB> },
-        ));
+fn check_scope<P: Property>(scope: AudioUnitScope) -> Result<()> {
+    if P::SCOPES.contains(&scope) {
+        Ok(())
+    } else {
+        Err(Error::Status(ffi::kAudioUnitErr_InvalidScope))
+    }
+}
 
-        let data = ffi::AURenderCallbackStruct {
-            inputProc: Some(audio_unit_render_cb),
-            inputProcRefCon: thunk as *mut _,
-        };
-        try!(self.set_property(
-            Self::SET_INPUT_CALLBACK,
-            AudioUnitScope::Global,
-            0,
-            &data,
-        ));
-        Ok(CallbackHandle { thunk: thunk })
+fn check_size(property: AudioUnitProperty, expected: usize, actual: usize) -> Result<()> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(Error::PropertySize {
+            property,
+            expected,
+            actual,
+        })
+    }
+}
+
+fn check_array_size(
+    property: AudioUnitProperty,
+    item_size: usize,
+    actual: usize,
+) -> Result<()> {
+    if item_size != 0 && actual % item_size == 0 {
+        Ok(())
+    } else {
+        Err(Error::PropertySize {
+            property,
+            expected: item_size,
+            actual,
+        })
+    }
+}
+
+fn check_trailing_size(
+    property: AudioUnitProperty,
+    offset: usize,
+    item_size: usize,
+    actual: usize,
+) -> Result<()> {
+    if actual >= offset {
+        check_array_size(property, item_size, actual - offset)
+    } else {
+        Err(Error::PropertySize {
+            property,
+            expected: offset,
+            actual,
+        })
     }
-*/
+}
 
 pub extern fn audio_unit_render_cb(
     ref_con: *mut c_void,
@@ -625,14 +1201,14 @@ pub extern fn audio_unit_render_cb(
     number_frames: u32,
     data: *mut ffi::AudioBufferList,
 ) -> ffi::OSStatus {
-    panic::wrap(|| unsafe {
+    let status = panic::wrap(|| unsafe {
         let payload = &mut *(ref_con as *mut CallbackThunk<AudioUnitRenderCB>);
         let callback = &mut payload.cb;
         let mut new_action =
             super::AudioUnitRenderActionFlags::from_bits_truncate(*action);
         let time_stamp = AudioTimeStampRef::from_ptr(time_stamp as _);
         let mut data = AudioBufferListRef::from_ptr(data);
-        callback(
+        let result = callback(
             &mut new_action,
             time_stamp,
             bus_number,
@@ -640,8 +1216,9 @@ pub extern fn audio_unit_render_cb(
             &mut data,
         );
         *action = new_action.bits();
+        result.err().unwrap_or(0)
     });
-    0
+    status.unwrap_or_else(panic::status)
 }
 
 pub extern fn audio_unit_property_listener(
@@ -659,3 +1236,632 @@ pub extern fn audio_unit_property_listener(
         callback(unit, id, scope, element);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::slice;
+    use std::sync::Mutex;
+
+    /// A property store standing in for AudioToolbox. `reported_size`
+    /// overrides the size `get_property_info` reports, to fake a unit that
+    /// disagrees with the caller about a property's type.
+    #[derive(Default)]
+    struct StubBackend {
+        properties: Mutex<HashMap<ffi::AudioUnitPropertyID, Vec<u8>>>,
+        reported_size: Option<u32>,
+        scheduled: Arc<Mutex<Vec<ffi::AudioUnitParameterEvent>>>,
+    }
+
+    impl AudioUnitBackend for StubBackend {
+        unsafe fn get_property_info(
+            &self,
+            _unit: ffi::AudioUnit,
+            id: ffi::AudioUnitPropertyID,
+            _scope: ffi::AudioUnitScope,
+            _element: ffi::AudioUnitElement,
+            data_size: *mut u32,
+            writable: *mut ffi::Boolean,
+        ) -> ffi::OSStatus {
+            match self.properties.lock().unwrap().get(&id) {
+                Some(data) => {
+                    *data_size = self.reported_size.unwrap_or(data.len() as u32);
+                    *writable = 1;
+                    0
+                },
+                None => ffi::kAudioUnitErr_InvalidProperty,
+            }
+        }
+
+        unsafe fn get_property(
+            &self,
+            _unit: ffi::AudioUnit,
+            id: ffi::AudioUnitPropertyID,
+            _scope: ffi::AudioUnitScope,
+            _element: ffi::AudioUnitElement,
+            data: *mut c_void,
+            data_size: *mut u32,
+        ) -> ffi::OSStatus {
+            match self.properties.lock().unwrap().get(&id) {
+                Some(value) => {
+                    let size = cmp::min(*data_size as usize, value.len());
+                    ptr::copy_nonoverlapping(value.as_ptr(), data as *mut u8, size);
+                    *data_size = size as u32;
+                    0
+                },
+                None => ffi::kAudioUnitErr_InvalidProperty,
+            }
+        }
+
+        unsafe fn set_property(
+            &self,
+            _unit: ffi::AudioUnit,
+            id: ffi::AudioUnitPropertyID,
+            _scope: ffi::AudioUnitScope,
+            _element: ffi::AudioUnitElement,
+            data: *const c_void,
+            data_size: u32,
+        ) -> ffi::OSStatus {
+            let value = slice::from_raw_parts(data as *const u8, data_size as usize);
+            self.properties.lock().unwrap().insert(id, value.to_vec());
+            0
+        }
+
+        unsafe fn schedule_parameters(
+            &self,
+            _unit: ffi::AudioUnit,
+            events: *const ffi::AudioUnitParameterEvent,
+            num_events: u32,
+        ) -> ffi::OSStatus {
+            let events = slice::from_raw_parts(events, num_events as usize);
+            self.scheduled.lock().unwrap().extend_from_slice(events);
+            0
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Trailing {
+        count: u32,
+        items: [u32; 1],
+    }
+
+    const PROP: AudioUnitProperty = 1000;
+
+    fn stub() -> AudioUnit {
+        AudioUnit::with_backend(StubBackend::default())
+    }
+
+    fn stored(unit: &AudioUnit) -> Vec<u8> {
+        let (size, _) = unit.get_property_info(PROP, AudioUnitScope::Global, 0).unwrap();
+        let mut data = vec![0u8; size as usize];
+        let mut data_size = size;
+        unsafe {
+            let status = unit.backend().get_property(
+                unit.as_ptr(),
+                PROP,
+                ffi::kAudioUnitScope_Global,
+                0,
+                data.as_mut_ptr() as *mut _,
+                &mut data_size,
+            );
+            assert_eq!(status, 0);
+        }
+        data
+    }
+
+    #[test]
+    fn set_property_sends_value() {
+        let unit = stub();
+        unit.set_property(PROP, AudioUnitScope::Global, 0, &0x1234_5678u32)
+            .unwrap();
+        assert_eq!(stored(&unit), bytes_of(&0x1234_5678u32));
+        let value: u32 = unit.get_property(PROP, AudioUnitScope::Global, 0).unwrap();
+        assert_eq!(value, 0x1234_5678);
+    }
+
+    #[test]
+    fn set_property_array_sends_byte_size() {
+        let unit = stub();
+        unit.set_property_array(PROP, AudioUnitScope::Global, 0, &[1i32, -1, 2])
+            .unwrap();
+        assert_eq!(stored(&unit).len(), 12);
+    }
+
+    #[test]
+    fn get_property_array_returns_items() {
+        let unit = stub();
+        unit.set_property_array(PROP, AudioUnitScope::Global, 0, &[1i32, -1, 2])
+            .unwrap();
+        let map: Vec<i32> = unit.get_property_array(PROP, AudioUnitScope::Global, 0)
+            .unwrap();
+        assert_eq!(map, vec![1, -1, 2]);
+
+        unit.set_property_array::<i32>(PROP, AudioUnitScope::Global, 0, &[])
+            .unwrap();
+        let map: Vec<i32> = unit.get_property_array(PROP, AudioUnitScope::Global, 0)
+            .unwrap();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn arrays_can_be_queried() {
+        let unit = stub();
+        let bin = |frequency, magnitude| ffi::AudioUnitFrequencyResponseBin {
+            mFrequency: frequency,
+            mMagnitude: magnitude,
+        };
+        let response = [bin(100.0, 0.5), bin(1000.0, 0.25)];
+        let id = ffi::kAudioUnitProperty_FrequencyResponse;
+        unit.set_property_array(id, AudioUnitScope::Global, 0, &response).unwrap();
+
+        // The unit only fills in two of the bins asked for.
+        let mut bins = vec![bin(0.0, 0.0); 3];
+        unit.query(props::FrequencyResponse, AudioUnitScope::Global, 0, &mut bins)
+            .unwrap();
+        let bins: Vec<_> = bins.iter().map(|b| (b.mFrequency, b.mMagnitude)).collect();
+        assert_eq!(bins, vec![(100.0, 0.5), (1000.0, 0.25)]);
+
+        let mut none = Vec::new();
+        unit.query(props::ParametersForOverview, AudioUnitScope::Global, 0, &mut none)
+            .unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn get_property_rejects_size_mismatch() {
+        let unit = stub();
+        unit.set_property(PROP, AudioUnitScope::Global, 0, &1.0f64).unwrap();
+        assert_eq!(
+            unit.get_property::<u32>(PROP, AudioUnitScope::Global, 0),
+            Err(Error::PropertySize {
+                property: PROP,
+                expected: 4,
+                actual: 8,
+            })
+        );
+        assert_eq!(
+            unit.get_property_array::<[u8; 3]>(PROP, AudioUnitScope::Global, 0),
+            Err(Error::PropertySize {
+                property: PROP,
+                expected: 3,
+                actual: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn get_property_checks_reported_size() {
+        let unit = AudioUnit::with_backend(StubBackend {
+            reported_size: Some(2),
+            ..Default::default()
+        });
+        unit.set_property(PROP, AudioUnitScope::Global, 0, &1u32).unwrap();
+        assert_eq!(
+            unit.get_property::<u32>(PROP, AudioUnitScope::Global, 0),
+            Err(Error::PropertySize {
+                property: PROP,
+                expected: 4,
+                actual: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn variable_length_round_trip() {
+        let unit = stub();
+        let header = Trailing {
+            count: 3,
+            items: [0],
+        };
+        let items = [7u32, 8, 9];
+        unit.set_property_variable(PROP, AudioUnitScope::Global, 0, &header, &items)
+            .unwrap();
+        assert_eq!(stored(&unit).len(), 4 + 3 * 4);
+
+        let (header, items): (Trailing, Vec<u32>) = unit.get_property_variable(
+            PROP,
+            AudioUnitScope::Global,
+            0,
+        ).unwrap();
+        assert_eq!(
+            header,
+            Trailing {
+                count: 3,
+                items: [7],
+            }
+        );
+        assert_eq!(items, vec![7, 8, 9]);
+
+        // An empty array leaves just the fixed part.
+        let header = Trailing {
+            count: 0,
+            items: [0],
+        };
+        let items: [u32; 0] = [];
+        unit.set_property_variable(PROP, AudioUnitScope::Global, 0, &header, &items)
+            .unwrap();
+        let (header, items): (Trailing, Vec<u32>) = unit.get_property_variable(
+            PROP,
+            AudioUnitScope::Global,
+            0,
+        ).unwrap();
+        assert_eq!(header.count, 0);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn variable_length_rejects_partial_item() {
+        let unit = stub();
+        unit.set_property_array(PROP, AudioUnitScope::Global, 0, &[0u8; 10])
+            .unwrap();
+        assert_eq!(
+            unit.get_property_variable::<Trailing, u32>(PROP, AudioUnitScope::Global, 0),
+            Err(Error::PropertySize {
+                property: PROP,
+                expected: 4,
+                actual: 6,
+            })
+        );
+    }
+
+    #[test]
+    fn ramp_event_converts() {
+        let event = AudioUnitParameterEvent {
+            scope: AudioUnitScope::Input,
+            element: 2,
+            parameter: 7,
+            event: AudioUnitParameterEventData::Ramp {
+                start_buffer_offset: -32,
+                duration_in_frames: 480,
+                start_value: 0.25,
+                end_value: 1.0,
+            },
+        };
+        let mut raw = ffi::AudioUnitParameterEvent::from(&event);
+        assert_eq!(raw.scope, ffi::kAudioUnitScope_Input);
+        assert_eq!(raw.element, 2);
+        assert_eq!(raw.parameter, 7);
+        assert_eq!(raw.eventType, ffi::kParameterEvent_Ramped);
+        let ramp = unsafe { *raw.ramp() };
+        assert_eq!(ramp.startBufferOffset, -32);
+        assert_eq!(ramp.durationInFrames, 480);
+        assert_eq!(ramp.startValue, 0.25);
+        assert_eq!(ramp.endValue, 1.0);
+    }
+
+    #[test]
+    fn immediate_event_converts() {
+        let event = AudioUnitParameterEvent {
+            scope: AudioUnitScope::Global,
+            element: 0,
+            parameter: 3,
+            event: AudioUnitParameterEventData::Immediate {
+                buffer_offset: 100,
+                value: -6.0,
+            },
+        };
+        let mut raw = ffi::AudioUnitParameterEvent::from(&event);
+        assert_eq!(raw.scope, ffi::kAudioUnitScope_Global);
+        assert_eq!(raw.parameter, 3);
+        assert_eq!(raw.eventType, ffi::kParameterEvent_Immediate);
+        let immediate = unsafe { *raw.immediate() };
+        assert_eq!(immediate.bufferOffset, 100);
+        assert_eq!(immediate.value, -6.0);
+    }
+
+    #[test]
+    fn schedule_parameters_sends_every_event() {
+        let scheduled = Arc::new(Mutex::new(Vec::new()));
+        let unit = AudioUnit::with_backend(StubBackend {
+            scheduled: scheduled.clone(),
+            ..Default::default()
+        });
+        let events: Vec<AudioUnitParameterEvent> = (0..SCHEDULE_BATCH as u32 + 3)
+            .map(|i| AudioUnitParameterEvent {
+                scope: AudioUnitScope::Global,
+                element: 0,
+                parameter: i,
+                event: AudioUnitParameterEventData::Immediate {
+                    buffer_offset: i,
+                    value: i as f32,
+                },
+            })
+            .collect();
+        unit.schedule_parameters(&events).unwrap();
+        let mut scheduled = scheduled.lock().unwrap();
+        assert_eq!(scheduled.len(), events.len());
+        for (i, raw) in scheduled.iter_mut().enumerate() {
+            assert_eq!(raw.parameter, i as u32);
+            assert_eq!(unsafe { (*raw.immediate()).value }, i as f32);
+        }
+    }
+
+    #[test]
+    fn known_codes_round_trip() {
+        let effect = ffi::kAudioUnitType_Effect;
+        assert_eq!(AudioUnitType::from(effect), AudioUnitType::Effect);
+        assert_eq!(ffi::OSType::from(AudioUnitType::Effect), effect);
+        assert_eq!(
+            AudioUnitSubType::from(FourCC::new(b"lmtr")),
+            AudioUnitSubType::PeakLimiter
+        );
+        assert_eq!(
+            FourCC::from(AudioUnitManufacturer::Apple),
+            FourCC(ffi::kAudioUnitManufacturer_Apple)
+        );
+    }
+
+    #[test]
+    fn unknown_codes_decode_as_other() {
+        let code = FourCC::new(b"Acme");
+        assert_eq!(AudioUnitManufacturer::from(code), AudioUnitManufacturer::Other(code));
+        assert_eq!(AudioUnitType::from(code.0), AudioUnitType::Other(code));
+        assert_eq!(FourCC::from(AudioUnitSubType::from(code)), code);
+    }
+
+    #[test]
+    fn alias_decodes_as_its_variant() {
+        let code = ffi::OSType::from(AudioUnitSubType::TimePitch);
+        assert_eq!(code, ffi::kAudioUnitSubType_Pitch);
+        assert_eq!(AudioUnitSubType::from(code), AudioUnitSubType::Pitch);
+    }
+
+    #[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+    mod sim {
+        use super::*;
+        use sim::new_unit;
+        use {callback_panic_message, callback_panicked, set_callback_panic_status,
+             take_callback_panic};
+        use {AudioComponentQuery, AudioOutputUnit};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+
+        /// Counts calls, and adds 100 when the closure holding it is freed.
+        struct Counter(Arc<AtomicUsize>);
+
+        impl Counter {
+            fn new() -> (Counter, Arc<AtomicUsize>) {
+                let count = Arc::new(AtomicUsize::new(0));
+                (Counter(count.clone()), count)
+            }
+
+            fn hit(&self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        impl Drop for Counter {
+            fn drop(&mut self) {
+                self.0.fetch_add(100, Ordering::SeqCst);
+            }
+        }
+
+        /// Render `frames` of bus 0 into one mono buffer.
+        fn render_mono(unit: &AudioUnitRef, frames: u32) -> Result<Vec<f32>> {
+            let mut samples = vec![0.0f32; frames as usize];
+            let mut list = ffi::AudioBufferList {
+                mNumberBuffers: 1,
+                mBuffers: [ffi::AudioBuffer {
+                    mNumberChannels: 1,
+                    mDataByteSize: frames * 4,
+                    mData: samples.as_mut_ptr() as *mut _,
+                }],
+            };
+            let mut flags = AudioUnitRenderActionFlags::empty();
+            unsafe {
+                let mut time_stamp: ffi::AudioTimeStamp = mem::zeroed();
+                unit.render(
+                    &mut flags,
+                    AudioTimeStampRef::from_ptr(&mut time_stamp),
+                    0,
+                    frames,
+                    AudioBufferListRef::from_ptr_mut(&mut list),
+                )?;
+            }
+            Ok(samples)
+        }
+
+        /// Wait up to five seconds for `count` to reach `n`.
+        fn wait_for(count: &AtomicUsize, n: usize) {
+            for _ in 0..5000 {
+                if count.load(Ordering::SeqCst) >= n {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            panic!("only {} calls", count.load(Ordering::SeqCst));
+        }
+
+        /// Assert that a closure counted by `count` was freed and is no
+        /// longer called.
+        fn assert_freed(count: &AtomicUsize) {
+            let calls = count.load(Ordering::SeqCst);
+            assert!(calls >= 100);
+            thread::sleep(Duration::from_millis(30));
+            assert_eq!(count.load(Ordering::SeqCst), calls);
+        }
+
+        fn started_output() -> AudioOutputUnit {
+            let query = AudioComponentQuery::new()
+                .sub_kind(AudioUnitSubType::GenericOutput);
+            let output = AudioOutputUnit::find_instance(query).unwrap().unwrap();
+            output.initialize().unwrap();
+            output.start().unwrap();
+            output
+        }
+
+        fn mono_filter() -> AudioUnit {
+            let filter = new_unit(ffi::kAudioUnitType_Effect,
+                                  ffi::kAudioUnitSubType_LowPassFilter);
+            let mono = StreamFormat::float32(44100.0, 1).unwrap();
+            filter.set_stream_format(AudioUnitScope::Input, 0, &mono).unwrap();
+            filter.set_stream_format(AudioUnitScope::Output, 0, &mono).unwrap();
+            filter.initialize().unwrap();
+            filter
+        }
+
+        #[test]
+        fn render_callbacks_run_until_dropped() {
+            let filter = mono_filter();
+            let (counter, calls) = Counter::new();
+            {
+                let guard = filter.set_render_callback(0, move |_, _, bus, frames, data| {
+                    assert_eq!((bus, frames), (0, 64));
+                    unsafe { assert!(!(*data.as_ptr()).mBuffers[0].mData.is_null()) };
+                    counter.hit();
+                    Ok(())
+                }).unwrap();
+                assert_eq!(guard.bus(), 0);
+                render_mono(&filter, 64).unwrap();
+                render_mono(&filter, 64).unwrap();
+                assert_eq!(calls.load(Ordering::SeqCst), 2);
+            }
+            // The closure was freed, and the filter has no input left.
+            assert_eq!(calls.load(Ordering::SeqCst), 102);
+            assert!(render_mono(&filter, 64).is_err());
+        }
+
+        #[test]
+        fn render_callbacks_can_be_removed_early() {
+            let filter = mono_filter();
+            let (counter, calls) = Counter::new();
+            let guard = filter.set_render_callback(0, move |_, _, _, _, _| {
+                counter.hit();
+                Err(ffi::kAudioUnitErr_NoConnection)
+            }).unwrap();
+            assert_eq!(render_mono(&filter, 64), Err(Error::Status(
+                ffi::kAudioUnitErr_NoConnection,
+            )));
+            filter.remove_render_callback(guard).unwrap();
+            assert_eq!(calls.load(Ordering::SeqCst), 101);
+            assert!(filter.set_render_callback(7, |_, _, _, _, _| Ok(())).is_err());
+        }
+
+        #[test]
+        fn panicking_callbacks_return_the_configured_status() {
+            let _lock = panic::tests::lock();
+            let filter = mono_filter();
+            take_callback_panic();
+            let _guard = filter.set_render_callback(0, |_, _, _, _, _| panic!("dsp bug"))
+                .unwrap();
+            let timeout = Err(Error::Status(ffi::kAudioUnitErr_RenderTimeout));
+            assert_eq!(render_mono(&filter, 64), timeout);
+            assert_eq!(callback_panic_message(), Some("dsp bug".to_owned()));
+
+            set_callback_panic_status(-1);
+            let result = render_mono(&filter, 64);
+            set_callback_panic_status(ffi::kAudioUnitErr_RenderTimeout);
+            assert_eq!(result, Err(Error::Status(-1)));
+            let payload = take_callback_panic().unwrap();
+            assert_eq!(payload.downcast_ref::<&str>(), Some(&"dsp bug"));
+            assert!(!callback_panicked());
+        }
+
+        #[test]
+        fn guards_leave_the_unit_writable() {
+            let mixer = new_unit(ffi::kAudioUnitType_Mixer,
+                                 ffi::kAudioUnitSubType_MultiChannelMixer);
+            let (listened, formats) = Counter::new();
+            let (notified, renders) = Counter::new();
+            {
+                let _listener = mixer.add_property_listener(
+                    ffi::kAudioUnitProperty_StreamFormat,
+                    move |_, _, _, _| listened.hit(),
+                ).unwrap();
+                let _notify = mixer.add_render_notify(move |_, _, _, _, _| {
+                    notified.hit();
+                    Ok(())
+                }).unwrap();
+
+                let mono = StreamFormat::float32(44100.0, 1).unwrap();
+                mixer.set_stream_format(AudioUnitScope::Output, 0, &mono).unwrap();
+                mixer.set(props::MaximumFramesPerSlice, AudioUnitScope::Global, 0, &256)
+                    .unwrap();
+                mixer.set_element_count(AudioUnitScope::Input, 2).unwrap();
+                mixer.initialize().unwrap();
+                render_mono(&mixer, 64).unwrap();
+                assert_eq!(formats.load(Ordering::SeqCst), 1);
+                // Once before and once after the render.
+                assert_eq!(renders.load(Ordering::SeqCst), 2);
+            }
+            assert_eq!(formats.load(Ordering::SeqCst), 101);
+            assert_eq!(renders.load(Ordering::SeqCst), 102);
+
+            mixer.uninitialize().unwrap();
+            let stereo = StreamFormat::float32(44100.0, 2).unwrap();
+            mixer.set_stream_format(AudioUnitScope::Output, 0, &stereo).unwrap();
+            assert_eq!(formats.load(Ordering::SeqCst), 101);
+        }
+
+        #[test]
+        fn timing_properties_are_durations() {
+            let limiter = new_unit(ffi::kAudioUnitType_Effect,
+                                   ffi::kAudioUnitSubType_PeakLimiter);
+            assert_eq!(limiter.latency(), Ok(Duration::from_millis(12)));
+            assert_eq!(limiter.tail_time(), Ok(Duration::new(0, 0)));
+            let delay = new_unit(ffi::kAudioUnitType_Effect,
+                                 ffi::kAudioUnitSubType_Delay);
+            assert_eq!(delay.latency(), Ok(Duration::new(0, 0)));
+            assert_eq!(delay.tail_time(), Ok(Duration::from_secs(2)));
+
+            let scope = AudioUnitScope::Output;
+            assert_eq!(delay.presentation_latency(scope, 0), Ok(Duration::new(0, 0)));
+            let five = Duration::from_millis(5);
+            delay.set_presentation_latency(scope, 0, five).unwrap();
+            assert_eq!(delay.presentation_latency(scope, 0), Ok(five));
+            assert!(delay.presentation_latency(scope, 3).is_err());
+
+            // Frames are counted at the output element's rate.
+            let tail = delay.tail_time().unwrap();
+            assert_eq!(delay.duration_to_frames(tail), Ok(88200));
+            let format = StreamFormat::float32(48000.0, 2).unwrap();
+            delay.set_stream_format(AudioUnitScope::Output, 0, &format).unwrap();
+            assert_eq!(delay.duration_to_frames(tail), Ok(96000));
+            assert_eq!(delay.duration_to_frames(Duration::from_millis(70)), Ok(3360));
+        }
+
+        #[test]
+        fn render_notify_guards_can_be_dropped_while_started() {
+            let output = started_output();
+            for _ in 0..10 {
+                let (counter, calls) = Counter::new();
+                let guard = output.add_render_notify(move |_, _, _, _, _| {
+                    // Linger, so the guard is likely dropped mid-call.
+                    thread::sleep(Duration::from_millis(2));
+                    counter.hit();
+                    Ok(())
+                }).unwrap();
+                wait_for(&calls, 1);
+                drop(guard);
+                assert_freed(&calls);
+            }
+            output.stop().unwrap();
+        }
+
+        #[test]
+        fn render_callbacks_can_be_removed_while_rendering() {
+            let output = started_output();
+            for _ in 0..10 {
+                let (counter, calls) = Counter::new();
+                let guard = output.set_render_callback(0, move |_, _, _, _, _| {
+                    thread::sleep(Duration::from_millis(2));
+                    counter.hit();
+                    Ok(())
+                }).unwrap();
+                wait_for(&calls, 1);
+                output.remove_render_callback(guard).unwrap();
+                assert_freed(&calls);
+            }
+            output.stop().unwrap();
+        }
+    }
+
+    fn bytes_of<T>(value: &T) -> Vec<u8> {
+        unsafe {
+            slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+                .to_vec()
+        }
+    }
+}
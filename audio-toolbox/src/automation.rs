@@ -0,0 +1,337 @@
+use {AudioUnitElement, AudioUnitParameter, AudioUnitParameterEvent,
+     AudioUnitParameterEventData, AudioUnitScope};
+use std::cmp::Ordering::{Greater, Less};
+
+/// How an automation lane moves from one breakpoint to the next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    /// Hold the breakpoint's value, then jump at the next breakpoint.
+    Step,
+    /// Ramp linearly to the next breakpoint's value.
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    /// Sample time at which the lane reaches `value`.
+    pub time: u64,
+    pub value: f32,
+    /// The shape of the segment that starts here.
+    pub curve: Curve,
+}
+
+impl Breakpoint {
+    pub fn new(time: u64, value: f32, curve: Curve) -> Breakpoint {
+        Breakpoint { time, value, curve }
+    }
+}
+
+/// A timeline of breakpoints for one parameter, turned into the
+/// immediate and ramp events for each render slice.
+///
+/// Before its first breakpoint the lane holds the first value, and after
+/// its last it holds the last value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutomationLane {
+    parameter: AudioUnitParameter,
+    scope: AudioUnitScope,
+    element: AudioUnitElement,
+    // Sorted by time, with no two at the same time.
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl AutomationLane {
+    pub fn new(
+        parameter: AudioUnitParameter,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> AutomationLane {
+        AutomationLane {
+            parameter,
+            scope,
+            element,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn parameter(&self) -> AudioUnitParameter {
+        self.parameter
+    }
+
+    pub fn scope(&self) -> AudioUnitScope {
+        self.scope
+    }
+
+    pub fn element(&self) -> AudioUnitElement {
+        self.element
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Add `breakpoint`, replacing any already at its time.
+    pub fn insert(&mut self, breakpoint: Breakpoint) {
+        match self.breakpoints.binary_search_by_key(&breakpoint.time, |b| b.time) {
+            Ok(i) => self.breakpoints[i] = breakpoint,
+            Err(i) => self.breakpoints.insert(i, breakpoint),
+        }
+    }
+
+    /// Remove the breakpoint at `time`, if there is one.
+    pub fn remove(&mut self, time: u64) -> Option<Breakpoint> {
+        match self.breakpoints.binary_search_by_key(&time, |b| b.time) {
+            Ok(i) => Some(self.breakpoints.remove(i)),
+            Err(_) => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// The lane's value at sample time `time`, or `None` if it has no
+    /// breakpoints.
+    pub fn value_at(&self, time: f64) -> Option<f32> {
+        let i = self.segment(time);
+        let first = self.breakpoints.first()?;
+        match i {
+            None => Some(first.value),
+            Some(i) => Some(self.segment_value(i, time)),
+        }
+    }
+
+    /// The events that make the parameter follow the lane over the slice of
+    /// `frames` frames starting at `sample_time`, as in the slice's
+    /// `AudioTimeStamp`. Ramps are clipped to the slice, so each slice's
+    /// events stand on their own.
+    pub fn events(&self, sample_time: f64, frames: u32) -> Vec<AudioUnitParameterEvent> {
+        let mut events = Vec::new();
+        self.events_into(sample_time, frames, &mut events);
+        events
+    }
+
+    /// `events`, replacing the contents of `events` so that its allocation
+    /// can be reused from one render slice to the next.
+    pub fn events_into(
+        &self,
+        sample_time: f64,
+        frames: u32,
+        events: &mut Vec<AudioUnitParameterEvent>,
+    ) {
+        events.clear();
+        if self.breakpoints.is_empty() || frames == 0 {
+            return;
+        }
+        let start = sample_time.round() as i64;
+        let end = start + frames as i64;
+
+        // Where the lane stands as the slice begins.
+        let first = match self.segment(start as f64) {
+            Some(i) => {
+                events.push(self.segment_event(i, start, end));
+                i + 1
+            },
+            None => {
+                let value = self.breakpoints[0].value;
+                events.push(self.event(immediate(0, value)));
+                0
+            },
+        };
+        // Then every breakpoint that falls inside it.
+        for i in first..self.breakpoints.len() {
+            if self.breakpoints[i].time as i64 >= end {
+                break;
+            }
+            events.push(self.segment_event(i, start, end));
+        }
+    }
+
+    /// The index of the last breakpoint at or before `time`.
+    fn segment(&self, time: f64) -> Option<usize> {
+        let after = self.breakpoints
+            .binary_search_by(|b| if b.time as f64 <= time { Less } else { Greater })
+            .unwrap_or_else(|i| i);
+        after.checked_sub(1)
+    }
+
+    /// The value at `time` in the segment starting at breakpoint `i`.
+    fn segment_value(&self, i: usize, time: f64) -> f32 {
+        let from = self.breakpoints[i];
+        match (from.curve, self.breakpoints.get(i + 1)) {
+            (Curve::Linear, Some(to)) => {
+                let position = (time - from.time as f64) / (to.time - from.time) as f64;
+                let position = position.max(0.0).min(1.0);
+                (from.value as f64 + (to.value - from.value) as f64 * position) as f32
+            },
+            _ => from.value,
+        }
+    }
+
+    /// The event for the part of segment `i` within the slice from `start`
+    /// to `end`. The segment must overlap the slice.
+    fn segment_event(&self, i: usize, start: i64, end: i64) -> AudioUnitParameterEvent {
+        let from = self.breakpoints[i];
+        let begin = (from.time as i64).max(start);
+        let offset = (begin - start) as u32;
+        let data = match (from.curve, self.breakpoints.get(i + 1)) {
+            (Curve::Linear, Some(to)) => {
+                let finish = (to.time as i64).min(end);
+                AudioUnitParameterEventData::Ramp {
+                    start_buffer_offset: offset as i32,
+                    duration_in_frames: (finish - begin) as u32,
+                    start_value: self.segment_value(i, begin as f64),
+                    end_value: self.segment_value(i, finish as f64),
+                }
+            },
+            _ => immediate(offset, from.value),
+        };
+        self.event(data)
+    }
+
+    fn event(&self, data: AudioUnitParameterEventData) -> AudioUnitParameterEvent {
+        AudioUnitParameterEvent {
+            scope: self.scope,
+            element: self.element,
+            parameter: self.parameter,
+            event: data,
+        }
+    }
+}
+
+fn immediate(offset: u32, value: f32) -> AudioUnitParameterEventData {
+    AudioUnitParameterEventData::Immediate {
+        buffer_offset: offset,
+        value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use AudioUnitParameterEventData::Ramp;
+
+    fn lane(points: &[(u64, f32, Curve)]) -> AutomationLane {
+        let mut lane = AutomationLane::new(5, AudioUnitScope::Global, 0);
+        for &(time, value, curve) in points {
+            lane.insert(Breakpoint::new(time, value, curve));
+        }
+        lane
+    }
+
+    fn data(
+        lane: &AutomationLane,
+        start: f64,
+        frames: u32,
+    ) -> Vec<AudioUnitParameterEventData> {
+        lane.events(start, frames).into_iter().map(|e| e.event).collect()
+    }
+
+    fn ramp(offset: i32, frames: u32, from: f32, to: f32) -> AudioUnitParameterEventData {
+        Ramp {
+            start_buffer_offset: offset,
+            duration_in_frames: frames,
+            start_value: from,
+            end_value: to,
+        }
+    }
+
+    #[test]
+    fn empty_lane_has_no_events() {
+        let lane = lane(&[]);
+        assert!(lane.events(0.0, 512).is_empty());
+        assert_eq!(lane.value_at(0.0), None);
+    }
+
+    #[test]
+    fn events_carry_the_lane_address() {
+        let mut lane = AutomationLane::new(9, AudioUnitScope::Input, 2);
+        lane.insert(Breakpoint::new(0, 1.0, Curve::Step));
+        let events = lane.events(0.0, 64);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].parameter, 9);
+        assert_eq!(events[0].scope, AudioUnitScope::Input);
+        assert_eq!(events[0].element, 2);
+    }
+
+    #[test]
+    fn holds_first_value_until_first_breakpoint() {
+        let lane = lane(&[(1000, 0.5, Curve::Step), (1020, 0.75, Curve::Step)]);
+        assert_eq!(data(&lane, 0.0, 512), vec![immediate(0, 0.5)]);
+        assert_eq!(
+            data(&lane, 512.0, 512),
+            vec![immediate(0, 0.5), immediate(488, 0.5), immediate(508, 0.75)]
+        );
+        assert_eq!(data(&lane, 2048.0, 512), vec![immediate(0, 0.75)]);
+    }
+
+    #[test]
+    fn ramp_is_split_across_slices() {
+        let lane = lane(&[(256, 0.0, Curve::Linear), (768, 1.0, Curve::Step)]);
+        assert_eq!(
+            data(&lane, 0.0, 512),
+            vec![immediate(0, 0.0), ramp(256, 256, 0.0, 0.5)]
+        );
+        assert_eq!(
+            data(&lane, 512.0, 512),
+            vec![ramp(0, 256, 0.5, 1.0), immediate(256, 1.0)]
+        );
+        assert_eq!(data(&lane, 1024.0, 512), vec![immediate(0, 1.0)]);
+    }
+
+    #[test]
+    fn ramp_spanning_whole_slice() {
+        let lane = lane(&[(0, 0.0, Curve::Linear), (4000, 4.0, Curve::Linear)]);
+        assert_eq!(data(&lane, 1000.0, 1000), vec![ramp(0, 1000, 1.0, 2.0)]);
+    }
+
+    #[test]
+    fn breakpoint_at_slice_start() {
+        let lane = lane(&[(0, 0.0, Curve::Step), (512, 2.0, Curve::Linear),
+                          (1536, 4.0, Curve::Step)]);
+        assert_eq!(data(&lane, 512.0, 512), vec![ramp(0, 512, 2.0, 3.0)]);
+    }
+
+    #[test]
+    fn fractional_and_negative_sample_times() {
+        let lane = lane(&[(0, 1.0, Curve::Step), (10, 2.0, Curve::Step)]);
+        assert_eq!(
+            data(&lane, -16.0, 32),
+            vec![immediate(0, 1.0), immediate(16, 1.0), immediate(26, 2.0)]
+        );
+        assert_eq!(data(&lane, 9.6, 4), vec![immediate(0, 2.0)]);
+    }
+
+    #[test]
+    fn value_at_interpolates() {
+        let lane = lane(&[(100, 1.0, Curve::Linear), (200, 3.0, Curve::Step),
+                          (300, 0.0, Curve::Step)]);
+        assert_eq!(lane.value_at(0.0), Some(1.0));
+        assert_eq!(lane.value_at(150.0), Some(2.0));
+        assert_eq!(lane.value_at(250.0), Some(3.0));
+        assert_eq!(lane.value_at(1e9), Some(0.0));
+    }
+
+    #[test]
+    fn insert_keeps_order_and_replaces() {
+        let mut lane = lane(&[(300, 3.0, Curve::Step), (100, 1.0, Curve::Step)]);
+        lane.insert(Breakpoint::new(200, 2.0, Curve::Linear));
+        lane.insert(Breakpoint::new(100, 1.5, Curve::Step));
+        let times: Vec<u64> = lane.breakpoints().iter().map(|b| b.time).collect();
+        assert_eq!(times, vec![100, 200, 300]);
+        assert_eq!(lane.breakpoints()[0].value, 1.5);
+        assert_eq!(lane.remove(200).map(|b| b.value), Some(2.0));
+        assert_eq!(lane.remove(200), None);
+        assert_eq!(lane.breakpoints().len(), 2);
+    }
+
+    #[test]
+    fn reuses_event_buffer() {
+        let lane = lane(&[(0, 1.0, Curve::Step)]);
+        let mut events = lane.events(0.0, 64);
+        lane.events_into(64.0, 0, &mut events);
+        assert!(events.is_empty());
+        lane.events_into(64.0, 64, &mut events);
+        assert_eq!(events.len(), 1);
+    }
+}
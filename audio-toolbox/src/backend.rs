@@ -0,0 +1,538 @@
+//! The seam between the safe wrappers and the AudioToolbox C API.
+//!
+//! Every call the wrappers make on an audio unit goes through an
+//! `AudioUnitBackend`. Units created by AudioToolbox use `FfiBackend`, which
+//! forwards to the framework. `AudioUnit::with_backend` creates a unit driven
+//! by any other implementation, e.g. a fake that returns scripted errors.
+
+use ffi;
+use std::ops;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::Arc;
+
+/// `kAudio_UnimplementedError`
+pub const UNIMPLEMENTED: ffi::OSStatus = -4;
+
+/// The AudioUnit API, one method per C function.
+///
+/// Methods take the same arguments as their C counterparts and return the
+/// `OSStatus` the wrappers turn into a `Result`. `unit` is the handle the
+/// method was invoked through; pass it on to any listener or render notify
+/// procs the backend calls. Every method defaults to returning
+/// `kAudio_UnimplementedError`, so a fake only needs to provide the calls it
+/// scripts.
+///
+/// Only the wrappers call these methods. Each method's `# Safety` section
+/// says what they guarantee, which an implementation may rely on, and what
+/// they in turn rely on the implementation for.
+#[allow(unused_variables)]
+pub trait AudioUnitBackend: Send + Sync {
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend, as for every method: it
+    /// came from `AudioComponentInstanceNew` or `AudioUnit::with_backend`,
+    /// and `dispose` hasn't been called on it.
+    unsafe fn initialize(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend.
+    unsafe fn uninitialize(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. `data_size` and `writable`
+    /// are each either null or valid for a write for the duration of the
+    /// call.
+    unsafe fn get_property_info(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data_size: *mut u32,
+        writable: *mut ffi::Boolean,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. `data_size` is valid for
+    /// reads and writes, and `data` for writes of the `*data_size` bytes it
+    /// holds on entry, for the duration of the call. `data` is aligned for
+    /// the property's C type. On success `*data_size` is set to the bytes
+    /// written, no more than it held on entry.
+    unsafe fn get_property(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data: *mut c_void,
+        data_size: *mut u32,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. `data` is null or valid for
+    /// reads of `data_size` bytes, aligned for the property's C type, for the
+    /// duration of the call; the backend copies what it keeps. A
+    /// `kAudioUnitProperty_SetRenderCallback` or
+    /// `kAudioOutputUnitProperty_SetInputCallback` set doesn't return while
+    /// the callback it replaces may still be running on another thread, as
+    /// the caller frees the callback's user data as soon as it does.
+    unsafe fn set_property(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data: *const c_void,
+        data_size: u32,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. `user_data` stays valid
+    /// until the listener is removed with the same `id`, `proc_` and
+    /// `user_data`, or `unit` is disposed.
+    unsafe fn add_property_listener(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        proc_: ffi::AudioUnitPropertyListenerProc,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. On success the backend
+    /// doesn't return until no other thread is running the listener, and
+    /// never calls it again, as the caller frees `user_data` straight away.
+    unsafe fn remove_property_listener_with_user_data(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        proc_: ffi::AudioUnitPropertyListenerProc,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. `user_data` stays valid
+    /// until the notification is removed with the same `proc_` and
+    /// `user_data`, or `unit` is disposed.
+    unsafe fn add_render_notify(
+        &self,
+        unit: ffi::AudioUnit,
+        proc_: ffi::AURenderCallback,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. On success the backend
+    /// doesn't return while a render on another thread may still call
+    /// `proc_` with `user_data`, and never calls it again, as the caller
+    /// frees `user_data` straight away.
+    unsafe fn remove_render_notify(
+        &self,
+        unit: ffi::AudioUnit,
+        proc_: ffi::AURenderCallback,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend and `value` is valid for a
+    /// write for the duration of the call.
+    unsafe fn get_parameter(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitParameterID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        value: *mut ffi::AudioUnitParameterValue,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend.
+    unsafe fn set_parameter(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitParameterID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        value: ffi::AudioUnitParameterValue,
+        buffer_offset_in_frames: u32,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend and `events` points to
+    /// `num_events` initialized events, valid for reads for the duration of
+    /// the call.
+    unsafe fn schedule_parameters(
+        &self,
+        unit: ffi::AudioUnit,
+        events: *const ffi::AudioUnitParameterEvent,
+        num_events: u32,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend. `action_flags` is null or
+    /// valid for reads and writes, and `time_stamp` valid for reads, for the
+    /// duration of the call. `data` is a valid buffer list whose buffers
+    /// either hold `mDataByteSize` writable bytes or have null `mData`, in
+    /// which case the backend may point them at memory of its own that
+    /// stays valid until the next render of `unit`.
+    unsafe fn render(
+        &self,
+        unit: ffi::AudioUnit,
+        action_flags: *mut ffi::AudioUnitRenderActionFlags,
+        time_stamp: *const ffi::AudioTimeStamp,
+        output_bus_number: u32,
+        number_frames: u32,
+        data: *mut ffi::AudioBufferList,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// As for `render`, except that `data` holds the input on entry and is
+    /// processed in place.
+    unsafe fn process(
+        &self,
+        unit: ffi::AudioUnit,
+        action_flags: *mut ffi::AudioUnitRenderActionFlags,
+        time_stamp: *const ffi::AudioTimeStamp,
+        number_frames: u32,
+        data: *mut ffi::AudioBufferList,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend.
+    unsafe fn reset(
+        &self,
+        unit: ffi::AudioUnit,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// `AUParameterListenerNotify` with no sending listener or object, for a
+    /// parameter of `unit`.
+    ///
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend and `parameter` is valid for
+    /// reads for the duration of the call, naming `unit` in `mAudioUnit`.
+    unsafe fn parameter_listener_notify(
+        &self,
+        unit: ffi::AudioUnit,
+        parameter: *const ffi::AudioUnitParameter,
+    ) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for an output unit driven by this backend.
+    unsafe fn output_unit_start(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// # Safety
+    ///
+    /// `unit` is a live handle for an output unit driven by this backend.
+    unsafe fn output_unit_stop(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        UNIMPLEMENTED
+    }
+
+    /// Called once when the owning `AudioUnit` is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `unit` is a live handle for this backend, and is never passed to it
+    /// again afterwards.
+    unsafe fn dispose(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        0
+    }
+}
+
+/// The backend for units instantiated from an `AudioComponent`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FfiBackend;
+
+impl AudioUnitBackend for FfiBackend {
+    unsafe fn initialize(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        ffi::AudioUnitInitialize(unit)
+    }
+
+    unsafe fn uninitialize(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        ffi::AudioUnitUninitialize(unit)
+    }
+
+    unsafe fn get_property_info(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data_size: *mut u32,
+        writable: *mut ffi::Boolean,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitGetPropertyInfo(unit, id, scope, element, data_size, writable)
+    }
+
+    unsafe fn get_property(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data: *mut c_void,
+        data_size: *mut u32,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitGetProperty(unit, id, scope, element, data, data_size)
+    }
+
+    unsafe fn set_property(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data: *const c_void,
+        data_size: u32,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitSetProperty(unit, id, scope, element, data, data_size)
+    }
+
+    unsafe fn add_property_listener(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        proc_: ffi::AudioUnitPropertyListenerProc,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitAddPropertyListener(unit, id, Some(proc_), user_data)
+    }
+
+    unsafe fn remove_property_listener_with_user_data(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        proc_: ffi::AudioUnitPropertyListenerProc,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitRemovePropertyListenerWithUserData(unit, id, Some(proc_), user_data)
+    }
+
+    unsafe fn add_render_notify(
+        &self,
+        unit: ffi::AudioUnit,
+        proc_: ffi::AURenderCallback,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitAddRenderNotify(unit, Some(proc_), user_data)
+    }
+
+    unsafe fn remove_render_notify(
+        &self,
+        unit: ffi::AudioUnit,
+        proc_: ffi::AURenderCallback,
+        user_data: *mut c_void,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitRemoveRenderNotify(unit, Some(proc_), user_data)
+    }
+
+    unsafe fn get_parameter(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitParameterID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        value: *mut ffi::AudioUnitParameterValue,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitGetParameter(unit, id, scope, element, value)
+    }
+
+    unsafe fn set_parameter(
+        &self,
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitParameterID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        value: ffi::AudioUnitParameterValue,
+        buffer_offset_in_frames: u32,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitSetParameter(
+            unit,
+            id,
+            scope,
+            element,
+            value,
+            buffer_offset_in_frames,
+        )
+    }
+
+    unsafe fn schedule_parameters(
+        &self,
+        unit: ffi::AudioUnit,
+        events: *const ffi::AudioUnitParameterEvent,
+        num_events: u32,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitScheduleParameters(unit, events, num_events)
+    }
+
+    unsafe fn render(
+        &self,
+        unit: ffi::AudioUnit,
+        action_flags: *mut ffi::AudioUnitRenderActionFlags,
+        time_stamp: *const ffi::AudioTimeStamp,
+        output_bus_number: u32,
+        number_frames: u32,
+        data: *mut ffi::AudioBufferList,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitRender(
+            unit,
+            action_flags,
+            time_stamp,
+            output_bus_number,
+            number_frames,
+            data,
+        )
+    }
+
+    unsafe fn process(
+        &self,
+        unit: ffi::AudioUnit,
+        action_flags: *mut ffi::AudioUnitRenderActionFlags,
+        time_stamp: *const ffi::AudioTimeStamp,
+        number_frames: u32,
+        data: *mut ffi::AudioBufferList,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitProcess(unit, action_flags, time_stamp, number_frames, data)
+    }
+
+    unsafe fn reset(
+        &self,
+        unit: ffi::AudioUnit,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) -> ffi::OSStatus {
+        ffi::AudioUnitReset(unit, scope, element)
+    }
+
+    unsafe fn parameter_listener_notify(
+        &self,
+        _unit: ffi::AudioUnit,
+        parameter: *const ffi::AudioUnitParameter,
+    ) -> ffi::OSStatus {
+        ffi::AUParameterListenerNotify(ptr::null_mut(), ptr::null_mut(), parameter)
+    }
+
+    unsafe fn output_unit_start(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        ffi::AudioOutputUnitStart(unit)
+    }
+
+    unsafe fn output_unit_stop(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        ffi::AudioOutputUnitStop(unit)
+    }
+
+    unsafe fn dispose(&self, unit: ffi::AudioUnit) -> ffi::OSStatus {
+        ffi::AudioComponentInstanceDispose(unit)
+    }
+}
+
+//==============================================================================
+// Dispatch
+
+// A unit with a custom backend has a handle of its own, pointing at an
+// `Installed` that holds the backend. Its low bit is set: real instances are
+// heap allocations, so theirs never is. That lets the render thread find a
+// unit's backend without taking a lock.
+const INSTALLED: usize = 1;
+
+struct Installed {
+    backend: Arc<AudioUnitBackend>,
+}
+
+unsafe fn installed<'a>(unit: ffi::AudioUnit) -> Option<&'a Installed> {
+    if unit as usize & INSTALLED == 0 {
+        None
+    } else {
+        Some(&*((unit as usize & !INSTALLED) as *const Installed))
+    }
+}
+
+/// The backend driving `unit`.
+pub struct Backend(Option<Arc<AudioUnitBackend>>);
+
+impl ops::Deref for Backend {
+    type Target = AudioUnitBackend;
+
+    fn deref(&self) -> &Self::Target {
+        match self.0 {
+            Some(ref backend) => &**backend,
+            None => &FfiBackend,
+        }
+    }
+}
+
+pub fn get(unit: ffi::AudioUnit) -> Backend {
+    // Handles passed in come from a live `AudioUnit`, so an `Installed`
+    // one hasn't been disposed.
+    Backend(unsafe { installed(unit) }.map(|installed| installed.backend.clone()))
+}
+
+/// Allocate a handle for a unit driven by `backend`.
+pub fn install(backend: Arc<AudioUnitBackend>) -> ffi::AudioUnit {
+    let installed = Box::into_raw(Box::new(Installed { backend }));
+    (installed as usize | INSTALLED) as ffi::AudioUnit
+}
+
+/// Dispose of `unit` through its backend, releasing the handle if it came
+/// from `install`.
+pub unsafe fn dispose(unit: ffi::AudioUnit) -> ffi::OSStatus {
+    match installed(unit) {
+        Some(installed) => {
+            let status = installed.backend.dispose(unit);
+            drop(Box::from_raw(installed as *const Installed as *mut Installed));
+            status
+        },
+        None => FfiBackend.dispose(unit),
+    }
+}
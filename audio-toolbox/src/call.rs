@@ -1,5 +1,5 @@
-use audio_toolbox_sys as ffi;
-use core_audio::Error;
+use ffi;
+use Error;
 
 pub fn cvt_r(ret: ffi::OSStatus) -> Result<(), Error> {
     match ret {
@@ -0,0 +1,92 @@
+//! Copying the Core Foundation values AudioToolbox hands out into Rust
+//! types. Calls go through `ffi` so the simulator can stand in for Core
+//! Foundation too.
+
+use {Error, Result};
+use ffi;
+use plist;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+/// Copy a `CFString` into a `String`. Ownership of `s` isn't affected.
+pub unsafe fn string(s: ffi::CFStringRef) -> String {
+    let len = ffi::CFStringGetLength(s);
+    let encoding = ffi::kCFStringEncodingUTF8;
+    let size = ffi::CFStringGetMaximumSizeForEncoding(len, encoding) + 1;
+    let mut buffer = vec![0 as c_char; size as usize];
+    if ffi::CFStringGetCString(s, buffer.as_mut_ptr(), size, encoding) == 0 {
+        return String::new();
+    }
+    CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+}
+
+/// A new `CFString` holding `s`. The caller releases it.
+pub fn create_string(s: &str) -> ffi::CFStringRef {
+    unsafe {
+        ffi::CFStringCreateWithBytes(
+            ptr::null(),
+            s.as_ptr(),
+            s.len() as ffi::CFIndex,
+            ffi::kCFStringEncodingUTF8,
+            0,
+        )
+    }
+}
+
+/// Copy a `CFArray` of `CFString`s. Ownership of `a` isn't affected.
+pub unsafe fn string_array(a: ffi::CFArrayRef) -> Vec<String> {
+    (0..ffi::CFArrayGetCount(a))
+        .map(|i| string(ffi::CFArrayGetValueAtIndex(a, i) as ffi::CFStringRef))
+        .collect()
+}
+
+/// Copy a property list into a `plist::Value`, going through its binary
+/// encoding. Ownership of `p` isn't affected.
+pub unsafe fn property_list(p: ffi::CFPropertyListRef) -> Result<plist::Value> {
+    let mut error = ptr::null();
+    let data = ffi::CFPropertyListCreateData(
+        ptr::null(),
+        p,
+        ffi::kCFPropertyListBinaryFormat_v1_0,
+        0,
+        &mut error,
+    );
+    if data.is_null() {
+        if !error.is_null() {
+            ffi::CFRelease(error as ffi::CFTypeRef);
+        }
+        return Err(Error::Status(ffi::kAudioUnitErr_InvalidPropertyValue));
+    }
+    let bytes = ffi::CFDataGetBytePtr(data);
+    let len = ffi::CFDataGetLength(data) as usize;
+    let value = plist::from_bytes(slice::from_raw_parts(bytes, len));
+    ffi::CFRelease(data as ffi::CFTypeRef);
+    value
+}
+
+/// A new property list holding `value`. The caller releases it.
+pub fn create_property_list(value: &plist::Value) -> Result<ffi::CFPropertyListRef> {
+    let bytes = plist::to_binary(value);
+    let len = bytes.len() as ffi::CFIndex;
+    unsafe {
+        let data = ffi::CFDataCreate(ptr::null(), bytes.as_ptr(), len);
+        let mut error = ptr::null();
+        let p = ffi::CFPropertyListCreateWithData(
+            ptr::null(),
+            data,
+            ffi::kCFPropertyListImmutable,
+            ptr::null_mut(),
+            &mut error,
+        );
+        ffi::CFRelease(data as ffi::CFTypeRef);
+        if p.is_null() {
+            if !error.is_null() {
+                ffi::CFRelease(error as ffi::CFTypeRef);
+            }
+            return Err(Error::Status(ffi::kAudioUnitErr_InvalidPropertyValue));
+        }
+        Ok(p)
+    }
+}
@@ -0,0 +1,316 @@
+use {AudioUnitRef, AudioUnitScope, ChannelLayout, Error, Result};
+use ffi;
+use props;
+
+/// How many channels one side of a `ChannelConfig` allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelCount {
+    Exactly(u32),
+    /// -1 or -2.
+    Any,
+    /// -N for N > 2.
+    UpTo(u32),
+}
+
+impl ChannelCount {
+    pub fn allows(&self, n: u32) -> bool {
+        match *self {
+            ChannelCount::Exactly(m) => n == m,
+            ChannelCount::Any => true,
+            ChannelCount::UpTo(m) => n <= m,
+        }
+    }
+
+    /// The allowed count nearest to `n`.
+    pub fn nearest(&self, n: u32) -> u32 {
+        match *self {
+            ChannelCount::Exactly(m) => m,
+            ChannelCount::Any => n,
+            ChannelCount::UpTo(m) => n.min(m),
+        }
+    }
+}
+
+impl ::std::convert::From<i16> for ChannelCount {
+    fn from(n: i16) -> Self {
+        match n {
+            -1 | -2 => ChannelCount::Any,
+            n if n < 0 => ChannelCount::UpTo(-(n as i32) as u32),
+            n => ChannelCount::Exactly(n as u32),
+        }
+    }
+}
+
+impl ::std::convert::Into<i16> for ChannelCount {
+    /// `Any` becomes -1. Counts too large for an `i16` are clamped.
+    fn into(self) -> i16 {
+        match self {
+            ChannelCount::Exactly(n) => n.min(i16::max_value() as u32) as i16,
+            ChannelCount::Any => -1,
+            ChannelCount::UpTo(n) => -(n.min(32768) as i32) as i16,
+        }
+    }
+}
+
+/// One entry of a unit's `SupportedNumChannels`, decoded from an
+/// `AUChannelInfo`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelConfig {
+    /// -1 on both sides: any count, as long as inputs and outputs match.
+    Matching,
+    Counts {
+        inputs: ChannelCount,
+        outputs: ChannelCount,
+    },
+}
+
+impl ChannelConfig {
+    /// Whether the configuration allows `inputs` input and `outputs`
+    /// output channels.
+    pub fn supports(&self, inputs: u32, outputs: u32) -> bool {
+        match *self {
+            ChannelConfig::Matching => inputs == outputs,
+            ChannelConfig::Counts { inputs: i, outputs: o } => {
+                i.allows(inputs) && o.allows(outputs)
+            },
+        }
+    }
+
+    /// The pairs this configuration allows that are nearest to the one
+    /// wanted.
+    fn nearest(&self, inputs: u32, outputs: u32) -> Vec<(u32, u32)> {
+        match *self {
+            ChannelConfig::Matching => vec![(inputs, inputs), (outputs, outputs)],
+            ChannelConfig::Counts { inputs: i, outputs: o } => {
+                vec![(i.nearest(inputs), o.nearest(outputs))]
+            },
+        }
+    }
+}
+
+impl ::std::convert::From<ffi::AUChannelInfo> for ChannelConfig {
+    fn from(info: ffi::AUChannelInfo) -> Self {
+        if info.inChannels == -1 && info.outChannels == -1 {
+            return ChannelConfig::Matching;
+        }
+        ChannelConfig::Counts {
+            inputs: info.inChannels.into(),
+            outputs: info.outChannels.into(),
+        }
+    }
+}
+
+impl ::std::convert::Into<ffi::AUChannelInfo> for ChannelConfig {
+    /// `Any` on both sides is written as -1/-2, which unlike -1/-1 doesn't
+    /// require the counts to match.
+    fn into(self) -> ffi::AUChannelInfo {
+        match self {
+            ChannelConfig::Matching => ffi::AUChannelInfo {
+                inChannels: -1,
+                outChannels: -1,
+            },
+            ChannelConfig::Counts {
+                inputs: ChannelCount::Any,
+                outputs: ChannelCount::Any,
+            } => ffi::AUChannelInfo {
+                inChannels: -1,
+                outChannels: -2,
+            },
+            ChannelConfig::Counts { inputs, outputs } => ffi::AUChannelInfo {
+                inChannels: inputs.into(),
+                outChannels: outputs.into(),
+            },
+        }
+    }
+}
+
+/// Whether `info` allows `inputs` input and `outputs` output channels.
+///
+/// Non-negative values are exact counts. -1 on both sides allows any count
+/// provided the two are equal; otherwise -1 and -2 allow any count on their
+/// side. Other negative values -N allow up to N channels.
+pub fn channel_info_supports(
+    info: &ffi::AUChannelInfo,
+    inputs: u32,
+    outputs: u32,
+) -> bool {
+    ChannelConfig::from(*info).supports(inputs, outputs)
+}
+
+/// Whether any of `configs` allows the pair, as with `channel_info_supports`.
+pub fn channel_configs_support(
+    configs: &[ffi::AUChannelInfo],
+    inputs: u32,
+    outputs: u32,
+) -> bool {
+    configs.iter().any(|info| channel_info_supports(info, inputs, outputs))
+}
+
+/// The (inputs, outputs) pair allowed by `configs` that is closest to the
+/// one wanted. Ties go to the pair that drops fewer of the wanted channels,
+/// then to the earlier configuration. `None` if `configs` is empty.
+pub fn best_channel_config(
+    configs: &[ffi::AUChannelInfo],
+    inputs: u32,
+    outputs: u32,
+) -> Option<(u32, u32)> {
+    let distance = |(i, o): (u32, u32)| {
+        let diff = |a: u32, b: u32| if a > b { a - b } else { b - a };
+        let dropped = inputs.saturating_sub(i).saturating_add(outputs.saturating_sub(o));
+        (diff(i, inputs).saturating_add(diff(o, outputs)), dropped)
+    };
+    let mut best: Option<(u32, u32)> = None;
+    for info in configs {
+        for candidate in ChannelConfig::from(*info).nearest(inputs, outputs) {
+            if best.map_or(true, |b| distance(candidate) < distance(b)) {
+                best = Some(candidate);
+            }
+        }
+    }
+    best
+}
+
+impl AudioUnitRef {
+    /// The unit's `kAudioUnitProperty_SupportedNumChannels`. Units that
+    /// don't publish it accept any count as long as inputs and outputs
+    /// match, which is reported as a single -1/-1 entry.
+    pub fn supported_num_channels(&self) -> Result<Vec<ffi::AUChannelInfo>> {
+        match self.get(props::SupportedNumChannels, AudioUnitScope::Global, 0) {
+            Err(Error::Status(ffi::kAudioUnitErr_InvalidProperty)) => {
+                Ok(vec![ffi::AUChannelInfo {
+                    inChannels: -1,
+                    outChannels: -1,
+                }])
+            },
+            result => result,
+        }
+    }
+
+    /// Whether the unit can be initialized with `inputs` input and
+    /// `outputs` output channels.
+    pub fn supports_channels(&self, inputs: u32, outputs: u32) -> Result<bool> {
+        let configs = self.supported_num_channels()?;
+        Ok(channel_configs_support(&configs, inputs, outputs))
+    }
+
+    /// The supported (inputs, outputs) pair closest to the one wanted; see
+    /// `best_channel_config`.
+    pub fn best_channel_config(
+        &self,
+        inputs: u32,
+        outputs: u32,
+    ) -> Result<Option<(u32, u32)>> {
+        let configs = self.supported_num_channels()?;
+        Ok(best_channel_config(&configs, inputs, outputs))
+    }
+
+    /// `best_channel_config` for the channel counts of two layouts. Only
+    /// the counts matter: `SupportedNumChannels` says nothing about which
+    /// speakers a unit expects, so pick the layout itself from
+    /// `supported_channel_layout_tags` once the counts are settled.
+    pub fn best_channel_config_for_layouts(
+        &self,
+        input: &ChannelLayout,
+        output: &ChannelLayout,
+    ) -> Result<Option<(u32, u32)>> {
+        self.best_channel_config(input.channel_count(), output.channel_count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(inputs: i16, outputs: i16) -> ffi::AUChannelInfo {
+        ffi::AUChannelInfo {
+            inChannels: inputs,
+            outChannels: outputs,
+        }
+    }
+
+    #[test]
+    fn documented_examples() {
+        // The examples from the `kAudioUnitProperty_SupportedNumChannels`
+        // documentation, as (config, inputs, outputs, supported).
+        let table = [
+            // Mono in, mono out.
+            ((1, 1), 1, 1, true),
+            ((1, 1), 2, 2, false),
+            // Mono in, stereo out.
+            ((1, 2), 1, 2, true),
+            ((1, 2), 2, 2, false),
+            // Stereo in, stereo out.
+            ((2, 2), 2, 2, true),
+            ((2, 2), 1, 2, false),
+            // Any number in and out, as long as they match.
+            ((-1, -1), 1, 1, true),
+            ((-1, -1), 6, 6, true),
+            ((-1, -1), 2, 6, false),
+            // Any number in and out, independently.
+            ((-1, -2), 2, 6, true),
+            ((-2, -1), 6, 2, true),
+            ((-2, -2), 8, 1, true),
+            // Any number in, stereo out.
+            ((-1, 2), 5, 2, true),
+            ((-1, 2), 5, 1, false),
+            // Up to 4 in, up to 8 out.
+            ((-4, -8), 4, 8, true),
+            ((-4, -8), 1, 3, true),
+            ((-4, -8), 5, 8, false),
+            ((-4, -8), 4, 9, false),
+            // No input: a generator or instrument.
+            ((0, 2), 0, 2, true),
+            ((0, 2), 2, 2, false),
+        ];
+        for &((i, o), inputs, outputs, supported) in table.iter() {
+            assert_eq!(channel_info_supports(&info(i, o), inputs, outputs), supported,
+                       "{}/{} with {} in, {} out", i, o, inputs, outputs);
+        }
+        let configs = [info(1, 1), info(1, 2), info(2, 2)];
+        assert!(channel_configs_support(&configs, 1, 2));
+        assert!(!channel_configs_support(&configs, 2, 1));
+        assert!(!channel_configs_support(&[], 0, 0));
+    }
+
+    #[test]
+    fn configs_round_trip() {
+        use self::ChannelCount::*;
+        let counts = |inputs, outputs| ChannelConfig::Counts { inputs, outputs };
+        let table = [
+            ((1, 2), counts(Exactly(1), Exactly(2))),
+            ((-1, -1), ChannelConfig::Matching),
+            ((-1, -2), counts(Any, Any)),
+            ((-1, 2), counts(Any, Exactly(2))),
+            ((0, -8), counts(Exactly(0), UpTo(8))),
+        ];
+        for &((i, o), config) in table.iter() {
+            assert_eq!(ChannelConfig::from(info(i, o)), config);
+            let back: ffi::AUChannelInfo = config.into();
+            assert_eq!((back.inChannels, back.outChannels), (i, o));
+        }
+        // -2 reads as any count, which is written back as -1.
+        assert_eq!(ChannelConfig::from(info(-2, 2)), counts(Any, Exactly(2)));
+        let back: ffi::AUChannelInfo = counts(UpTo(40000), Exactly(70000)).into();
+        assert_eq!((back.inChannels, back.outChannels), (-32768, 32767));
+    }
+
+    #[test]
+    fn best_config_is_the_nearest_pair() {
+        let configs = [info(1, 1), info(1, 2), info(2, 2)];
+        assert_eq!(best_channel_config(&configs, 1, 2), Some((1, 2)));
+        // (2, 2) and (1, 1) are as far from (2, 1); (2, 2) drops nothing.
+        assert_eq!(best_channel_config(&configs, 2, 1), Some((2, 2)));
+        assert_eq!(best_channel_config(&configs, 6, 6), Some((2, 2)));
+        assert_eq!(best_channel_config(&[info(-1, -1)], 2, 6), Some((6, 6)));
+        assert_eq!(best_channel_config(&[info(-4, -8)], 6, 6), Some((4, 6)));
+        assert_eq!(best_channel_config(&[], 2, 2), None);
+    }
+
+    #[test]
+    fn huge_counts_do_not_overflow() {
+        let max = u32::max_value();
+        let configs = [info(1, 1), info(-1, -1)];
+        assert_eq!(best_channel_config(&configs, max, max), Some((max, max)));
+        assert_eq!(best_channel_config(&[info(0, 0)], max, max), Some((0, 0)));
+    }
+}
@@ -0,0 +1,631 @@
+use {AudioUnitElement, AudioUnitRef, AudioUnitScope, Result};
+use ffi;
+use props;
+
+/// A `kAudioChannelLayoutTag_*`. The low 16 bits of every tag but the two
+/// `Use*` ones hold its channel count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelLayoutTag {
+    /// The layout is given by its channel descriptions.
+    UseChannelDescriptions,
+    /// The layout is given by its channel bitmap.
+    UseChannelBitmap,
+    Mono,
+    Stereo,
+    StereoHeadphones,
+    MatrixStereo,
+    MidSide,
+    XY,
+    Binaural,
+    AmbisonicBFormat,
+    /// Also `kAudioChannelLayoutTag_AudioUnit_4`.
+    Quadraphonic,
+    /// Also `kAudioChannelLayoutTag_AudioUnit_5`.
+    Pentagonal,
+    /// Also `kAudioChannelLayoutTag_AudioUnit_6`.
+    Hexagonal,
+    /// Also `kAudioChannelLayoutTag_AudioUnit_8`.
+    Octagonal,
+    Cube,
+    Mpeg3_0A,
+    Mpeg3_0B,
+    Mpeg4_0A,
+    Mpeg4_0B,
+    Mpeg5_0A,
+    /// Also `kAudioChannelLayoutTag_AudioUnit_5_0`.
+    Mpeg5_0B,
+    Mpeg5_0C,
+    Mpeg5_0D,
+    /// L R C LFE Ls Rs, also `kAudioChannelLayoutTag_AudioUnit_5_1`.
+    Mpeg5_1A,
+    Mpeg5_1B,
+    Mpeg5_1C,
+    Mpeg5_1D,
+    /// L R C LFE Ls Rs Cs, also `kAudioChannelLayoutTag_AudioUnit_6_1`.
+    Mpeg6_1A,
+    /// L R C LFE Ls Rs Lc Rc, also `kAudioChannelLayoutTag_AudioUnit_7_1_Front`.
+    Mpeg7_1A,
+    Mpeg7_1B,
+    /// L R C LFE Ls Rs Rls Rrs, also `kAudioChannelLayoutTag_AudioUnit_7_1`.
+    Mpeg7_1C,
+    EmagicDefault7_1,
+    SmpteDtv,
+    Itu2_1,
+    Itu2_2,
+    AudioUnit6_0,
+    AudioUnit7_0,
+    AudioUnit7_0Front,
+    /// `kAudioChannelLayoutTag_DiscreteInOrder` with this many channels.
+    DiscreteInOrder(u32),
+    /// `kAudioChannelLayoutTag_Unknown` with this many channels.
+    Unknown(u32),
+    Other(ffi::AudioChannelLayoutTag),
+}
+
+impl ChannelLayoutTag {
+    /// Channels in the layout the tag names, or zero for
+    /// `UseChannelDescriptions` and `UseChannelBitmap`.
+    pub fn channel_count(&self) -> u32 {
+        let tag: ffi::AudioChannelLayoutTag = (*self).into();
+        tag & 0xFFFF
+    }
+}
+
+impl ::std::convert::From<ffi::AudioChannelLayoutTag> for ChannelLayoutTag {
+    fn from(ffi: ffi::AudioChannelLayoutTag) -> Self {
+        use ChannelLayoutTag::*;
+        let count = ffi & 0xFFFF;
+        match ffi {
+            ffi::kAudioChannelLayoutTag_UseChannelDescriptions => UseChannelDescriptions,
+            ffi::kAudioChannelLayoutTag_UseChannelBitmap => UseChannelBitmap,
+            ffi::kAudioChannelLayoutTag_Mono => Mono,
+            ffi::kAudioChannelLayoutTag_Stereo => Stereo,
+            ffi::kAudioChannelLayoutTag_StereoHeadphones => StereoHeadphones,
+            ffi::kAudioChannelLayoutTag_MatrixStereo => MatrixStereo,
+            ffi::kAudioChannelLayoutTag_MidSide => MidSide,
+            ffi::kAudioChannelLayoutTag_XY => XY,
+            ffi::kAudioChannelLayoutTag_Binaural => Binaural,
+            ffi::kAudioChannelLayoutTag_Ambisonic_B_Format => AmbisonicBFormat,
+            ffi::kAudioChannelLayoutTag_Quadraphonic => Quadraphonic,
+            ffi::kAudioChannelLayoutTag_Pentagonal => Pentagonal,
+            ffi::kAudioChannelLayoutTag_Hexagonal => Hexagonal,
+            ffi::kAudioChannelLayoutTag_Octagonal => Octagonal,
+            ffi::kAudioChannelLayoutTag_Cube => Cube,
+            ffi::kAudioChannelLayoutTag_MPEG_3_0_A => Mpeg3_0A,
+            ffi::kAudioChannelLayoutTag_MPEG_3_0_B => Mpeg3_0B,
+            ffi::kAudioChannelLayoutTag_MPEG_4_0_A => Mpeg4_0A,
+            ffi::kAudioChannelLayoutTag_MPEG_4_0_B => Mpeg4_0B,
+            ffi::kAudioChannelLayoutTag_MPEG_5_0_A => Mpeg5_0A,
+            ffi::kAudioChannelLayoutTag_MPEG_5_0_B => Mpeg5_0B,
+            ffi::kAudioChannelLayoutTag_MPEG_5_0_C => Mpeg5_0C,
+            ffi::kAudioChannelLayoutTag_MPEG_5_0_D => Mpeg5_0D,
+            ffi::kAudioChannelLayoutTag_MPEG_5_1_A => Mpeg5_1A,
+            ffi::kAudioChannelLayoutTag_MPEG_5_1_B => Mpeg5_1B,
+            ffi::kAudioChannelLayoutTag_MPEG_5_1_C => Mpeg5_1C,
+            ffi::kAudioChannelLayoutTag_MPEG_5_1_D => Mpeg5_1D,
+            ffi::kAudioChannelLayoutTag_MPEG_6_1_A => Mpeg6_1A,
+            ffi::kAudioChannelLayoutTag_MPEG_7_1_A => Mpeg7_1A,
+            ffi::kAudioChannelLayoutTag_MPEG_7_1_B => Mpeg7_1B,
+            ffi::kAudioChannelLayoutTag_MPEG_7_1_C => Mpeg7_1C,
+            ffi::kAudioChannelLayoutTag_Emagic_Default_7_1 => EmagicDefault7_1,
+            ffi::kAudioChannelLayoutTag_SMPTE_DTV => SmpteDtv,
+            ffi::kAudioChannelLayoutTag_ITU_2_1 => Itu2_1,
+            ffi::kAudioChannelLayoutTag_ITU_2_2 => Itu2_2,
+            ffi::kAudioChannelLayoutTag_AudioUnit_6_0 => AudioUnit6_0,
+            ffi::kAudioChannelLayoutTag_AudioUnit_7_0 => AudioUnit7_0,
+            ffi::kAudioChannelLayoutTag_AudioUnit_7_0_Front => AudioUnit7_0Front,
+            _ if ffi - count == ffi::kAudioChannelLayoutTag_DiscreteInOrder => {
+                DiscreteInOrder(count)
+            },
+            _ if ffi - count == ffi::kAudioChannelLayoutTag_Unknown => Unknown(count),
+            other => Other(other),
+        }
+    }
+}
+
+impl ::std::convert::Into<ffi::AudioChannelLayoutTag> for ChannelLayoutTag {
+    fn into(self) -> ffi::AudioChannelLayoutTag {
+        use ChannelLayoutTag::*;
+        match self {
+            UseChannelDescriptions => ffi::kAudioChannelLayoutTag_UseChannelDescriptions,
+            UseChannelBitmap => ffi::kAudioChannelLayoutTag_UseChannelBitmap,
+            Mono => ffi::kAudioChannelLayoutTag_Mono,
+            Stereo => ffi::kAudioChannelLayoutTag_Stereo,
+            StereoHeadphones => ffi::kAudioChannelLayoutTag_StereoHeadphones,
+            MatrixStereo => ffi::kAudioChannelLayoutTag_MatrixStereo,
+            MidSide => ffi::kAudioChannelLayoutTag_MidSide,
+            XY => ffi::kAudioChannelLayoutTag_XY,
+            Binaural => ffi::kAudioChannelLayoutTag_Binaural,
+            AmbisonicBFormat => ffi::kAudioChannelLayoutTag_Ambisonic_B_Format,
+            Quadraphonic => ffi::kAudioChannelLayoutTag_Quadraphonic,
+            Pentagonal => ffi::kAudioChannelLayoutTag_Pentagonal,
+            Hexagonal => ffi::kAudioChannelLayoutTag_Hexagonal,
+            Octagonal => ffi::kAudioChannelLayoutTag_Octagonal,
+            Cube => ffi::kAudioChannelLayoutTag_Cube,
+            Mpeg3_0A => ffi::kAudioChannelLayoutTag_MPEG_3_0_A,
+            Mpeg3_0B => ffi::kAudioChannelLayoutTag_MPEG_3_0_B,
+            Mpeg4_0A => ffi::kAudioChannelLayoutTag_MPEG_4_0_A,
+            Mpeg4_0B => ffi::kAudioChannelLayoutTag_MPEG_4_0_B,
+            Mpeg5_0A => ffi::kAudioChannelLayoutTag_MPEG_5_0_A,
+            Mpeg5_0B => ffi::kAudioChannelLayoutTag_MPEG_5_0_B,
+            Mpeg5_0C => ffi::kAudioChannelLayoutTag_MPEG_5_0_C,
+            Mpeg5_0D => ffi::kAudioChannelLayoutTag_MPEG_5_0_D,
+            Mpeg5_1A => ffi::kAudioChannelLayoutTag_MPEG_5_1_A,
+            Mpeg5_1B => ffi::kAudioChannelLayoutTag_MPEG_5_1_B,
+            Mpeg5_1C => ffi::kAudioChannelLayoutTag_MPEG_5_1_C,
+            Mpeg5_1D => ffi::kAudioChannelLayoutTag_MPEG_5_1_D,
+            Mpeg6_1A => ffi::kAudioChannelLayoutTag_MPEG_6_1_A,
+            Mpeg7_1A => ffi::kAudioChannelLayoutTag_MPEG_7_1_A,
+            Mpeg7_1B => ffi::kAudioChannelLayoutTag_MPEG_7_1_B,
+            Mpeg7_1C => ffi::kAudioChannelLayoutTag_MPEG_7_1_C,
+            EmagicDefault7_1 => ffi::kAudioChannelLayoutTag_Emagic_Default_7_1,
+            SmpteDtv => ffi::kAudioChannelLayoutTag_SMPTE_DTV,
+            Itu2_1 => ffi::kAudioChannelLayoutTag_ITU_2_1,
+            Itu2_2 => ffi::kAudioChannelLayoutTag_ITU_2_2,
+            AudioUnit6_0 => ffi::kAudioChannelLayoutTag_AudioUnit_6_0,
+            AudioUnit7_0 => ffi::kAudioChannelLayoutTag_AudioUnit_7_0,
+            AudioUnit7_0Front => ffi::kAudioChannelLayoutTag_AudioUnit_7_0_Front,
+            DiscreteInOrder(n) => {
+                ffi::kAudioChannelLayoutTag_DiscreteInOrder | (n & 0xFFFF)
+            },
+            Unknown(n) => ffi::kAudioChannelLayoutTag_Unknown | (n & 0xFFFF),
+            Other(tag) => tag,
+        }
+    }
+}
+
+/// What a channel carries, from `kAudioChannelLabel_*`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelLabel {
+    Unused,
+    UseCoordinates,
+    Left,
+    Right,
+    Center,
+    LfeScreen,
+    LeftSurround,
+    RightSurround,
+    LeftCenter,
+    RightCenter,
+    CenterSurround,
+    LeftSurroundDirect,
+    RightSurroundDirect,
+    TopCenterSurround,
+    VerticalHeightLeft,
+    VerticalHeightCenter,
+    VerticalHeightRight,
+    TopBackLeft,
+    TopBackCenter,
+    TopBackRight,
+    RearSurroundLeft,
+    RearSurroundRight,
+    LeftWide,
+    RightWide,
+    Lfe2,
+    LeftTotal,
+    RightTotal,
+    Mono,
+    Unknown,
+    /// `kAudioChannelLabel_Discrete_N`, a channel with no particular role.
+    Discrete(u32),
+    Other(ffi::AudioChannelLabel),
+}
+
+impl ::std::convert::From<ffi::AudioChannelLabel> for ChannelLabel {
+    fn from(ffi: ffi::AudioChannelLabel) -> Self {
+        use ChannelLabel::*;
+        match ffi {
+            ffi::kAudioChannelLabel_Unused => Unused,
+            ffi::kAudioChannelLabel_UseCoordinates => UseCoordinates,
+            ffi::kAudioChannelLabel_Left => Left,
+            ffi::kAudioChannelLabel_Right => Right,
+            ffi::kAudioChannelLabel_Center => Center,
+            ffi::kAudioChannelLabel_LFEScreen => LfeScreen,
+            ffi::kAudioChannelLabel_LeftSurround => LeftSurround,
+            ffi::kAudioChannelLabel_RightSurround => RightSurround,
+            ffi::kAudioChannelLabel_LeftCenter => LeftCenter,
+            ffi::kAudioChannelLabel_RightCenter => RightCenter,
+            ffi::kAudioChannelLabel_CenterSurround => CenterSurround,
+            ffi::kAudioChannelLabel_LeftSurroundDirect => LeftSurroundDirect,
+            ffi::kAudioChannelLabel_RightSurroundDirect => RightSurroundDirect,
+            ffi::kAudioChannelLabel_TopCenterSurround => TopCenterSurround,
+            ffi::kAudioChannelLabel_VerticalHeightLeft => VerticalHeightLeft,
+            ffi::kAudioChannelLabel_VerticalHeightCenter => VerticalHeightCenter,
+            ffi::kAudioChannelLabel_VerticalHeightRight => VerticalHeightRight,
+            ffi::kAudioChannelLabel_TopBackLeft => TopBackLeft,
+            ffi::kAudioChannelLabel_TopBackCenter => TopBackCenter,
+            ffi::kAudioChannelLabel_TopBackRight => TopBackRight,
+            ffi::kAudioChannelLabel_RearSurroundLeft => RearSurroundLeft,
+            ffi::kAudioChannelLabel_RearSurroundRight => RearSurroundRight,
+            ffi::kAudioChannelLabel_LeftWide => LeftWide,
+            ffi::kAudioChannelLabel_RightWide => RightWide,
+            ffi::kAudioChannelLabel_LFE2 => Lfe2,
+            ffi::kAudioChannelLabel_LeftTotal => LeftTotal,
+            ffi::kAudioChannelLabel_RightTotal => RightTotal,
+            ffi::kAudioChannelLabel_Mono => Mono,
+            ffi::kAudioChannelLabel_Unknown => Unknown,
+            label if label & 0xFFFF_0000 == ffi::kAudioChannelLabel_Discrete => {
+                Discrete(label & 0xFFFF)
+            },
+            other => Other(other),
+        }
+    }
+}
+
+impl ::std::convert::Into<ffi::AudioChannelLabel> for ChannelLabel {
+    fn into(self) -> ffi::AudioChannelLabel {
+        use ChannelLabel::*;
+        match self {
+            Unused => ffi::kAudioChannelLabel_Unused,
+            UseCoordinates => ffi::kAudioChannelLabel_UseCoordinates,
+            Left => ffi::kAudioChannelLabel_Left,
+            Right => ffi::kAudioChannelLabel_Right,
+            Center => ffi::kAudioChannelLabel_Center,
+            LfeScreen => ffi::kAudioChannelLabel_LFEScreen,
+            LeftSurround => ffi::kAudioChannelLabel_LeftSurround,
+            RightSurround => ffi::kAudioChannelLabel_RightSurround,
+            LeftCenter => ffi::kAudioChannelLabel_LeftCenter,
+            RightCenter => ffi::kAudioChannelLabel_RightCenter,
+            CenterSurround => ffi::kAudioChannelLabel_CenterSurround,
+            LeftSurroundDirect => ffi::kAudioChannelLabel_LeftSurroundDirect,
+            RightSurroundDirect => ffi::kAudioChannelLabel_RightSurroundDirect,
+            TopCenterSurround => ffi::kAudioChannelLabel_TopCenterSurround,
+            VerticalHeightLeft => ffi::kAudioChannelLabel_VerticalHeightLeft,
+            VerticalHeightCenter => ffi::kAudioChannelLabel_VerticalHeightCenter,
+            VerticalHeightRight => ffi::kAudioChannelLabel_VerticalHeightRight,
+            TopBackLeft => ffi::kAudioChannelLabel_TopBackLeft,
+            TopBackCenter => ffi::kAudioChannelLabel_TopBackCenter,
+            TopBackRight => ffi::kAudioChannelLabel_TopBackRight,
+            RearSurroundLeft => ffi::kAudioChannelLabel_RearSurroundLeft,
+            RearSurroundRight => ffi::kAudioChannelLabel_RearSurroundRight,
+            LeftWide => ffi::kAudioChannelLabel_LeftWide,
+            RightWide => ffi::kAudioChannelLabel_RightWide,
+            Lfe2 => ffi::kAudioChannelLabel_LFE2,
+            LeftTotal => ffi::kAudioChannelLabel_LeftTotal,
+            RightTotal => ffi::kAudioChannelLabel_RightTotal,
+            Mono => ffi::kAudioChannelLabel_Mono,
+            Unknown => ffi::kAudioChannelLabel_Unknown,
+            Discrete(n) => ffi::kAudioChannelLabel_Discrete | (n & 0xFFFF),
+            Other(label) => label,
+        }
+    }
+}
+
+bitflags! {
+    /// `kAudioChannelBit_*`, the speakers present in a bitmap layout.
+    pub struct ChannelBitmap: u32 {
+        const LEFT = ffi::kAudioChannelBit_Left;
+        const RIGHT = ffi::kAudioChannelBit_Right;
+        const CENTER = ffi::kAudioChannelBit_Center;
+        const LFE_SCREEN = ffi::kAudioChannelBit_LFEScreen;
+        const LEFT_SURROUND = ffi::kAudioChannelBit_LeftSurround;
+        const RIGHT_SURROUND = ffi::kAudioChannelBit_RightSurround;
+        const LEFT_CENTER = ffi::kAudioChannelBit_LeftCenter;
+        const RIGHT_CENTER = ffi::kAudioChannelBit_RightCenter;
+        const CENTER_SURROUND = ffi::kAudioChannelBit_CenterSurround;
+        const LEFT_SURROUND_DIRECT = ffi::kAudioChannelBit_LeftSurroundDirect;
+        const RIGHT_SURROUND_DIRECT = ffi::kAudioChannelBit_RightSurroundDirect;
+        const TOP_CENTER_SURROUND = ffi::kAudioChannelBit_TopCenterSurround;
+        const VERTICAL_HEIGHT_LEFT = ffi::kAudioChannelBit_VerticalHeightLeft;
+        const VERTICAL_HEIGHT_CENTER = ffi::kAudioChannelBit_VerticalHeightCenter;
+        const VERTICAL_HEIGHT_RIGHT = ffi::kAudioChannelBit_VerticalHeightRight;
+        const TOP_BACK_LEFT = ffi::kAudioChannelBit_TopBackLeft;
+        const TOP_BACK_CENTER = ffi::kAudioChannelBit_TopBackCenter;
+        const TOP_BACK_RIGHT = ffi::kAudioChannelBit_TopBackRight;
+    }
+}
+
+impl ChannelBitmap {
+    /// The label of each speaker in the bitmap, in channel order.
+    pub fn labels(&self) -> Vec<ChannelLabel> {
+        // Bit n stands for the speaker labelled n + 1.
+        (0..32)
+            .filter(|&bit| self.bits() & (1 << bit) != 0)
+            .map(|bit| ChannelLabel::from(bit + 1))
+            .collect()
+    }
+}
+
+bitflags! {
+    /// `kAudioChannelFlags_*`, how to read a description's coordinates.
+    pub struct ChannelFlags: u32 {
+        const RECTANGULAR_COORDINATES = ffi::kAudioChannelFlags_RectangularCoordinates;
+        const SPHERICAL_COORDINATES = ffi::kAudioChannelFlags_SphericalCoordinates;
+        const METERS = ffi::kAudioChannelFlags_Meters;
+    }
+}
+
+/// An `AudioChannelDescription`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelDescription {
+    pub label: ChannelLabel,
+    pub flags: ChannelFlags,
+    /// Only meaningful when `label` is `UseCoordinates`.
+    pub coordinates: [f32; 3],
+}
+
+impl ChannelDescription {
+    pub fn new(label: ChannelLabel) -> ChannelDescription {
+        ChannelDescription {
+            label,
+            flags: ChannelFlags::empty(),
+            coordinates: [0.0; 3],
+        }
+    }
+}
+
+impl ::std::convert::From<ffi::AudioChannelDescription> for ChannelDescription {
+    fn from(desc: ffi::AudioChannelDescription) -> Self {
+        ChannelDescription {
+            label: desc.mChannelLabel.into(),
+            flags: ChannelFlags::from_bits_truncate(desc.mChannelFlags),
+            coordinates: desc.mCoordinates,
+        }
+    }
+}
+
+impl ::std::convert::Into<ffi::AudioChannelDescription> for ChannelDescription {
+    fn into(self) -> ffi::AudioChannelDescription {
+        ffi::AudioChannelDescription {
+            mChannelLabel: self.label.into(),
+            mChannelFlags: self.flags.bits(),
+            mCoordinates: self.coordinates,
+        }
+    }
+}
+
+/// An owned `AudioChannelLayout`. Exactly one of `tag`, `bitmap` and
+/// `descriptions` says what the channels are, as chosen by `tag`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelLayout {
+    pub tag: ChannelLayoutTag,
+    /// Used when `tag` is `UseChannelBitmap`.
+    pub bitmap: ChannelBitmap,
+    /// Used when `tag` is `UseChannelDescriptions`. Units may also describe
+    /// tagged layouts here.
+    pub descriptions: Vec<ChannelDescription>,
+}
+
+impl ChannelLayout {
+    pub fn from_tag(tag: ChannelLayoutTag) -> ChannelLayout {
+        ChannelLayout {
+            tag,
+            bitmap: ChannelBitmap::empty(),
+            descriptions: Vec::new(),
+        }
+    }
+
+    pub fn from_bitmap(bitmap: ChannelBitmap) -> ChannelLayout {
+        ChannelLayout {
+            tag: ChannelLayoutTag::UseChannelBitmap,
+            bitmap,
+            descriptions: Vec::new(),
+        }
+    }
+
+    pub fn from_descriptions(descriptions: Vec<ChannelDescription>) -> ChannelLayout {
+        ChannelLayout {
+            tag: ChannelLayoutTag::UseChannelDescriptions,
+            bitmap: ChannelBitmap::empty(),
+            descriptions,
+        }
+    }
+
+    /// A layout of one channel per label, in order.
+    pub fn from_labels(labels: &[ChannelLabel]) -> ChannelLayout {
+        let descriptions = labels.iter().map(|&l| ChannelDescription::new(l)).collect();
+        ChannelLayout::from_descriptions(descriptions)
+    }
+
+    pub fn channel_count(&self) -> u32 {
+        match self.tag {
+            ChannelLayoutTag::UseChannelDescriptions => self.descriptions.len() as u32,
+            ChannelLayoutTag::UseChannelBitmap => self.bitmap.bits().count_ones(),
+            tag => tag.channel_count(),
+        }
+    }
+
+    /// A description of each channel, expanding a bitmap layout. Tagged
+    /// layouts only have descriptions if the unit supplied them.
+    pub fn channel_descriptions(&self) -> Vec<ChannelDescription> {
+        match self.tag {
+            ChannelLayoutTag::UseChannelBitmap => {
+                self.bitmap.labels().into_iter().map(ChannelDescription::new).collect()
+            },
+            _ => self.descriptions.clone(),
+        }
+    }
+
+    /// The fixed part of the C struct; the descriptions follow it.
+    fn header(&self) -> ffi::AudioChannelLayout {
+        ffi::AudioChannelLayout {
+            mChannelLayoutTag: self.tag.into(),
+            mChannelBitmap: self.bitmap.bits(),
+            mNumberChannelDescriptions: self.descriptions.len() as u32,
+            ..Default::default()
+        }
+    }
+}
+
+impl AudioUnitRef {
+    pub fn channel_layout(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<ChannelLayout> {
+        let (header, descriptions) = self.get_property_variable::<
+            ffi::AudioChannelLayout,
+            ffi::AudioChannelDescription,
+        >(ffi::kAudioUnitProperty_AudioChannelLayout, scope, element)?;
+        let count = header.mNumberChannelDescriptions as usize;
+        Ok(ChannelLayout {
+            tag: header.mChannelLayoutTag.into(),
+            bitmap: ChannelBitmap::from_bits_truncate(header.mChannelBitmap),
+            descriptions: descriptions.into_iter().take(count).map(Into::into).collect(),
+        })
+    }
+
+    /// Set the layout of an element's channels. The unit rejects layouts
+    /// whose channel count differs from the element's stream format.
+    pub fn set_channel_layout(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        layout: &ChannelLayout,
+    ) -> Result<()> {
+        let descriptions: Vec<ffi::AudioChannelDescription> =
+            layout.descriptions.iter().map(|&d| d.into()).collect();
+        self.set_property_variable(
+            ffi::kAudioUnitProperty_AudioChannelLayout,
+            scope,
+            element,
+            &layout.header(),
+            &descriptions,
+        )
+    }
+
+    /// The layout tags an element accepts.
+    pub fn supported_channel_layout_tags(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<Vec<ChannelLayoutTag>> {
+        let tags = self.get(props::SupportedChannelLayoutTags, scope, element)?;
+        Ok(tags.into_iter().map(ChannelLayoutTag::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitmaps_expand_to_labels_in_channel_order() {
+        let surround = ChannelBitmap::LEFT | ChannelBitmap::RIGHT |
+            ChannelBitmap::CENTER | ChannelBitmap::LFE_SCREEN |
+            ChannelBitmap::LEFT_SURROUND | ChannelBitmap::RIGHT_SURROUND;
+        assert_eq!(surround.labels(), vec![
+            ChannelLabel::Left,
+            ChannelLabel::Right,
+            ChannelLabel::Center,
+            ChannelLabel::LfeScreen,
+            ChannelLabel::LeftSurround,
+            ChannelLabel::RightSurround,
+        ]);
+        let height = ChannelBitmap::TOP_BACK_RIGHT | ChannelBitmap::LEFT;
+        assert_eq!(height.labels(), vec![ChannelLabel::Left, ChannelLabel::TopBackRight]);
+        assert_eq!(ChannelBitmap::empty().labels(), vec![]);
+
+        let layout = ChannelLayout::from_bitmap(surround);
+        assert_eq!(layout.channel_count(), 6);
+        assert_eq!(layout.channel_descriptions()[3], ChannelDescription::new(
+            ChannelLabel::LfeScreen,
+        ));
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        use ChannelLayoutTag::*;
+        let tags = [
+            UseChannelDescriptions, UseChannelBitmap, Mono, Stereo, StereoHeadphones,
+            MatrixStereo, MidSide, XY, Binaural, AmbisonicBFormat, Quadraphonic,
+            Pentagonal, Hexagonal, Octagonal, Cube, Mpeg3_0A, Mpeg3_0B, Mpeg4_0A,
+            Mpeg4_0B, Mpeg5_0A, Mpeg5_0B, Mpeg5_0C, Mpeg5_0D, Mpeg5_1A, Mpeg5_1B,
+            Mpeg5_1C, Mpeg5_1D, Mpeg6_1A, Mpeg7_1A, Mpeg7_1B, Mpeg7_1C, EmagicDefault7_1,
+            SmpteDtv, Itu2_1, Itu2_2, AudioUnit6_0, AudioUnit7_0, AudioUnit7_0Front,
+            DiscreteInOrder(0), DiscreteInOrder(12), Unknown(0), Unknown(6),
+            Other((200 << 16) | 3),
+        ];
+        for &tag in tags.iter() {
+            let ffi: ffi::AudioChannelLayoutTag = tag.into();
+            assert_eq!(ChannelLayoutTag::from(ffi), tag);
+        }
+        assert_eq!(Mpeg5_1A.channel_count(), 6);
+        assert_eq!(Mpeg7_1C.channel_count(), 8);
+        assert_eq!(UseChannelBitmap.channel_count(), 0);
+    }
+
+    #[test]
+    fn discrete_and_unknown_tags_pack_their_channel_count() {
+        use ChannelLayoutTag::*;
+        let discrete: ffi::AudioChannelLayoutTag = DiscreteInOrder(12).into();
+        assert_eq!(discrete, ffi::kAudioChannelLayoutTag_DiscreteInOrder | 12);
+        let unknown: ffi::AudioChannelLayoutTag = Unknown(6).into();
+        assert_eq!(unknown, ffi::kAudioChannelLayoutTag_Unknown | 6);
+        assert_eq!(ChannelLayoutTag::from(ffi::kAudioChannelLayoutTag_Unknown | 0xFFFF),
+                   Unknown(0xFFFF));
+        assert_eq!(DiscreteInOrder(12).channel_count(), 12);
+        assert_eq!(ChannelLayout::from_tag(Unknown(6)).channel_count(), 6);
+
+        // Only 16 bits of count fit in a tag.
+        let wide: ffi::AudioChannelLayoutTag = DiscreteInOrder(0x1_0002).into();
+        assert_eq!(ChannelLayoutTag::from(wide), DiscreteInOrder(2));
+    }
+
+    #[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+    mod sim {
+        use super::*;
+        use sim::new_unit;
+        use {AudioUnit, StreamFormat};
+
+        const INPUT: AudioUnitScope = AudioUnitScope::Input;
+
+        fn reverb_with_channels(channels: u32) -> AudioUnit {
+            let unit = new_unit(ffi::kAudioUnitType_Effect,
+                                ffi::kAudioUnitSubType_MatrixReverb);
+            let format = StreamFormat::float32(48000.0, channels).unwrap();
+            unit.set_stream_format(INPUT, 0, &format).unwrap();
+            unit
+        }
+
+        #[test]
+        fn surround_tags_round_trip() {
+            let tags = [(6, ChannelLayoutTag::Mpeg5_1A), (8, ChannelLayoutTag::Mpeg7_1C)];
+            for &(channels, tag) in tags.iter() {
+                let unit = reverb_with_channels(channels);
+                let supported = unit.supported_channel_layout_tags(INPUT, 0).unwrap();
+                assert!(supported.contains(&tag));
+                assert_eq!(unit.channel_layout(INPUT, 0).unwrap().tag,
+                           ChannelLayoutTag::Unknown(channels));
+                let layout = ChannelLayout::from_tag(tag);
+                unit.set_channel_layout(INPUT, 0, &layout).unwrap();
+                assert_eq!(unit.channel_layout(INPUT, 0).unwrap(), layout);
+            }
+        }
+
+        #[test]
+        fn layouts_must_match_the_stream_format() {
+            let unit = reverb_with_channels(2);
+            let layout = ChannelLayout::from_tag(ChannelLayoutTag::Mpeg5_1A);
+            assert!(unit.set_channel_layout(INPUT, 0, &layout).is_err());
+            assert_eq!(unit.channel_layout(INPUT, 0).unwrap().tag,
+                       ChannelLayoutTag::Stereo);
+        }
+
+        #[test]
+        fn bitmaps_and_descriptions_round_trip() {
+            let unit = reverb_with_channels(8);
+            let bitmap = ChannelLayout::from_bitmap(
+                ChannelBitmap::LEFT | ChannelBitmap::RIGHT | ChannelBitmap::CENTER |
+                    ChannelBitmap::LFE_SCREEN | ChannelBitmap::LEFT_SURROUND |
+                    ChannelBitmap::RIGHT_SURROUND | ChannelBitmap::LEFT_CENTER |
+                    ChannelBitmap::RIGHT_CENTER,
+            );
+            unit.set_channel_layout(INPUT, 0, &bitmap).unwrap();
+            assert_eq!(unit.channel_layout(INPUT, 0).unwrap(), bitmap);
+
+            // Every description past the first lives in the struct's tail.
+            let mut descriptions: Vec<_> = (0..7)
+                .map(|n| ChannelDescription::new(ChannelLabel::Discrete(n)))
+                .collect();
+            descriptions.push(ChannelDescription {
+                label: ChannelLabel::UseCoordinates,
+                flags: ChannelFlags::SPHERICAL_COORDINATES,
+                coordinates: [30.0, 15.0, 1.0],
+            });
+            let described = ChannelLayout::from_descriptions(descriptions);
+            unit.set_channel_layout(INPUT, 0, &described).unwrap();
+            assert_eq!(unit.channel_layout(INPUT, 0).unwrap(), described);
+        }
+    }
+}
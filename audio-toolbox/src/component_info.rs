@@ -0,0 +1,313 @@
+use {AudioComponent, AudioUnitScope, ChannelConfig, ChannelLayoutTag, Error, Result};
+use call;
+use cf;
+use ffi;
+use plist;
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+use std::{fmt, ptr};
+
+/// A component's name as `AudioComponentCopyName` gives it, such as
+/// `"Apple: AUDelay"`, split at the first colon.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComponentName {
+    /// Empty if the name has no manufacturer part.
+    pub manufacturer: String,
+    pub name: String,
+}
+
+impl ComponentName {
+    pub fn parse(full: &str) -> ComponentName {
+        let (manufacturer, name) = match full.find(':') {
+            Some(i) => (&full[..i], &full[i + 1..]),
+            None => ("", full),
+        };
+        ComponentName {
+            manufacturer: manufacturer.trim().to_owned(),
+            name: name.trim().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ComponentName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.manufacturer.is_empty() {
+            f.write_str(&self.name)
+        } else {
+            write!(f, "{}: {}", self.manufacturer, self.name)
+        }
+    }
+}
+
+/// A component's version, packed by AudioToolbox as `0xMMMMmmbb`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ComponentVersion {
+    pub major: u16,
+    pub minor: u8,
+    pub bugfix: u8,
+}
+
+impl ::std::convert::From<u32> for ComponentVersion {
+    fn from(version: u32) -> Self {
+        ComponentVersion {
+            major: (version >> 16) as u16,
+            minor: (version >> 8) as u8,
+            bugfix: version as u8,
+        }
+    }
+}
+
+impl ::std::convert::From<ComponentVersion> for u32 {
+    fn from(version: ComponentVersion) -> Self {
+        (version.major as u32) << 16 | (version.minor as u32) << 8 | version.bugfix as u32
+    }
+}
+
+impl fmt::Display for ComponentVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.bugfix)
+    }
+}
+
+/// What `AudioComponentCopyConfigurationInfo` reports about an audio unit,
+/// which AudioToolbox caches so that hosts needn't open the unit.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigurationInfo {
+    pub has_custom_view: bool,
+    /// The unit's `SupportedNumChannels`; empty if it doesn't publish any.
+    pub channel_configurations: Vec<ChannelConfig>,
+    /// The channel count of each input bus of a new instance.
+    pub initial_inputs: Vec<u32>,
+    /// The channel count of each output bus of a new instance.
+    pub initial_outputs: Vec<u32>,
+    /// The scopes whose element count can be set.
+    pub bus_count_writable: Vec<AudioUnitScope>,
+    pub input_layout_tags: Vec<ChannelLayoutTag>,
+    pub output_layout_tags: Vec<ChannelLayoutTag>,
+    /// Every other key, such as `ValidationResult`.
+    pub extra: BTreeMap<String, plist::Value>,
+}
+
+/// Keys held in their own `ConfigurationInfo` field rather than in `extra`.
+const FIELD_KEYS: &'static [&'static str] = &[
+    ffi::kAudioUnitConfigurationInfo_HasCustomView,
+    ffi::kAudioUnitConfigurationInfo_ChannelConfigurations,
+    ffi::kAudioUnitConfigurationInfo_InitialInputs,
+    ffi::kAudioUnitConfigurationInfo_InitialOutputs,
+    ffi::kAudioUnitConfigurationInfo_BusCountWritable,
+    ffi::kAudioUnitConfigurationInfo_SupportedChannelLayoutTags,
+];
+
+/// The integers in `value`, if it is an array. Other items are skipped.
+fn integers(value: Option<&plist::Value>) -> Vec<i64> {
+    value
+        .and_then(|value| value.as_array())
+        .map_or(Vec::new(), |items| items.iter().filter_map(|i| i.as_integer()).collect())
+}
+
+/// Booleans may also be stored as numbers.
+fn flag(value: &plist::Value) -> bool {
+    value.as_boolean().unwrap_or(value.as_integer().map_or(false, |i| i != 0))
+}
+
+impl ConfigurationInfo {
+    /// Read the configuration dictionary. Missing keys, and values of the
+    /// wrong type, are left at their defaults.
+    pub fn from_plist(value: &plist::Value) -> Result<ConfigurationInfo> {
+        use AudioUnitScope::{Global, Input, Output};
+        let dict = match value.as_dictionary() {
+            Some(dict) => dict,
+            None => return Err(Error::Status(ffi::kAudioUnitErr_InvalidPropertyValue)),
+        };
+        let get = |key: &str| dict.get(key);
+
+        let configs = get(ffi::kAudioUnitConfigurationInfo_ChannelConfigurations);
+        let channel_configurations = configs
+            .and_then(|value| value.as_array())
+            .map_or(Vec::new(), |configs| {
+                configs
+                    .iter()
+                    .map(|config| integers(Some(config)))
+                    .filter(|config| config.len() == 2)
+                    .map(|config| {
+                        ChannelConfig::from(ffi::AUChannelInfo {
+                            inChannels: config[0] as i16,
+                            outChannels: config[1] as i16,
+                        })
+                    })
+                    .collect()
+            });
+
+        let scopes = [Global, Input, Output];
+        let bus_count_writable = get(ffi::kAudioUnitConfigurationInfo_BusCountWritable)
+            .and_then(|value| value.as_array())
+            .map_or(Vec::new(), |writable| {
+                scopes
+                    .iter()
+                    .zip(writable)
+                    .filter(|&(_, writable)| flag(writable))
+                    .map(|(&scope, _)| scope)
+                    .collect()
+            });
+
+        let layouts = get(ffi::kAudioUnitConfigurationInfo_SupportedChannelLayoutTags)
+            .and_then(|value| value.as_dictionary());
+        let tags = |scope: &str| {
+            integers(layouts.and_then(|layouts| layouts.get(scope)))
+                .into_iter()
+                .map(|tag| ChannelLayoutTag::from(tag as ffi::AudioChannelLayoutTag))
+                .collect()
+        };
+        let channels = |key: &str| {
+            integers(get(key)).into_iter().map(|n| n as u32).collect()
+        };
+
+        Ok(ConfigurationInfo {
+            has_custom_view: get(ffi::kAudioUnitConfigurationInfo_HasCustomView)
+                .map_or(false, flag),
+            channel_configurations,
+            initial_inputs: channels(ffi::kAudioUnitConfigurationInfo_InitialInputs),
+            initial_outputs: channels(ffi::kAudioUnitConfigurationInfo_InitialOutputs),
+            bus_count_writable,
+            input_layout_tags: tags("Input"),
+            output_layout_tags: tags("Output"),
+            extra: dict.iter()
+                .filter(|&(k, _)| !FIELD_KEYS.contains(&k.as_str()))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        })
+    }
+}
+
+impl AudioComponent {
+    /// The component's name; both parts are empty if it has none.
+    pub fn name(&self) -> Result<ComponentName> {
+        let mut name = ptr::null();
+        unsafe {
+            call::cvt_r(ffi::AudioComponentCopyName(self.as_ptr(), &mut name))?;
+            if name.is_null() {
+                return Ok(ComponentName::parse(""));
+            }
+            let full = cf::string(name);
+            ffi::CFRelease(name as ffi::CFTypeRef);
+            Ok(ComponentName::parse(&full))
+        }
+    }
+
+    pub fn version(&self) -> Result<ComponentVersion> {
+        let mut version = 0u32;
+        unsafe {
+            call::cvt_r(ffi::AudioComponentGetVersion(self.as_ptr(), &mut version))?;
+        }
+        Ok(version.into())
+    }
+
+    /// The component's configuration, without opening it. The icon URL
+    /// isn't a property list value, so it is left out.
+    pub fn configuration_info(&self) -> Result<ConfigurationInfo> {
+        let mut dict = ptr::null();
+        unsafe {
+            call::cvt_r(ffi::AudioComponentCopyConfigurationInfo(
+                self.as_ptr(),
+                &mut dict,
+            ))?;
+            let copy = ffi::CFDictionaryCreateMutableCopy(ptr::null(), 0, dict);
+            ffi::CFRelease(dict as ffi::CFTypeRef);
+            let key = cf::create_string(ffi::kAudioUnitConfigurationInfo_IconURL);
+            ffi::CFDictionaryRemoveValue(copy, key as *const c_void);
+            ffi::CFRelease(key as ffi::CFTypeRef);
+            let value = cf::property_list(copy as ffi::CFPropertyListRef);
+            ffi::CFRelease(copy as ffi::CFTypeRef);
+            ConfigurationInfo::from_plist(&value?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ChannelCount;
+    use plist::Value::{Array, Boolean, Dictionary, Integer};
+
+    #[test]
+    fn name_splits_at_first_colon() {
+        let name = ComponentName::parse("Acme Audio: Delay: Tape");
+        assert_eq!(name.manufacturer, "Acme Audio");
+        assert_eq!(name.name, "Delay: Tape");
+        assert_eq!(ComponentName::parse("Delay").to_string(), "Delay");
+    }
+
+    #[test]
+    fn version_round_trips() {
+        let version = ComponentVersion::from(0x0002_0a03);
+        assert_eq!((version.major, version.minor, version.bugfix), (2, 10, 3));
+        assert_eq!(version.to_string(), "2.10.3");
+        assert_eq!(u32::from(version), 0x0002_0a03);
+    }
+
+    #[test]
+    fn configuration_info_reads_known_keys() {
+        let mut dict = BTreeMap::new();
+        let mut insert = |key: &str, value| dict.insert(key.to_owned(), value);
+        insert(ffi::kAudioUnitConfigurationInfo_HasCustomView, Integer(1));
+        insert(
+            ffi::kAudioUnitConfigurationInfo_ChannelConfigurations,
+            Array(vec![Array(vec![Integer(1), Integer(2)]), Array(vec![Integer(-1)])]),
+        );
+        insert(ffi::kAudioUnitConfigurationInfo_InitialOutputs, Array(vec![Integer(2)]));
+        insert(
+            ffi::kAudioUnitConfigurationInfo_BusCountWritable,
+            Array(vec![Boolean(false), Boolean(true), Boolean(true)]),
+        );
+        let mut layouts = BTreeMap::new();
+        layouts.insert("Output".to_owned(), Array(vec![Integer(6619138)]));
+        insert(
+            ffi::kAudioUnitConfigurationInfo_SupportedChannelLayoutTags,
+            Dictionary(layouts),
+        );
+        insert("ValidationResult", Integer(1));
+
+        let info = ConfigurationInfo::from_plist(&Dictionary(dict)).unwrap();
+        assert!(info.has_custom_view);
+        assert_eq!(info.channel_configurations, vec![ChannelConfig::Counts {
+            inputs: ChannelCount::Exactly(1),
+            outputs: ChannelCount::Exactly(2),
+        }]);
+        assert!(info.initial_inputs.is_empty());
+        assert_eq!(info.initial_outputs, vec![2]);
+        assert_eq!(
+            info.bus_count_writable,
+            vec![AudioUnitScope::Input, AudioUnitScope::Output]
+        );
+        assert!(info.input_layout_tags.is_empty());
+        assert_eq!(info.output_layout_tags, vec![ChannelLayoutTag::Stereo]);
+        assert_eq!(info.extra.keys().collect::<Vec<_>>(), vec!["ValidationResult"]);
+        assert!(ConfigurationInfo::from_plist(&Integer(0)).is_err());
+    }
+
+    #[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+    mod sim {
+        use super::*;
+        use {AudioComponentQuery, AudioUnitSubType};
+
+        #[test]
+        fn components_describe_themselves() {
+            let reverb = AudioComponentQuery::new()
+                .sub_kind(AudioUnitSubType::MatrixReverb)
+                .first()
+                .unwrap();
+            assert_eq!(reverb.name().unwrap().to_string(), "Apple: AUMatrixReverb");
+
+            let info = reverb.configuration_info().unwrap();
+            let exactly = |inputs, outputs| ChannelConfig::Counts {
+                inputs: ChannelCount::Exactly(inputs),
+                outputs: ChannelCount::Exactly(outputs),
+            };
+            assert_eq!(info.channel_configurations,
+                       vec![exactly(1, 1), exactly(1, 2), exactly(2, 2)]);
+            assert!(info.channel_configurations.iter().any(|c| c.supports(1, 2)));
+            assert!(info.input_layout_tags.contains(&ChannelLayoutTag::Stereo));
+            assert!(info.output_layout_tags.contains(&ChannelLayoutTag::Mpeg5_1A));
+        }
+    }
+}
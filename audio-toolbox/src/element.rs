@@ -0,0 +1,139 @@
+use {AudioUnitElement, AudioUnitRef, AudioUnitScope, Error, Result};
+use cf;
+use ffi;
+use props;
+use std::ops;
+
+/// The elements (buses) of one scope, as returned by `AudioUnitRef::buses`.
+#[derive(Clone, Debug)]
+pub struct Buses {
+    scope: AudioUnitScope,
+    elements: ops::Range<AudioUnitElement>,
+}
+
+impl Buses {
+    pub fn scope(&self) -> AudioUnitScope {
+        self.scope
+    }
+}
+
+impl Iterator for Buses {
+    type Item = AudioUnitElement;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.elements.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Buses {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.elements.next_back()
+    }
+}
+
+impl ExactSizeIterator for Buses {}
+
+impl AudioUnitRef {
+    /// The number of elements in `scope`.
+    pub fn element_count(&self, scope: AudioUnitScope) -> Result<u32> {
+        self.get(props::ElementCount, scope, 0)
+    }
+
+    /// Resize `scope`, such as a mixer's input scope. Most units only allow
+    /// this before they are initialized.
+    pub fn set_element_count(&self, scope: AudioUnitScope, count: u32) -> Result<()> {
+        self.set(props::ElementCount, scope, 0, &count)
+    }
+
+    /// The elements currently in `scope`.
+    pub fn buses(&self, scope: AudioUnitScope) -> Result<Buses> {
+        Ok(Buses {
+            scope: scope,
+            elements: 0..self.element_count(scope)?,
+        })
+    }
+
+    /// The element's name, or `None` if it hasn't been given one.
+    pub fn element_name(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<Option<String>> {
+        let name = match self.get(props::ElementName, scope, element) {
+            Ok(name) => name,
+            Err(Error::Status(ffi::kAudioUnitErr_PropertyNotInUse)) |
+            Err(Error::Status(ffi::kAudioUnitErr_InvalidProperty)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if name.is_null() {
+            return Ok(None);
+        }
+        unsafe {
+            let s = cf::string(name);
+            ffi::CFRelease(name as ffi::CFTypeRef);
+            Ok(Some(s))
+        }
+    }
+
+    pub fn set_element_name(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        name: &str,
+    ) -> Result<()> {
+        let name = cf::create_string(name);
+        let result = self.set(props::ElementName, scope, element, &name);
+        unsafe { ffi::CFRelease(name as ffi::CFTypeRef) };
+        result
+    }
+}
+
+#[cfg(all(test, any(feature = "sim", not(target_vendor = "apple"))))]
+mod tests {
+    use super::*;
+    use sim::new_unit;
+
+    const INPUT: AudioUnitScope = AudioUnitScope::Input;
+    const OUTPUT: AudioUnitScope = AudioUnitScope::Output;
+
+    #[test]
+    fn mixers_resize_their_inputs_until_initialized() {
+        let mixer = new_unit(ffi::kAudioUnitType_Mixer,
+                             ffi::kAudioUnitSubType_MultiChannelMixer);
+        assert_eq!(mixer.element_count(INPUT).unwrap(), 8);
+        mixer.set_element_count(INPUT, 3).unwrap();
+        assert_eq!(mixer.element_count(INPUT).unwrap(), 3);
+        assert_eq!(mixer.buses(INPUT).unwrap().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(mixer.set_element_count(OUTPUT, 2).is_err());
+
+        mixer.initialize().unwrap();
+        assert!(mixer.set_element_count(INPUT, 4).is_err());
+        assert_eq!(mixer.element_count(INPUT).unwrap(), 3);
+    }
+
+    #[test]
+    fn effects_have_fixed_buses() {
+        let eq = new_unit(ffi::kAudioUnitType_Effect, ffi::kAudioUnitSubType_NBandEQ);
+        assert_eq!(eq.buses(OUTPUT).unwrap().len(), 1);
+        assert!(eq.set_element_count(INPUT, 2).is_err());
+    }
+
+    #[test]
+    fn elements_can_be_named() {
+        let mixer = new_unit(ffi::kAudioUnitType_Mixer,
+                             ffi::kAudioUnitSubType_MultiChannelMixer);
+        assert_eq!(mixer.element_name(INPUT, 1).unwrap(), None);
+        mixer.set_element_name(INPUT, 1, "Drums – Overheads").unwrap();
+        assert_eq!(mixer.element_name(INPUT, 1).unwrap(),
+                   Some("Drums – Overheads".to_owned()));
+        assert_eq!(mixer.element_name(INPUT, 0).unwrap(), None);
+
+        mixer.set_element_name(INPUT, 1, "Bass").unwrap();
+        assert_eq!(mixer.element_name(INPUT, 1).unwrap(), Some("Bass".to_owned()));
+        assert!(mixer.set_element_name(INPUT, 8, "Missing").is_err());
+    }
+}
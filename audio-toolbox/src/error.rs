@@ -0,0 +1,76 @@
+use {AudioUnitProperty, PresetError, StreamFormatError};
+use plist::PlistError;
+use ffi;
+use std::{error, fmt, result};
+
+/// Errors from this crate.
+///
+/// Earlier versions re-exported `core_audio::Error` under this name. Code
+/// that matched on that type, or converted it with `?`, needs updating;
+/// `Error::Status` and `osstatus` give the `OSStatus` it used to carry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// An AudioToolbox call returned a failing `OSStatus`.
+    Status(ffi::OSStatus),
+    /// The unit reported a size for `property` that doesn't match the Rust
+    /// type (or, for arrays, element type) it is being read as.
+    PropertySize {
+        property: AudioUnitProperty,
+        expected: usize,
+        actual: usize,
+    },
+    /// A `StreamFormat` failed validation before being sent to the unit.
+    StreamFormat(StreamFormatError),
+    Plist(PlistError),
+    Preset(PresetError),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    pub fn from_osstatus(status: ffi::OSStatus) -> Error {
+        Error::Status(status)
+    }
+
+    /// The `OSStatus` behind this error, if it came from AudioToolbox.
+    pub fn osstatus(&self) -> Option<ffi::OSStatus> {
+        match *self {
+            Error::Status(status) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Status(status) => write!(f, "AudioToolbox error {}", status),
+            Error::PropertySize {
+                property,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "property {} has size {}, expected {}",
+                property,
+                actual,
+                expected
+            ),
+            Error::StreamFormat(ref e) => write!(f, "invalid stream format: {}", e),
+            Error::Plist(ref e) => e.fmt(f),
+            Error::Preset(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Status(_) => "AudioToolbox error",
+            Error::PropertySize { .. } => "property size mismatch",
+            Error::StreamFormat(_) => "invalid stream format",
+            Error::Plist(_) => "malformed property list",
+            Error::Preset(_) => "invalid preset",
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! The raw AudioToolbox API used by the safe wrappers.
+//!
+//! On Apple targets this is `audio_toolbox_sys` unchanged. Everywhere else
+//! (or with the `sim` feature) the framework entry points are replaced by
+//! the in-process simulator in `sim`, so the safe API builds and runs
+//! without AudioToolbox being present.
+
+pub use audio_toolbox_sys::*;
+
+#[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+pub use sim::{AUParameterListenerNotify, AudioComponentCopyConfigurationInfo,
+              AudioComponentCopyName, AudioComponentCount, AudioComponentFindNext,
+              AudioComponentGetDescription, AudioComponentGetVersion,
+              AudioComponentInstanceDispose, AudioComponentInstanceNew,
+              AudioComponentValidate,
+              AudioOutputUnitStart, AudioOutputUnitStop,
+              AudioUnitAddPropertyListener, AudioUnitAddRenderNotify,
+              AudioUnitGetParameter, AudioUnitGetProperty, AudioUnitGetPropertyInfo,
+              AudioUnitInitialize, AudioUnitProcess,
+              AudioUnitRemovePropertyListenerWithUserData, AudioUnitRemoveRenderNotify,
+              AudioUnitRender, AudioUnitReset,
+              AudioUnitScheduleParameters, AudioUnitSetParameter, AudioUnitSetProperty,
+              AudioUnitUninitialize, CFArrayGetCount, CFArrayGetValueAtIndex,
+              CFDataCreate, CFDataGetBytePtr, CFDataGetLength,
+              CFDictionaryCreateMutableCopy, CFDictionaryRemoveValue,
+              CFPropertyListCreateData, CFPropertyListCreateWithData, CFRelease,
+              CFStringCreateWithBytes,
+              CFStringGetCString, CFStringGetLength, CFStringGetMaximumSizeForEncoding};
@@ -0,0 +1,252 @@
+use ffi;
+use std::{error, fmt};
+use std::str::FromStr;
+
+/// A four character code, the `OSType` that AudioToolbox uses for component
+/// types, subtypes and manufacturers.
+///
+/// Displayed as the characters in single quotes (`'aufx'`), or in hex if
+/// any of them isn't printable ASCII. Parsed from either form, as well as
+/// the bare characters; see `from_str` for how the two are told apart.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FourCC(pub ffi::OSType);
+
+impl FourCC {
+    /// `FourCC::new(b"aufx")`.
+    pub const fn new(code: &[u8; 4]) -> FourCC {
+        FourCC(
+            (code[0] as u32) << 24 | (code[1] as u32) << 16 | (code[2] as u32) << 8 |
+                code[3] as u32,
+        )
+    }
+
+    pub fn as_u32(&self) -> ffi::OSType {
+        self.0
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        [(self.0 >> 24) as u8, (self.0 >> 16) as u8, (self.0 >> 8) as u8, self.0 as u8]
+    }
+
+    /// Whether all four characters are printable ASCII.
+    pub fn is_printable(&self) -> bool {
+        self.to_bytes().iter().all(|&b| b >= 0x20 && b < 0x7f)
+    }
+
+    /// The four characters, if they are printable.
+    fn chars(&self) -> Option<String> {
+        if self.is_printable() {
+            Some(self.to_bytes().iter().map(|&b| b as char).collect())
+        } else {
+            None
+        }
+    }
+}
+
+impl ::std::convert::From<ffi::OSType> for FourCC {
+    fn from(code: ffi::OSType) -> Self {
+        FourCC(code)
+    }
+}
+
+impl<'a> ::std::convert::From<&'a [u8; 4]> for FourCC {
+    fn from(code: &'a [u8; 4]) -> Self {
+        FourCC::new(code)
+    }
+}
+
+impl ::std::convert::From<FourCC> for ffi::OSType {
+    fn from(code: FourCC) -> Self {
+        code.0
+    }
+}
+
+impl fmt::Display for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.chars() {
+            Some(chars) => write!(f, "'{}'", chars),
+            None => write!(f, "0x{:08x}", self.0),
+        }
+    }
+}
+
+impl fmt::Debug for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FourCC({})", self)
+    }
+}
+
+/// Why a string isn't a four character code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseFourCCError;
+
+impl fmt::Display for ParseFourCCError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("expected four ASCII characters or a 0x-prefixed 32-bit hex number")
+    }
+}
+
+impl error::Error for ParseFourCCError {
+    fn description(&self) -> &str {
+        "invalid four character code"
+    }
+}
+
+impl FromStr for FourCC {
+    type Err = ParseFourCCError;
+
+    /// Parse `aufx`, `'aufx'` or `0x61756678`.
+    ///
+    /// Exactly four ASCII characters, optionally in single quotes, are
+    /// always the characters of the code, so `0x12` is `'0x12'`. Anything
+    /// else must be `0x` followed by one to eight hex digits.
+    fn from_str(s: &str) -> Result<FourCC, ParseFourCCError> {
+        let chars = if s.len() == 6 && s.starts_with('\'') && s.ends_with('\'') {
+            &s[1..5]
+        } else {
+            s
+        };
+        let bytes = chars.as_bytes();
+        if bytes.len() == 4 && chars.is_ascii() {
+            return Ok(FourCC::new(&[bytes[0], bytes[1], bytes[2], bytes[3]]));
+        }
+        let digits = if s.starts_with("0x") || s.starts_with("0X") {
+            &s[2..]
+        } else {
+            return Err(ParseFourCCError);
+        };
+        // `from_str_radix` would also take a sign.
+        if digits.is_empty() || digits.len() > 8 ||
+            !digits.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Err(ParseFourCCError);
+        }
+        u32::from_str_radix(digits, 16).map(FourCC).map_err(|_| ParseFourCCError)
+    }
+}
+
+// Human-readable formats get a string: the bare characters (`"aufx"`) when
+// they are printable, otherwise `"0x61756678"`, read back through
+// `FromStr`. Other formats get the number.
+#[cfg(feature = "serde")]
+mod serialization {
+    use super::FourCC;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+
+    impl Serialize for FourCC {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if !serializer.is_human_readable() {
+                return serializer.serialize_u32(self.0);
+            }
+            match self.chars() {
+                Some(chars) => serializer.serialize_str(&chars),
+                None => serializer.serialize_str(&format!("0x{:08x}", self.0)),
+            }
+        }
+    }
+
+    struct FourCCVisitor;
+
+    impl<'de> de::Visitor<'de> for FourCCVisitor {
+        type Value = FourCC;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a four character code")
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<FourCC, E> {
+            s.parse().map_err(|_| E::invalid_value(de::Unexpected::Str(s), &self))
+        }
+
+        fn visit_u32<E: de::Error>(self, n: u32) -> Result<FourCC, E> {
+            Ok(FourCC(n))
+        }
+
+        fn visit_u64<E: de::Error>(self, n: u64) -> Result<FourCC, E> {
+            if n > u32::max_value() as u64 {
+                return Err(E::invalid_value(de::Unexpected::Unsigned(n), &self));
+            }
+            Ok(FourCC(n as u32))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for FourCC {
+        fn deserialize<D>(deserializer: D) -> Result<FourCC, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            if deserializer.is_human_readable() {
+                deserializer.deserialize_str(FourCCVisitor)
+            } else {
+                deserializer.deserialize_u32(FourCCVisitor)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_characters_or_hex() {
+        assert_eq!(FourCC::new(b"aufx").to_string(), "'aufx'");
+        assert_eq!(FourCC::new(b"0x12").to_string(), "'0x12'");
+        assert_eq!(FourCC(0x0000_0001).to_string(), "0x00000001");
+        assert_eq!(FourCC(0x6175_667f).to_string(), "0x6175667f");
+        assert_eq!(format!("{:?}", FourCC::new(b"appl")), "FourCC('appl')");
+    }
+
+    #[test]
+    fn parses_characters_then_hex() {
+        let aufx = FourCC::new(b"aufx");
+        assert_eq!("aufx".parse(), Ok(aufx));
+        assert_eq!("'aufx'".parse(), Ok(aufx));
+        assert_eq!("0x61756678".parse(), Ok(aufx));
+        assert_eq!("0X61756678".parse(), Ok(aufx));
+        assert_eq!("0x1".parse(), Ok(FourCC(1)));
+        // Four characters are never a number, quoted or not.
+        assert_eq!("0x12".parse(), Ok(FourCC::new(b"0x12")));
+        assert_eq!("'0x12'".parse(), Ok(FourCC::new(b"0x12")));
+        assert_eq!("0x12345".parse(), Ok(FourCC(0x12345)));
+        assert_eq!("'ab'".parse(), Ok(FourCC::new(b"'ab'")));
+
+        for bad in &["", "abc", "abcde", "'abc'", "0x", "0x123456789", "0x+1234",
+                     "0xzzzzz", "12345", "\u{e9}tre"] {
+            assert_eq!(bad.parse::<FourCC>(), Err(ParseFourCCError), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn display_round_trips_through_from_str() {
+        for &code in &[FourCC::new(b"aumu"), FourCC::new(b"0x12"), FourCC(7),
+                       FourCC(0xffff_ffff)] {
+            assert_eq!(code.to_string().parse(), Ok(code));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    mod serialization {
+        use super::*;
+        use serde_test::{assert_de_tokens, assert_de_tokens_error, assert_tokens,
+                         Configure, Token};
+
+        #[test]
+        fn readable_formats_use_strings() {
+            assert_tokens(&FourCC::new(b"aufx").readable(), &[Token::Str("aufx")]);
+            assert_tokens(&FourCC(1).readable(), &[Token::Str("0x00000001")]);
+            assert_de_tokens(&FourCC::new(b"aufx").readable(), &[Token::Str("'aufx'")]);
+            assert_de_tokens_error::<::serde_test::Readable<FourCC>>(
+                &[Token::Str("aufxx")],
+                "invalid value: string \"aufxx\", expected a four character code",
+            );
+        }
+
+        #[test]
+        fn compact_formats_use_numbers() {
+            assert_tokens(&FourCC::new(b"aufx").compact(), &[Token::U32(0x6175_6678)]);
+            assert_tokens(&FourCC(1).compact(), &[Token::U32(1)]);
+        }
+    }
+}
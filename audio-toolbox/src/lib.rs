@@ -3,20 +3,58 @@ extern crate audio_toolbox_sys;
 extern crate bitflags;
 extern crate core_audio;
 extern crate core_foundation;
+#[macro_use]
+extern crate lazy_static;
 extern crate libc;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
 
 #[macro_use]
 mod ffi_types;
 
+mod backend;
 mod call;
+mod cf;
+mod channel_config;
+mod channel_layout;
+mod component_info;
+mod ffi;
+#[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+mod sim;
 mod audio_component;
+mod automation;
+mod element;
+mod error;
+mod four_cc;
 mod audio_unit;
 mod audio_output_unit;
 mod panic;
+mod parameter;
+pub mod plist;
+mod preset;
+pub mod props;
+mod stream_format;
 mod util;
+mod validation;
 
 pub use audio_component::*;
+pub use automation::*;
+pub use backend::{AudioUnitBackend, FfiBackend};
+pub use channel_config::*;
+pub use channel_layout::*;
+pub use component_info::*;
+pub use element::*;
+pub use error::{Error, Result};
+pub use four_cc::*;
 pub use audio_output_unit::*;
-pub use audio_toolbox_sys::OSType;
+pub use audio_toolbox_sys::{OSStatus, OSType};
 pub use audio_unit::*;
+pub use panic::{callback_panic_message, callback_panicked, set_callback_panic_status,
+                take_callback_panic};
+pub use parameter::*;
+pub use preset::*;
+pub use stream_format::*;
+pub use validation::*;
 pub use core_audio::*;
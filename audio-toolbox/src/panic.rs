@@ -1,36 +1,132 @@
+//! Panics in the closures AudioToolbox calls back into can't unwind into C.
+//! They are caught, the callback reports a failing status instead, and the
+//! first payload is kept for the host to inspect. Callbacks usually run on
+//! AudioToolbox's threads, so the payload is kept process-wide.
+
+use ffi;
 use std::any::Any;
-use std::cell::RefCell;
-use std::panic::UnwindSafe;
+use std::panic::{self, UnwindSafe};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+lazy_static! {
+    static ref LAST_PANIC: Mutex<Option<Box<Any + Send>>> = Mutex::new(None);
+}
 
-thread_local!(static LAST_ERROR: RefCell<Option<Box<Any + Send>>> = {
-    RefCell::new(None)
-});
+static PANIC_STATUS: AtomicIsize =
+    AtomicIsize::new(ffi::kAudioUnitErr_RenderTimeout as isize);
 
+/// Run `f`, catching a panic. Returns `None` if it panicked, after
+/// recording the payload unless an earlier one is still waiting to be
+/// taken.
 pub fn wrap<T, F: FnOnce() -> T + UnwindSafe>(f: F) -> Option<T> {
-    use std::panic;
-    if LAST_ERROR.with(|slot| slot.borrow().is_some()) {
-        return None;
-    }
     match panic::catch_unwind(f) {
         Ok(ret) => Some(ret),
         Err(e) => {
-            LAST_ERROR.with(move |slot| {
-                                *slot.borrow_mut() = Some(e);
-                            });
+            if let Ok(mut slot) = LAST_PANIC.lock() {
+                if slot.is_none() {
+                    *slot = Some(e);
+                }
+            }
             None
         },
     }
 }
 
-/*
-pub fn check() {
-    let err = LAST_ERROR.with(|slot| slot.borrow_mut().take());
-    if let Some(err) = err {
-        panic!(err)
+/// The status a render callback returns when its closure panics.
+pub fn status() -> ffi::OSStatus {
+    PANIC_STATUS.load(Ordering::Relaxed) as ffi::OSStatus
+}
+
+/// Make render callbacks whose closure panics return `status`, rather than
+/// the default `kAudioUnitErr_RenderTimeout`. The setting applies to every
+/// unit in the process.
+pub fn set_callback_panic_status(status: ffi::OSStatus) {
+    PANIC_STATUS.store(status as isize, Ordering::Relaxed);
+}
+
+/// Whether a callback closure of any unit, on any thread, has panicked
+/// since the payload was last taken.
+pub fn callback_panicked() -> bool {
+    LAST_PANIC.lock().map(|slot| slot.is_some()).unwrap_or(false)
+}
+
+/// The message of the recorded panic, if it had one.
+pub fn callback_panic_message() -> Option<String> {
+    let slot = LAST_PANIC.lock().ok()?;
+    let payload = slot.as_ref()?;
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        Some(s.to_string())
+    } else {
+        payload.downcast_ref::<String>().cloned()
     }
 }
 
-pub fn panicked() -> bool {
-    LAST_ERROR.with(|slot| slot.borrow().is_some())
+/// Take the payload of the first callback panic, clearing it so the next
+/// panic is recorded.
+///
+/// There is a single slot for the whole process, behind a `Mutex`, rather
+/// than one per thread as before: callbacks run on AudioToolbox's threads,
+/// so a per-thread payload was never seen by the host. With several units
+/// the payload may come from any of them, and whichever thread takes it
+/// first clears it for all.
+pub fn take_callback_panic() -> Option<Box<Any + Send>> {
+    LAST_PANIC.lock().ok().and_then(|mut slot| slot.take())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::sync::MutexGuard;
+
+    lazy_static! {
+        static ref STATE: Mutex<()> = Mutex::new(());
+    }
+
+    /// Hold while a test panics in callbacks or changes the panic status,
+    /// which every test in the process shares.
+    pub fn lock() -> MutexGuard<'static, ()> {
+        STATE.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn the_first_payload_is_kept_until_taken() {
+        let _lock = lock();
+        take_callback_panic();
+        assert_eq!(wrap(|| 5), Some(5));
+        assert!(!callback_panicked());
+
+        assert_eq!(wrap(|| panic!("first")), None::<()>);
+        assert_eq!(wrap(|| panic!("{}", "second")), None::<()>);
+        assert!(callback_panicked());
+        assert_eq!(callback_panic_message(), Some("first".to_owned()));
+        let payload = take_callback_panic().unwrap();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"first"));
+        assert!(!callback_panicked());
+        assert_eq!(callback_panic_message(), None);
+
+        // Formatted messages are `String`s.
+        assert_eq!(wrap(|| panic!("number {}", 3)), None::<()>);
+        assert_eq!(callback_panic_message(), Some("number 3".to_owned()));
+        assert!(take_callback_panic().is_some());
+        assert!(take_callback_panic().is_none());
+    }
+
+    #[test]
+    fn panics_from_other_threads_are_seen() {
+        let _lock = lock();
+        take_callback_panic();
+        ::std::thread::spawn(|| wrap(|| panic!("elsewhere"))).join().unwrap();
+        assert_eq!(callback_panic_message(), Some("elsewhere".to_owned()));
+        assert!(take_callback_panic().is_some());
+    }
+
+    #[test]
+    fn the_status_is_configurable() {
+        let _lock = lock();
+        assert_eq!(status(), ffi::kAudioUnitErr_RenderTimeout);
+        set_callback_panic_status(ffi::kAudioUnitErr_CannotDoInCurrentContext);
+        assert_eq!(status(), ffi::kAudioUnitErr_CannotDoInCurrentContext);
+        set_callback_panic_status(ffi::kAudioUnitErr_RenderTimeout);
+    }
 }
-*/
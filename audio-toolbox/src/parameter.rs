@@ -0,0 +1,393 @@
+use {AudioUnitElement, AudioUnitParameter, AudioUnitRef, AudioUnitScope, Result};
+use cf;
+use ffi;
+use props;
+use std::ffi::CStr;
+use std::ptr;
+
+/// What a parameter's value measures, from `kAudioUnitParameterUnit_*`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterUnit {
+    Generic,
+    Indexed,
+    Boolean,
+    Percent,
+    Seconds,
+    SampleFrames,
+    Phase,
+    Rate,
+    Hertz,
+    Cents,
+    RelativeSemiTones,
+    MIDINoteNumber,
+    MIDIController,
+    Decibels,
+    LinearGain,
+    Degrees,
+    EqualPowerCrossfade,
+    MixerFaderCurve1,
+    Pan,
+    Meters,
+    AbsoluteCents,
+    Octaves,
+    BPM,
+    Beats,
+    Milliseconds,
+    Ratio,
+    CustomUnit,
+    /// A unit this crate doesn't know about.
+    Other(ffi::AudioUnitParameterUnit),
+}
+
+impl ::std::convert::From<ffi::AudioUnitParameterUnit> for ParameterUnit {
+    fn from(ffi: ffi::AudioUnitParameterUnit) -> Self {
+        use ParameterUnit::*;
+        match ffi {
+            ffi::kAudioUnitParameterUnit_Generic => Generic,
+            ffi::kAudioUnitParameterUnit_Indexed => Indexed,
+            ffi::kAudioUnitParameterUnit_Boolean => Boolean,
+            ffi::kAudioUnitParameterUnit_Percent => Percent,
+            ffi::kAudioUnitParameterUnit_Seconds => Seconds,
+            ffi::kAudioUnitParameterUnit_SampleFrames => SampleFrames,
+            ffi::kAudioUnitParameterUnit_Phase => Phase,
+            ffi::kAudioUnitParameterUnit_Rate => Rate,
+            ffi::kAudioUnitParameterUnit_Hertz => Hertz,
+            ffi::kAudioUnitParameterUnit_Cents => Cents,
+            ffi::kAudioUnitParameterUnit_RelativeSemiTones => RelativeSemiTones,
+            ffi::kAudioUnitParameterUnit_MIDINoteNumber => MIDINoteNumber,
+            ffi::kAudioUnitParameterUnit_MIDIController => MIDIController,
+            ffi::kAudioUnitParameterUnit_Decibels => Decibels,
+            ffi::kAudioUnitParameterUnit_LinearGain => LinearGain,
+            ffi::kAudioUnitParameterUnit_Degrees => Degrees,
+            ffi::kAudioUnitParameterUnit_EqualPowerCrossfade => EqualPowerCrossfade,
+            ffi::kAudioUnitParameterUnit_MixerFaderCurve1 => MixerFaderCurve1,
+            ffi::kAudioUnitParameterUnit_Pan => Pan,
+            ffi::kAudioUnitParameterUnit_Meters => Meters,
+            ffi::kAudioUnitParameterUnit_AbsoluteCents => AbsoluteCents,
+            ffi::kAudioUnitParameterUnit_Octaves => Octaves,
+            ffi::kAudioUnitParameterUnit_BPM => BPM,
+            ffi::kAudioUnitParameterUnit_Beats => Beats,
+            ffi::kAudioUnitParameterUnit_Milliseconds => Milliseconds,
+            ffi::kAudioUnitParameterUnit_Ratio => Ratio,
+            ffi::kAudioUnitParameterUnit_CustomUnit => CustomUnit,
+            other => Other(other),
+        }
+    }
+}
+
+impl ::std::convert::Into<ffi::AudioUnitParameterUnit> for ParameterUnit {
+    fn into(self) -> ffi::AudioUnitParameterUnit {
+        use ParameterUnit::*;
+        match self {
+            Generic => ffi::kAudioUnitParameterUnit_Generic,
+            Indexed => ffi::kAudioUnitParameterUnit_Indexed,
+            Boolean => ffi::kAudioUnitParameterUnit_Boolean,
+            Percent => ffi::kAudioUnitParameterUnit_Percent,
+            Seconds => ffi::kAudioUnitParameterUnit_Seconds,
+            SampleFrames => ffi::kAudioUnitParameterUnit_SampleFrames,
+            Phase => ffi::kAudioUnitParameterUnit_Phase,
+            Rate => ffi::kAudioUnitParameterUnit_Rate,
+            Hertz => ffi::kAudioUnitParameterUnit_Hertz,
+            Cents => ffi::kAudioUnitParameterUnit_Cents,
+            RelativeSemiTones => ffi::kAudioUnitParameterUnit_RelativeSemiTones,
+            MIDINoteNumber => ffi::kAudioUnitParameterUnit_MIDINoteNumber,
+            MIDIController => ffi::kAudioUnitParameterUnit_MIDIController,
+            Decibels => ffi::kAudioUnitParameterUnit_Decibels,
+            LinearGain => ffi::kAudioUnitParameterUnit_LinearGain,
+            Degrees => ffi::kAudioUnitParameterUnit_Degrees,
+            EqualPowerCrossfade => ffi::kAudioUnitParameterUnit_EqualPowerCrossfade,
+            MixerFaderCurve1 => ffi::kAudioUnitParameterUnit_MixerFaderCurve1,
+            Pan => ffi::kAudioUnitParameterUnit_Pan,
+            Meters => ffi::kAudioUnitParameterUnit_Meters,
+            AbsoluteCents => ffi::kAudioUnitParameterUnit_AbsoluteCents,
+            Octaves => ffi::kAudioUnitParameterUnit_Octaves,
+            BPM => ffi::kAudioUnitParameterUnit_BPM,
+            Beats => ffi::kAudioUnitParameterUnit_Beats,
+            Milliseconds => ffi::kAudioUnitParameterUnit_Milliseconds,
+            Ratio => ffi::kAudioUnitParameterUnit_Ratio,
+            CustomUnit => ffi::kAudioUnitParameterUnit_CustomUnit,
+            Other(unit) => unit,
+        }
+    }
+}
+
+bitflags! {
+    /// `kAudioUnitParameterFlag_*`. The `DISPLAY_*` curves aren't
+    /// independent bits: compare `flags & DISPLAY_MASK` against them.
+    pub struct ParameterFlags: u32 {
+        const CF_NAME_RELEASE = ffi::kAudioUnitParameterFlag_CFNameRelease;
+        const OMIT_FROM_PRESETS = ffi::kAudioUnitParameterFlag_OmitFromPresets;
+        const PLOT_HISTORY = ffi::kAudioUnitParameterFlag_PlotHistory;
+        const METER_READ_ONLY = ffi::kAudioUnitParameterFlag_MeterReadOnly;
+        const DISPLAY_MASK = ffi::kAudioUnitParameterFlag_DisplayMask;
+        const DISPLAY_SQUARE_ROOT = ffi::kAudioUnitParameterFlag_DisplaySquareRoot;
+        const DISPLAY_SQUARED = ffi::kAudioUnitParameterFlag_DisplaySquared;
+        const DISPLAY_CUBED = ffi::kAudioUnitParameterFlag_DisplayCubed;
+        const DISPLAY_CUBE_ROOT = ffi::kAudioUnitParameterFlag_DisplayCubeRoot;
+        const DISPLAY_EXPONENTIAL = ffi::kAudioUnitParameterFlag_DisplayExponential;
+        const HAS_CLUMP = ffi::kAudioUnitParameterFlag_HasClump;
+        const VALUES_HAVE_STRINGS = ffi::kAudioUnitParameterFlag_ValuesHaveStrings;
+        const DISPLAY_LOGARITHMIC = ffi::kAudioUnitParameterFlag_DisplayLogarithmic;
+        const IS_HIGH_RESOLUTION = ffi::kAudioUnitParameterFlag_IsHighResolution;
+        const NON_REAL_TIME = ffi::kAudioUnitParameterFlag_NonRealTime;
+        const CAN_RAMP = ffi::kAudioUnitParameterFlag_CanRamp;
+        const EXPERT_MODE = ffi::kAudioUnitParameterFlag_ExpertMode;
+        const HAS_CF_NAME_STRING = ffi::kAudioUnitParameterFlag_HasCFNameString;
+        const IS_GLOBAL_META = ffi::kAudioUnitParameterFlag_IsGlobalMeta;
+        const IS_ELEMENT_META = ffi::kAudioUnitParameterFlag_IsElementMeta;
+        const IS_READABLE = ffi::kAudioUnitParameterFlag_IsReadable;
+        const IS_WRITABLE = ffi::kAudioUnitParameterFlag_IsWritable;
+    }
+}
+
+/// Everything a unit reports about one of its parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterInfo {
+    pub id: AudioUnitParameter,
+    pub name: String,
+    /// Set when `flags` has `HAS_CLUMP`.
+    pub clump_id: Option<u32>,
+    pub unit: ParameterUnit,
+    /// The unit's own label for `ParameterUnit::CustomUnit` values.
+    pub unit_name: Option<String>,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub flags: ParameterFlags,
+}
+
+impl ParameterInfo {
+    /// Copy out of `info`, releasing the name string if the unit asks for
+    /// that.
+    unsafe fn take(id: AudioUnitParameter, info: &ffi::AudioUnitParameterInfo) -> Self {
+        let flags = ParameterFlags::from_bits_truncate(info.flags);
+        let name = if flags.contains(ParameterFlags::HAS_CF_NAME_STRING) &&
+            !info.cfNameString.is_null()
+        {
+            let name = cf::string(info.cfNameString);
+            if flags.contains(ParameterFlags::CF_NAME_RELEASE) {
+                ffi::CFRelease(info.cfNameString as ffi::CFTypeRef);
+            }
+            name
+        } else {
+            CStr::from_ptr(info.name.as_ptr()).to_string_lossy().into_owned()
+        };
+        let unit = ParameterUnit::from(info.unit);
+        let unit_name = if unit == ParameterUnit::CustomUnit && !info.unitName.is_null() {
+            Some(cf::string(info.unitName))
+        } else {
+            None
+        };
+        ParameterInfo {
+            id,
+            name,
+            clump_id: if flags.contains(ParameterFlags::HAS_CLUMP) {
+                Some(info.clumpID)
+            } else {
+                None
+            },
+            unit,
+            unit_name,
+            min: info.minValue,
+            max: info.maxValue,
+            default: info.defaultValue,
+            flags,
+        }
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.flags.contains(ParameterFlags::IS_READABLE)
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags.contains(ParameterFlags::IS_WRITABLE)
+    }
+}
+
+impl AudioUnitRef {
+    /// The IDs of the parameters the unit publishes in `scope`.
+    pub fn parameter_list(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<Vec<AudioUnitParameter>> {
+        self.get(props::ParameterList, scope, element)
+    }
+
+    pub fn parameter_info(
+        &self,
+        scope: AudioUnitScope,
+        id: AudioUnitParameter,
+    ) -> Result<ParameterInfo> {
+        // ParameterInfo is addressed by parameter ID in the element slot.
+        let info = self.get(props::ParameterInfo, scope, id)?;
+        Ok(unsafe { ParameterInfo::take(id, &info) })
+    }
+
+    /// `parameter_info` for every parameter in `parameter_list`.
+    pub fn parameters(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+    ) -> Result<Vec<ParameterInfo>> {
+        self.parameter_list(scope, element)?
+            .into_iter()
+            .map(|id| self.parameter_info(scope, id))
+            .collect()
+    }
+
+    /// The unit's display text for `value` of parameter `id`, or for its
+    /// current value on `element` if `value` is `None`.
+    pub fn parameter_string_from_value(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        id: AudioUnitParameter,
+        value: Option<f32>,
+    ) -> Result<String> {
+        let mut query = ffi::AudioUnitParameterStringFromValue {
+            inParamID: id,
+            inValue: value.as_ref().map_or(ptr::null(), |v| v as *const _),
+            outString: ptr::null(),
+        };
+        self.query(props::ParameterStringFromValue, scope, element, &mut query)?;
+        if query.outString.is_null() {
+            return Ok(String::new());
+        }
+        unsafe {
+            let text = cf::string(query.outString);
+            ffi::CFRelease(query.outString as ffi::CFTypeRef);
+            Ok(text)
+        }
+    }
+
+    /// Parse `text`, as displayed by the unit or typed in by a user, into a
+    /// value of parameter `id`.
+    pub fn parameter_value_from_string(
+        &self,
+        scope: AudioUnitScope,
+        element: AudioUnitElement,
+        id: AudioUnitParameter,
+        text: &str,
+    ) -> Result<f32> {
+        let mut query = ffi::AudioUnitParameterValueFromString {
+            inParamID: id,
+            inString: cf::create_string(text),
+            outValue: 0.0,
+        };
+        let string = query.inString;
+        let result =
+            self.query(props::ParameterValueFromString, scope, element, &mut query);
+        unsafe { ffi::CFRelease(string as ffi::CFTypeRef) };
+        result.map(|_| query.outValue)
+    }
+
+    /// The names of each value of an indexed parameter, starting from its
+    /// minimum. Only parameters with `VALUES_HAVE_STRINGS` have them.
+    pub fn parameter_value_strings(
+        &self,
+        scope: AudioUnitScope,
+        id: AudioUnitParameter,
+    ) -> Result<Vec<String>> {
+        // Like ParameterInfo, addressed by parameter ID in the element slot.
+        let strings = self.get(props::ParameterValueStrings, scope, id)?;
+        if strings.is_null() {
+            return Ok(Vec::new());
+        }
+        unsafe {
+            let names = cf::string_array(strings);
+            ffi::CFRelease(strings as ffi::CFTypeRef);
+            Ok(names)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_round_trip() {
+        for code in 0..27 {
+            let unit = ParameterUnit::from(code);
+            assert!(match unit {
+                ParameterUnit::Other(_) => false,
+                _ => true,
+            });
+            let back: ffi::AudioUnitParameterUnit = unit.into();
+            assert_eq!(back, code);
+        }
+        assert_eq!(ParameterUnit::from(99), ParameterUnit::Other(99));
+        let back: ffi::AudioUnitParameterUnit = ParameterUnit::Other(99).into();
+        assert_eq!(back, 99);
+    }
+
+    #[test]
+    fn display_curve_is_read_under_mask() {
+        let flags = ParameterFlags::from_bits_truncate(
+            ffi::kAudioUnitParameterFlag_DisplayCubed |
+                ffi::kAudioUnitParameterFlag_IsReadable,
+        );
+        assert_eq!(flags & ParameterFlags::DISPLAY_MASK, ParameterFlags::DISPLAY_CUBED);
+        assert!(flags.contains(ParameterFlags::IS_READABLE));
+    }
+
+    #[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+    mod sim {
+        use super::*;
+        use sim::new_unit;
+
+        #[test]
+        fn parameters_describe_each_published_id() {
+            let unit = new_unit(ffi::kAudioUnitType_Effect, ffi::kAudioUnitSubType_Delay);
+            let scope = AudioUnitScope::Global;
+            assert_eq!(unit.parameter_list(scope, 0).unwrap(), vec![0, 1, 2, 3]);
+            let params = unit.parameters(scope, 0).unwrap();
+            assert_eq!(params.len(), 4);
+            let time = &params[1];
+            assert_eq!((time.id, time.name.as_str()), (1, "Delay Time"));
+            assert_eq!(time.unit, ParameterUnit::Seconds);
+            assert_eq!((time.min, time.max, time.default), (0.0, 2.0, 1.0));
+            assert!(time.is_readable() && time.is_writable());
+            assert_eq!(time.clump_id, None);
+            assert_eq!(params[0].unit, ParameterUnit::EqualPowerCrossfade);
+            assert!(unit.parameter_info(scope, 42).is_err());
+            assert!(unit.parameters(AudioUnitScope::Input, 0).unwrap().is_empty());
+        }
+
+        #[test]
+        fn indexed_parameters_are_flagged() {
+            let unit = new_unit(ffi::kAudioUnitType_Effect,
+                                ffi::kAudioUnitSubType_NBandEQ);
+            let scope = AudioUnitScope::Global;
+            let filter = unit.parameter_info(scope, 2000).unwrap();
+            assert_eq!(filter.unit, ParameterUnit::Indexed);
+            assert!(filter.flags.contains(ParameterFlags::VALUES_HAVE_STRINGS));
+            assert_eq!((filter.min, filter.max), (0.0, 10.0));
+            let gain = unit.parameter_info(scope, 0).unwrap();
+            assert!(!gain.flags.contains(ParameterFlags::VALUES_HAVE_STRINGS));
+        }
+
+        #[test]
+        fn values_round_trip_through_display_strings() {
+            let unit = new_unit(ffi::kAudioUnitType_Effect,
+                                ffi::kAudioUnitSubType_NBandEQ);
+            let scope = AudioUnitScope::Global;
+            let names = unit.parameter_value_strings(scope, 2000).unwrap();
+            assert_eq!((names.len(), names[7].as_str()), (11, "Low Shelf"));
+            assert!(unit.parameter_value_strings(scope, 3000).is_err());
+
+            let text = unit.parameter_string_from_value(scope, 0, 2000, Some(7.0));
+            assert_eq!(text.unwrap(), "Low Shelf");
+            let value = unit.parameter_value_from_string(scope, 0, 2000, "Band Stop");
+            assert_eq!(value.unwrap(), 6.0);
+
+            // `None` displays the current value.
+            let text = unit.parameter_string_from_value(scope, 0, 3000, None).unwrap();
+            assert_eq!(text, "1000.00");
+            let value = unit.parameter_value_from_string(scope, 0, 3000, &text);
+            assert_eq!(value.unwrap(), 1000.0);
+            assert!(unit.parameter_value_from_string(scope, 0, 3000, "loud").is_err());
+        }
+    }
+}
@@ -0,0 +1,1135 @@
+//! Property lists, read and written without Core Foundation.
+//!
+//! Both the XML and the binary (`bplist00`) encodings are supported, which
+//! covers `.aupreset` files and the state units hand back through
+//! `kAudioUnitProperty_ClassInfo`.
+
+use {Error, Result};
+use std::collections::BTreeMap;
+use std::{error, fmt, str};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Array(Vec<Value>),
+    Dictionary(BTreeMap<String, Value>),
+    Boolean(bool),
+    Data(Vec<u8>),
+    /// Seconds since 2001-01-01 00:00:00 UTC, the Core Foundation epoch.
+    Date(f64),
+    Real(f64),
+    Integer(i64),
+    String(String),
+}
+
+impl Value {
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_dictionary(&self) -> Option<&BTreeMap<String, Value>> {
+        match *self {
+            Value::Dictionary(ref dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Data(ref data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Reals with no fractional part count as integers too, as Core
+    /// Foundation's number conversions allow.
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(i) => Some(i),
+            Value::Real(r) if r.fract() == 0.0 => Some(r as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_real(&self) -> Option<f64> {
+        match *self {
+            Value::Real(r) => Some(r),
+            Value::Integer(i) => Some(i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Where a property list failed to parse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlistError {
+    /// Malformed XML, at this byte offset.
+    Xml(usize),
+    /// Malformed binary property list, at this byte offset.
+    Binary(usize),
+}
+
+impl ::std::convert::From<PlistError> for Error {
+    fn from(e: PlistError) -> Self {
+        Error::Plist(e)
+    }
+}
+
+impl fmt::Display for PlistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlistError::Xml(offset) => {
+                write!(f, "malformed XML property list at byte {}", offset)
+            },
+            PlistError::Binary(offset) => {
+                write!(f, "malformed binary property list at byte {}", offset)
+            },
+        }
+    }
+}
+
+impl error::Error for PlistError {
+    fn description(&self) -> &str {
+        "malformed property list"
+    }
+}
+
+const BINARY_MAGIC: &'static [u8] = b"bplist00";
+
+/// Parse a property list in either encoding.
+pub fn from_bytes(bytes: &[u8]) -> Result<Value> {
+    if bytes.starts_with(BINARY_MAGIC) {
+        BinaryReader::new(bytes)?.read()
+    } else {
+        XmlReader::new(bytes)?.read()
+    }
+}
+
+//==============================================================================
+// XML
+
+const XML_HEADER: &'static str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+<plist version=\"1.0\">\n";
+
+/// Encode `value` as an XML property list, laid out the way Core Foundation
+/// writes them.
+pub fn to_xml(value: &Value) -> Vec<u8> {
+    let mut out = String::from(XML_HEADER);
+    write_xml(&mut out, value, 0);
+    out.push_str("</plist>\n");
+    out.into_bytes()
+}
+
+fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push('\t');
+    }
+}
+
+fn escape(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_xml(out: &mut String, value: &Value, depth: usize) {
+    indent(out, depth);
+    match *value {
+        Value::Array(ref items) if items.is_empty() => out.push_str("<array/>"),
+        Value::Array(ref items) => {
+            out.push_str("<array>\n");
+            for item in items {
+                write_xml(out, item, depth + 1);
+            }
+            indent(out, depth);
+            out.push_str("</array>");
+        },
+        Value::Dictionary(ref dict) if dict.is_empty() => out.push_str("<dict/>"),
+        Value::Dictionary(ref dict) => {
+            out.push_str("<dict>\n");
+            for (key, item) in dict {
+                indent(out, depth + 1);
+                out.push_str("<key>");
+                escape(out, key);
+                out.push_str("</key>\n");
+                write_xml(out, item, depth + 1);
+            }
+            indent(out, depth);
+            out.push_str("</dict>");
+        },
+        Value::Boolean(true) => out.push_str("<true/>"),
+        Value::Boolean(false) => out.push_str("<false/>"),
+        Value::Data(ref data) => {
+            out.push_str("<data>\n");
+            let encoded = base64_encode(data);
+            for line in encoded.as_bytes().chunks(68) {
+                indent(out, depth);
+                out.push_str(str::from_utf8(line).unwrap());
+                out.push('\n');
+            }
+            indent(out, depth);
+            out.push_str("</data>");
+        },
+        Value::Date(date) => {
+            out.push_str("<date>");
+            out.push_str(&format_date(date));
+            out.push_str("</date>");
+        },
+        Value::Real(r) => {
+            out.push_str("<real>");
+            if r.is_nan() {
+                out.push_str("nan");
+            } else if r.is_infinite() {
+                out.push_str(if r > 0.0 { "+infinity" } else { "-infinity" });
+            } else {
+                out.push_str(&r.to_string());
+            }
+            out.push_str("</real>");
+        },
+        Value::Integer(i) => {
+            out.push_str("<integer>");
+            out.push_str(&i.to_string());
+            out.push_str("</integer>");
+        },
+        Value::String(ref s) => {
+            out.push_str("<string>");
+            escape(out, s);
+            out.push_str("</string>");
+        },
+    }
+    out.push('\n');
+}
+
+/// Nesting beyond this is rejected rather than risking the stack.
+const MAX_DEPTH: usize = 256;
+
+struct XmlReader<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self> {
+        match str::from_utf8(bytes) {
+            Ok(src) => Ok(XmlReader { src, pos: 0 }),
+            Err(e) => Err(PlistError::Xml(e.valid_up_to()).into()),
+        }
+    }
+
+    fn error<T>(&self) -> Result<T> {
+        Err(PlistError::Xml(self.pos).into())
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    /// Move past `end`, which must follow somewhere in the input.
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            },
+            None => self.error(),
+        }
+    }
+
+    /// Skip whitespace, comments, processing instructions and the DOCTYPE.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            let trimmed = self.rest().trim_start();
+            self.pos = self.src.len() - trimmed.len();
+            if trimmed.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if trimmed.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if trimmed.starts_with("<!DOCTYPE") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Read `<name ...>` or `<name .../>`, returning the name and whether
+    /// the element was empty. Attributes are ignored.
+    fn open_tag(&mut self) -> Result<(&'a str, bool)> {
+        self.skip_misc()?;
+        let rest = self.rest();
+        if !rest.starts_with('<') || rest.starts_with("</") {
+            return self.error();
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => return self.error(),
+        };
+        let tag = &rest[1..end];
+        let empty = tag.ends_with('/');
+        let name = tag.trim_end_matches('/')
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("");
+        if name.is_empty() {
+            return self.error();
+        }
+        self.pos += end + 1;
+        Ok((name, empty))
+    }
+
+    fn close_tag(&mut self, name: &str) -> Result<()> {
+        self.skip_misc()?;
+        let rest = self.rest();
+        if rest.starts_with("</") && rest[2..].starts_with(name) {
+            let after = rest[2 + name.len()..].trim_start();
+            if after.starts_with('>') {
+                self.pos = self.src.len() - after.len() + 1;
+                return Ok(());
+            }
+        }
+        self.error()
+    }
+
+    fn at_close_tag(&mut self) -> Result<bool> {
+        self.skip_misc()?;
+        Ok(self.rest().starts_with("</"))
+    }
+
+    /// Character data up to the next tag, with entities decoded.
+    fn text(&mut self) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("<![CDATA[") {
+                let body = &rest[9..];
+                match body.find("]]>") {
+                    Some(end) => {
+                        text.push_str(&body[..end]);
+                        self.pos += 9 + end + 3;
+                    },
+                    None => return self.error(),
+                }
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with('<') || rest.is_empty() {
+                return Ok(text);
+            } else if rest.starts_with('&') {
+                let end = match rest.find(';') {
+                    Some(end) => end,
+                    None => return self.error(),
+                };
+                let c = match &rest[1..end] {
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "amp" => Some('&'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    e if e.starts_with("#x") => {
+                        u32::from_str_radix(&e[2..], 16).ok().and_then(char::from_u32)
+                    },
+                    e if e.starts_with('#') => {
+                        e[1..].parse().ok().and_then(char::from_u32)
+                    },
+                    _ => None,
+                };
+                match c {
+                    Some(c) => text.push(c),
+                    None => return self.error(),
+                }
+                self.pos += end + 1;
+            } else {
+                let end = rest.find(|c| c == '<' || c == '&').unwrap_or(rest.len());
+                text.push_str(&rest[..end]);
+                self.pos += end;
+            }
+        }
+    }
+
+    /// The text content of a simple element whose open tag was just read.
+    fn content(&mut self, name: &str, empty: bool) -> Result<String> {
+        if empty {
+            return Ok(String::new());
+        }
+        let text = self.text()?;
+        self.close_tag(name)?;
+        Ok(text)
+    }
+
+    fn read(mut self) -> Result<Value> {
+        let start = self.pos;
+        let (name, empty) = self.open_tag()?;
+        let value = if name == "plist" {
+            if empty {
+                return self.error();
+            }
+            let value = self.value(0)?;
+            self.close_tag("plist")?;
+            value
+        } else {
+            self.pos = start;
+            self.value(0)?
+        };
+        self.skip_misc()?;
+        if !self.rest().is_empty() {
+            return self.error();
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return self.error();
+        }
+        let (name, empty) = self.open_tag()?;
+        let start = self.pos;
+        let value = match name {
+            "dict" => {
+                let mut dict = BTreeMap::new();
+                while !empty && !self.at_close_tag()? {
+                    let (tag, empty) = self.open_tag()?;
+                    if tag != "key" {
+                        return self.error();
+                    }
+                    let key = self.content("key", empty)?;
+                    let value = self.value(depth + 1)?;
+                    dict.insert(key, value);
+                }
+                if !empty {
+                    self.close_tag("dict")?;
+                }
+                Value::Dictionary(dict)
+            },
+            "array" => {
+                let mut items = Vec::new();
+                while !empty && !self.at_close_tag()? {
+                    items.push(self.value(depth + 1)?);
+                }
+                if !empty {
+                    self.close_tag("array")?;
+                }
+                Value::Array(items)
+            },
+            "true" | "false" => {
+                if !empty {
+                    self.close_tag(name)?;
+                }
+                Value::Boolean(name == "true")
+            },
+            "string" => Value::String(self.content(name, empty)?),
+            "data" => {
+                let text = self.content(name, empty)?;
+                match base64_decode(&text) {
+                    Some(data) => Value::Data(data),
+                    None => return Err(PlistError::Xml(start).into()),
+                }
+            },
+            "date" => {
+                let text = self.content(name, empty)?;
+                match parse_date(text.trim()) {
+                    Some(date) => Value::Date(date),
+                    None => return Err(PlistError::Xml(start).into()),
+                }
+            },
+            "real" => {
+                let text = self.content(name, empty)?;
+                match parse_real(text.trim()) {
+                    Some(r) => Value::Real(r),
+                    None => return Err(PlistError::Xml(start).into()),
+                }
+            },
+            "integer" => {
+                let text = self.content(name, empty)?;
+                match parse_integer(text.trim()) {
+                    Some(i) => Value::Integer(i),
+                    None => return Err(PlistError::Xml(start).into()),
+                }
+            },
+            _ => return self.error(),
+        };
+        Ok(value)
+    }
+}
+
+fn parse_real(text: &str) -> Option<f64> {
+    match text {
+        "nan" => Some(::std::f64::NAN),
+        "+infinity" | "infinity" | "inf" => Some(::std::f64::INFINITY),
+        "-infinity" | "-inf" => Some(::std::f64::NEG_INFINITY),
+        _ => text.parse().ok(),
+    }
+}
+
+/// Unsigned values too large for an `i64` wrap, as Core Foundation stores
+/// them.
+fn parse_integer(text: &str) -> Option<i64> {
+    let (negative, digits) = if text.starts_with('-') {
+        (true, &text[1..])
+    } else if text.starts_with('+') {
+        (false, &text[1..])
+    } else {
+        (false, text)
+    };
+    let (radix, digits) = if digits.starts_with("0x") || digits.starts_with("0X") {
+        (16, &digits[2..])
+    } else {
+        (10, digits)
+    };
+    // `from_str_radix` takes a sign of its own, which would let "-+5" in.
+    if !digits.starts_with(|c: char| c.is_digit(radix)) {
+        return None;
+    }
+    let magnitude = u64::from_str_radix(digits, radix).ok()?;
+    if negative {
+        if magnitude > i64::max_value() as u64 + 1 {
+            return None;
+        }
+        Some((magnitude as i64).wrapping_neg())
+    } else {
+        Some(magnitude as i64)
+    }
+}
+
+//==============================================================================
+// Dates
+
+/// Days from 1970-01-01 to 2001-01-01.
+const CF_EPOCH_DAYS: i64 = 11_323;
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SSZ`, the only form property lists use.
+fn parse_date(text: &str) -> Option<f64> {
+    let b = text.as_bytes();
+    if b.len() != 20 || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || b[13] != b':' ||
+        b[16] != b':' || b[19] != b'Z'
+    {
+        return None;
+    }
+    // Digits only, so no field is signed and slicing stays on char
+    // boundaries.
+    let field = |range: ::std::ops::Range<usize>| {
+        if b[range.clone()].iter().all(u8::is_ascii_digit) {
+            text[range].parse::<i64>().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if month < 1 || month > 12 || day < 1 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // Days past the end of the month land in the next one.
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    let seconds = hour * 3600 + minute * 60 + second;
+    Some(((days - CF_EPOCH_DAYS) * 86_400 + seconds) as f64)
+}
+
+/// Dates outside the years `parse_date` reads, 0000 to 9999, are clamped
+/// to them, and NaN is the reference date.
+fn format_date(date: f64) -> String {
+    let first = days_from_civil(0, 1, 1) * 86_400;
+    let last = days_from_civil(10_000, 1, 1) * 86_400 - 1;
+    let total = if date.is_nan() {
+        CF_EPOCH_DAYS * 86_400
+    } else {
+        // Clamp while still a float, as the cast would saturate instead.
+        let unix = date.floor() + (CF_EPOCH_DAYS * 86_400) as f64;
+        unix.max(first as f64).min(last as f64) as i64
+    };
+    let days = total.div_euclid(86_400);
+    let seconds = total.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+//==============================================================================
+// Base64, as used by `<data>`
+
+const BASE64: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| {
+            n | (b as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Whitespace is skipped, as `<data>` is usually wrapped over lines.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut n = 0u32;
+    let mut bits = 0;
+    let mut padding = false;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            padding = true;
+            continue;
+        }
+        if padding {
+            return None;
+        }
+        let v = BASE64.iter().position(|&b| b == c)? as u32;
+        n = n << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+//==============================================================================
+// Binary
+
+/// Objects can be referenced from more than one place, so a small binary
+/// property list can describe a huge tree. Decoding stops once the values
+/// produced (one per object, plus the bytes of data and strings) reach this
+/// many times the size of the input.
+const MAX_EXPANSION: usize = 64;
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    top: usize,
+    /// What is left of the decoding budget.
+    budget: usize,
+    /// The objects being decoded, to catch references back to them.
+    visiting: Vec<bool>,
+}
+
+fn be_uint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+impl<'a> BinaryReader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        let error = |offset| Err(PlistError::Binary(offset).into());
+        if data.len() < BINARY_MAGIC.len() + 32 {
+            return error(data.len());
+        }
+        let trailer_start = data.len() - 32;
+        let trailer = &data[trailer_start..];
+        let offset_size = trailer[6] as usize;
+        let ref_size = trailer[7] as usize;
+        let count = be_uint(&trailer[8..16]);
+        let top = be_uint(&trailer[16..24]);
+        let table = be_uint(&trailer[24..32]);
+        if offset_size == 0 || offset_size > 8 || ref_size == 0 || ref_size > 8 ||
+            top >= count || table < BINARY_MAGIC.len() as u64 ||
+            table > trailer_start as u64 ||
+            count > (trailer_start as u64 - table) / offset_size as u64
+        {
+            return error(trailer_start);
+        }
+        let table = table as usize;
+        let mut offsets = Vec::with_capacity(count as usize);
+        for i in 0..count as usize {
+            let at = table + i * offset_size;
+            let offset = be_uint(&data[at..at + offset_size]);
+            if offset < BINARY_MAGIC.len() as u64 || offset >= table as u64 {
+                return error(at);
+            }
+            offsets.push(offset as usize);
+        }
+        let visiting = vec![false; offsets.len()];
+        Ok(BinaryReader {
+            data: &data[..table],
+            offsets,
+            ref_size,
+            top: top as usize,
+            budget: data.len().saturating_mul(MAX_EXPANSION),
+            visiting,
+        })
+    }
+
+    fn read(mut self) -> Result<Value> {
+        let top = self.top;
+        self.object(top, 0)
+    }
+
+    /// Take `cost` from the budget, failing at `at` if it runs out.
+    fn spend(&mut self, cost: usize, at: usize) -> Result<()> {
+        match self.budget.checked_sub(cost) {
+            Some(left) => {
+                self.budget = left;
+                Ok(())
+            },
+            None => Err(PlistError::Binary(at).into()),
+        }
+    }
+
+    fn bytes(&self, at: usize, len: usize) -> Result<&'a [u8]> {
+        match at.checked_add(len) {
+            Some(end) if end <= self.data.len() => Ok(&self.data[at..end]),
+            _ => Err(PlistError::Binary(at).into()),
+        }
+    }
+
+    /// The length in a marker's low nibble, or in the integer after it.
+    /// Returns the length and where the payload starts.
+    fn length(&self, at: usize) -> Result<(usize, usize)> {
+        let nibble = (self.bytes(at, 1)?[0] & 0x0f) as usize;
+        if nibble != 0x0f {
+            return Ok((nibble, at + 1));
+        }
+        let marker = self.bytes(at + 1, 1)?[0];
+        if marker >> 4 != 0x1 || marker & 0x0f > 3 {
+            return Err(PlistError::Binary(at + 1).into());
+        }
+        let size = 1 << (marker & 0x0f);
+        let len = be_uint(self.bytes(at + 2, size)?);
+        if len > self.data.len() as u64 {
+            return Err(PlistError::Binary(at + 2).into());
+        }
+        Ok((len as usize, at + 2 + size))
+    }
+
+    fn refs(&self, at: usize, count: usize) -> Result<Vec<usize>> {
+        let size = count.checked_mul(self.ref_size).unwrap_or(usize::max_value());
+        let bytes = self.bytes(at, size)?;
+        bytes.chunks(self.ref_size)
+            .map(|r| {
+                let index = be_uint(r);
+                if index < self.offsets.len() as u64 {
+                    Ok(index as usize)
+                } else {
+                    Err(PlistError::Binary(at).into())
+                }
+            })
+            .collect()
+    }
+
+    fn object(&mut self, index: usize, depth: usize) -> Result<Value> {
+        let at = self.offsets[index];
+        // An object that contains itself would never finish.
+        if depth > MAX_DEPTH || self.visiting[index] {
+            return Err(PlistError::Binary(at).into());
+        }
+        self.spend(1, at)?;
+        self.visiting[index] = true;
+        let value = self.decode(at, depth);
+        self.visiting[index] = false;
+        value
+    }
+
+    fn decode(&mut self, at: usize, depth: usize) -> Result<Value> {
+        let marker = self.bytes(at, 1)?[0];
+        let value = match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Value::Boolean(false),
+                0x09 => Value::Boolean(true),
+                _ => return Err(PlistError::Binary(at).into()),
+            },
+            0x1 if marker & 0x0f <= 4 => {
+                let size = 1 << (marker & 0x0f);
+                let bytes = self.bytes(at + 1, size)?;
+                // 16-byte integers only exist to hold unsigned 64-bit
+                // values; the low half is the value.
+                let bytes = if size == 16 { &bytes[8..] } else { bytes };
+                Value::Integer(be_uint(bytes) as i64)
+            },
+            0x2 if marker & 0x0f == 2 => {
+                let bits = be_uint(self.bytes(at + 1, 4)?) as u32;
+                Value::Real(f32::from_bits(bits) as f64)
+            },
+            0x2 if marker & 0x0f == 3 => {
+                Value::Real(f64::from_bits(be_uint(self.bytes(at + 1, 8)?)))
+            },
+            0x3 if marker == 0x33 => {
+                Value::Date(f64::from_bits(be_uint(self.bytes(at + 1, 8)?)))
+            },
+            0x4 => {
+                let (len, start) = self.length(at)?;
+                self.spend(len, start)?;
+                Value::Data(self.bytes(start, len)?.to_vec())
+            },
+            0x5 => {
+                let (len, start) = self.length(at)?;
+                self.spend(len, start)?;
+                let bytes = self.bytes(start, len)?;
+                if !bytes.is_ascii() {
+                    return Err(PlistError::Binary(start).into());
+                }
+                Value::String(String::from_utf8(bytes.to_vec()).unwrap())
+            },
+            0x6 => {
+                let (len, start) = self.length(at)?;
+                let size = len.checked_mul(2).unwrap_or(usize::max_value());
+                self.spend(size, start)?;
+                let units: Vec<u16> = self.bytes(start, size)?
+                    .chunks(2)
+                    .map(|c| be_uint(c) as u16)
+                    .collect();
+                match String::from_utf16(&units) {
+                    Ok(s) => Value::String(s),
+                    Err(_) => return Err(PlistError::Binary(start).into()),
+                }
+            },
+            0xa => {
+                let (len, start) = self.length(at)?;
+                let mut items = Vec::with_capacity(len);
+                for r in self.refs(start, len)? {
+                    items.push(self.object(r, depth + 1)?);
+                }
+                Value::Array(items)
+            },
+            0xd => {
+                let (len, start) = self.length(at)?;
+                let keys = self.refs(start, len)?;
+                let values = self.refs(start + len * self.ref_size, len)?;
+                let mut dict = BTreeMap::new();
+                for (k, v) in keys.into_iter().zip(values) {
+                    let key = match self.object(k, depth + 1)? {
+                        Value::String(key) => key,
+                        _ => return Err(PlistError::Binary(self.offsets[k]).into()),
+                    };
+                    dict.insert(key, self.object(v, depth + 1)?);
+                }
+                Value::Dictionary(dict)
+            },
+            _ => return Err(PlistError::Binary(at).into()),
+        };
+        Ok(value)
+    }
+}
+
+enum Object<'a> {
+    Value(&'a Value),
+    Key(&'a str),
+}
+
+/// Number of bytes needed to hold `n` as a binary plist offset or ref.
+fn uint_size(n: u64) -> usize {
+    if n <= 0xff {
+        1
+    } else if n <= 0xffff {
+        2
+    } else if n <= 0xffff_ffff {
+        4
+    } else {
+        8
+    }
+}
+
+fn push_uint(out: &mut Vec<u8>, n: u64, size: usize) {
+    for i in (0..size).rev() {
+        out.push((n >> (8 * i)) as u8);
+    }
+}
+
+fn push_integer(out: &mut Vec<u8>, i: i64) {
+    // Negative values are always written as eight bytes.
+    let size = if i < 0 { 8 } else { uint_size(i as u64) };
+    out.push(0x10 | size.trailing_zeros() as u8);
+    push_uint(out, i as u64, size);
+}
+
+fn push_marker(out: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 0x0f {
+        out.push(kind << 4 | len as u8);
+    } else {
+        out.push(kind << 4 | 0x0f);
+        push_integer(out, len as i64);
+    }
+}
+
+/// Number `value` and everything under it in depth-first order, returning
+/// the index of `value`. Each object is listed with the indices of its
+/// children: keys then values for dictionaries.
+fn flatten<'a>(value: &'a Value, objects: &mut Vec<(Object<'a>, Vec<u64>)>) -> u64 {
+    let index = objects.len();
+    objects.push((Object::Value(value), Vec::new()));
+    let children = match *value {
+        Value::Array(ref items) => items.iter().map(|v| flatten(v, objects)).collect(),
+        Value::Dictionary(ref dict) => {
+            let mut keys: Vec<u64> = dict.keys()
+                .map(|k| {
+                    objects.push((Object::Key(k), Vec::new()));
+                    (objects.len() - 1) as u64
+                })
+                .collect();
+            let values: Vec<u64> = dict.values().map(|v| flatten(v, objects)).collect();
+            keys.extend(values);
+            keys
+        },
+        _ => Vec::new(),
+    };
+    objects[index].1 = children;
+    index as u64
+}
+
+fn push_string(out: &mut Vec<u8>, s: &str) {
+    if s.is_ascii() {
+        push_marker(out, 0x5, s.len());
+        out.extend_from_slice(s.as_bytes());
+    } else {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        push_marker(out, 0x6, units.len());
+        for unit in units {
+            push_uint(out, unit as u64, 2);
+        }
+    }
+}
+
+/// Encode `value` as a binary (`bplist00`) property list.
+pub fn to_binary(value: &Value) -> Vec<u8> {
+    let mut objects = Vec::new();
+    flatten(value, &mut objects);
+    let ref_size = uint_size(objects.len() as u64);
+
+    let mut out = BINARY_MAGIC.to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for &(ref object, ref children) in &objects {
+        offsets.push(out.len() as u64);
+        match *object {
+            Object::Key(key) => push_string(&mut out, key),
+            Object::Value(&Value::Array(_)) => push_marker(&mut out, 0xa, children.len()),
+            Object::Value(&Value::Dictionary(_)) => {
+                push_marker(&mut out, 0xd, children.len() / 2)
+            },
+            Object::Value(&Value::Boolean(b)) => out.push(if b { 0x09 } else { 0x08 }),
+            Object::Value(&Value::Data(ref data)) => {
+                push_marker(&mut out, 0x4, data.len());
+                out.extend_from_slice(data);
+            },
+            Object::Value(&Value::Date(date)) => {
+                out.push(0x33);
+                push_uint(&mut out, date.to_bits(), 8);
+            },
+            Object::Value(&Value::Real(r)) => {
+                out.push(0x23);
+                push_uint(&mut out, r.to_bits(), 8);
+            },
+            Object::Value(&Value::Integer(i)) => push_integer(&mut out, i),
+            Object::Value(&Value::String(ref s)) => push_string(&mut out, s),
+        }
+        for &child in children {
+            push_uint(&mut out, child, ref_size);
+        }
+    }
+
+    let table = out.len() as u64;
+    let offset_size = uint_size(table);
+    for offset in offsets {
+        push_uint(&mut out, offset, offset_size);
+    }
+    out.extend_from_slice(&[0; 6]);
+    out.push(offset_size as u8);
+    out.push(ref_size as u8);
+    push_uint(&mut out, objects.len() as u64, 8);
+    push_uint(&mut out, 0, 8);
+    push_uint(&mut out, table, 8);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ClassInfo;
+    use ffi;
+
+    const XML_PRESET: &'static [u8] =
+        include_bytes!("../tests/fixtures/Slapback.aupreset");
+    const BINARY_PRESET: &'static [u8] =
+        include_bytes!("../tests/fixtures/Slapback-binary.aupreset");
+
+    fn sample() -> Value {
+        let mut dict = BTreeMap::new();
+        dict.insert("flag".to_owned(), Value::Boolean(true));
+        dict.insert("blob".to_owned(), Value::Data((0..200).map(|i| i as u8).collect()));
+        dict.insert("when".to_owned(), Value::Date(-86_400.0));
+        dict.insert("gain".to_owned(), Value::Real(-0.25));
+        dict.insert("big".to_owned(), Value::Integer(1 << 40));
+        dict.insert("negative".to_owned(), Value::Integer(-7));
+        let text = Value::String("caf\u{e9}".into());
+        dict.insert("<escaped> & \"quoted\"".to_owned(), text);
+        dict.insert("empty".to_owned(), Value::Array(Vec::new()));
+        let items = (0..20).map(|i| Value::String(format!("item {}", i))).collect();
+        Value::Array(vec![Value::Dictionary(dict), Value::Array(items)])
+    }
+
+    /// A binary property list of `objects`, already encoded with one byte
+    /// refs, whose top object is the first.
+    fn binary(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = BINARY_MAGIC.to_vec();
+        let mut offsets = Vec::new();
+        for object in objects {
+            offsets.push(out.len() as u64);
+            out.extend_from_slice(object);
+        }
+        let table = out.len() as u64;
+        for offset in offsets {
+            push_uint(&mut out, offset, 2);
+        }
+        out.extend_from_slice(&[0, 0, 0, 0, 0, 0, 2, 1]);
+        push_uint(&mut out, objects.len() as u64, 8);
+        push_uint(&mut out, 0, 8);
+        push_uint(&mut out, table, 8);
+        out
+    }
+
+    #[test]
+    fn values_round_trip_through_both_encodings() {
+        let value = sample();
+        assert_eq!(from_bytes(&to_xml(&value)).unwrap(), value);
+        assert_eq!(from_bytes(&to_binary(&value)).unwrap(), value);
+        let binary = to_binary(&from_bytes(&to_xml(&value)).unwrap());
+        assert_eq!(to_xml(&from_bytes(&binary).unwrap()), to_xml(&value));
+    }
+
+    #[test]
+    fn reads_presets_in_either_encoding() {
+        let xml = from_bytes(XML_PRESET).unwrap();
+        assert_eq!(from_bytes(BINARY_PRESET).unwrap(), xml);
+        assert_eq!(from_bytes(&to_binary(&xml)).unwrap(), xml);
+
+        let preset = ClassInfo::from_bytes(BINARY_PRESET).unwrap();
+        assert_eq!(preset.name, "Slapback \u{2013} Tape");
+        assert_eq!(preset.kind, ffi::kAudioUnitType_Effect);
+        assert_eq!(preset.sub_kind, ffi::kAudioUnitSubType_Delay);
+        assert_eq!(preset.manufacturer, ffi::kAudioUnitManufacturer_Apple);
+        assert_eq!(preset.data.as_ref().map(|data| data.len()), Some(48));
+        assert!(preset.extra.contains_key("element-name"));
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        for len in 0..BINARY_PRESET.len() {
+            assert!(from_bytes(&BINARY_PRESET[..len]).is_err(), "{} bytes", len);
+        }
+        for len in 0..XML_PRESET.len() - 1 {
+            assert!(from_bytes(&XML_PRESET[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn cycles_are_an_error() {
+        // An array holding itself, and a dictionary holding an array that
+        // holds the dictionary.
+        assert!(from_bytes(&binary(&[vec![0xa1, 0]])).is_err());
+        let key = b"\x51k".to_vec();
+        let cycle = binary(&[vec![0xd1, 1, 2], key, vec![0xa1, 0]]);
+        assert_eq!(from_bytes(&cycle), Err(Error::Plist(PlistError::Binary(8))));
+    }
+
+    #[test]
+    fn dates_are_clamped_to_four_digit_years() {
+        assert_eq!(format_date(-86_400.0), "2000-12-31T00:00:00Z");
+        assert_eq!(format_date(::std::f64::NAN), "2001-01-01T00:00:00Z");
+        assert_eq!(format_date(1e300), "9999-12-31T23:59:59Z");
+        assert_eq!(format_date(::std::f64::NEG_INFINITY), "0000-01-01T00:00:00Z");
+        let mut date = vec![0x33];
+        push_uint(&mut date, 1e300f64.to_bits(), 8);
+        let xml = to_xml(&from_bytes(&binary(&[date])).unwrap());
+        let last = (days_from_civil(10_000, 1, 1) - CF_EPOCH_DAYS) * 86_400 - 1;
+        assert_eq!(from_bytes(&xml).unwrap(), Value::Date(last as f64));
+    }
+
+    #[test]
+    fn malformed_dates_and_integers_are_rejected() {
+        assert_eq!(parse_date("2024-02-29T12:30:59Z"), Some(730_902_659.0));
+        let dates = [
+            "2024-13-01T00:00:00Z",
+            "2023-02-29T00:00:00Z",
+            "2024-00-10T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "+024-01-01T00:00:00Z",
+            "2024-+1-01T00:00:00Z",
+            "2024-01-01T00:-1:00Z",
+            "2024-01-01T00:00:\u{e9}Z",
+        ];
+        for text in &dates {
+            assert_eq!(parse_date(text), None, "{}", text);
+        }
+        assert_eq!(parse_integer("+5"), Some(5));
+        assert_eq!(parse_integer("-0x10"), Some(-16));
+        assert_eq!(parse_integer("18446744073709551615"), Some(-1));
+        for text in &["-+5", "+-5", "++5", "--5", "0x+5", "-", "0x", ""] {
+            assert_eq!(parse_integer(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn shared_references_are_bounded() {
+        // Three references to one string are fine.
+        let shared = binary(&[vec![0xa3, 1, 1, 1], b"\x53abc".to_vec()]);
+        let abc = Value::String("abc".into());
+        assert_eq!(from_bytes(&shared).unwrap(), Value::Array(vec![abc; 3]));
+
+        // Each array referencing the next twice would decode to 2^40
+        // strings.
+        let mut objects: Vec<Vec<u8>> =
+            (1..41).map(|next| vec![0xa2, next, next]).collect();
+        objects.push(b"\x53abc".to_vec());
+        assert!(from_bytes(&binary(&objects)).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use {AudioComponentDescription, AudioComponentDescriptionRef, AudioUnitRef,
+     AudioUnitScope, Error, Result};
+use backend;
+use call;
+use cf;
+use ffi;
+use plist;
+use props;
+use std::collections::BTreeMap;
+use std::{error, fmt};
+
+/// An AU preset: the state a unit saves through
+/// `kAudioUnitProperty_ClassInfo`, and the contents of `.aupreset` files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassInfo {
+    pub version: i64,
+    pub kind: ffi::OSType,
+    pub sub_kind: ffi::OSType,
+    pub manufacturer: ffi::OSType,
+    pub name: String,
+    /// The unit's own opaque state.
+    pub data: Option<Vec<u8>>,
+    /// Every other key, such as `element-name` or a unit's own parameter
+    /// dictionaries, kept so that they survive being read and written back.
+    pub extra: BTreeMap<String, plist::Value>,
+}
+
+/// One of a unit's presets. Factory presets are numbered from zero, user
+/// presets are negative.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preset {
+    pub number: i32,
+    pub name: String,
+}
+
+/// Which preset `set_present_preset` selects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresetSelector<'a> {
+    /// The factory preset with this number.
+    Number(i32),
+    /// The factory preset with this name.
+    Name(&'a str),
+    /// This preset as it is, such as a user preset just restored.
+    Preset(&'a Preset),
+}
+
+/// Why a property list isn't a valid preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresetError {
+    /// The top level isn't a dictionary.
+    NotDictionary,
+    MissingKey(&'static str),
+    /// The key holds the wrong type of value.
+    InvalidValue(&'static str),
+    /// The preset belongs to a different component.
+    ComponentMismatch,
+    /// The unit has no factory preset with that number or name.
+    NotFound,
+}
+
+/// Keys held in their own `ClassInfo` field rather than in `extra`.
+const FIELD_KEYS: &'static [&'static str] = &[
+    ffi::kAUPresetVersionKey,
+    ffi::kAUPresetTypeKey,
+    ffi::kAUPresetSubtypeKey,
+    ffi::kAUPresetManufacturerKey,
+    ffi::kAUPresetNameKey,
+    ffi::kAUPresetDataKey,
+];
+
+fn integer(dict: &BTreeMap<String, plist::Value>, key: &'static str) -> Result<i64> {
+    match dict.get(key) {
+        None => Err(PresetError::MissingKey(key).into()),
+        Some(value) => value.as_integer().ok_or(PresetError::InvalidValue(key).into()),
+    }
+}
+
+/// Four-char codes are stored as signed 32-bit numbers.
+fn four_char_code(
+    dict: &BTreeMap<String, plist::Value>,
+    key: &'static str,
+) -> Result<ffi::OSType> {
+    integer(dict, key).map(|code| code as u32)
+}
+
+impl ClassInfo {
+    /// An empty preset for the component described by `desc`.
+    pub fn new(desc: &AudioComponentDescriptionRef, name: &str) -> ClassInfo {
+        let desc: &ffi::AudioComponentDescription = unsafe { &*desc.as_ptr() };
+        ClassInfo {
+            version: 0,
+            kind: desc.componentType,
+            sub_kind: desc.componentSubType,
+            manufacturer: desc.componentManufacturer,
+            name: name.to_owned(),
+            data: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_plist(value: &plist::Value) -> Result<ClassInfo> {
+        let dict = match value.as_dictionary() {
+            Some(dict) => dict,
+            None => return Err(PresetError::NotDictionary.into()),
+        };
+        use ffi::{kAUPresetDataKey, kAUPresetNameKey};
+        let name = match dict.get(kAUPresetNameKey) {
+            Some(&plist::Value::String(ref name)) => name.clone(),
+            Some(_) => return Err(PresetError::InvalidValue(kAUPresetNameKey).into()),
+            None => String::new(),
+        };
+        let data = match dict.get(kAUPresetDataKey) {
+            Some(&plist::Value::Data(ref data)) => Some(data.clone()),
+            Some(_) => return Err(PresetError::InvalidValue(kAUPresetDataKey).into()),
+            None => None,
+        };
+        Ok(ClassInfo {
+            version: integer(dict, ffi::kAUPresetVersionKey)?,
+            kind: four_char_code(dict, ffi::kAUPresetTypeKey)?,
+            sub_kind: four_char_code(dict, ffi::kAUPresetSubtypeKey)?,
+            manufacturer: four_char_code(dict, ffi::kAUPresetManufacturerKey)?,
+            name,
+            data,
+            extra: dict.iter()
+                .filter(|&(k, _)| !FIELD_KEYS.contains(&k.as_str()))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        })
+    }
+
+    pub fn to_plist(&self) -> plist::Value {
+        let mut dict = self.extra.clone();
+        let code = |code: ffi::OSType| plist::Value::Integer(code as i32 as i64);
+        dict.insert(ffi::kAUPresetVersionKey.into(), plist::Value::Integer(self.version));
+        dict.insert(ffi::kAUPresetTypeKey.into(), code(self.kind));
+        dict.insert(ffi::kAUPresetSubtypeKey.into(), code(self.sub_kind));
+        dict.insert(ffi::kAUPresetManufacturerKey.into(), code(self.manufacturer));
+        let name = plist::Value::String(self.name.clone());
+        dict.insert(ffi::kAUPresetNameKey.into(), name);
+        if let Some(ref data) = self.data {
+            dict.insert(ffi::kAUPresetDataKey.into(), plist::Value::Data(data.clone()));
+        }
+        plist::Value::Dictionary(dict)
+    }
+
+    /// Read an `.aupreset` file's contents, in either plist encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ClassInfo> {
+        ClassInfo::from_plist(&plist::from_bytes(bytes)?)
+    }
+
+    pub fn to_xml(&self) -> Vec<u8> {
+        plist::to_xml(&self.to_plist())
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        plist::to_binary(&self.to_plist())
+    }
+
+    pub fn description(&self) -> AudioComponentDescription {
+        AudioComponentDescription::new(self.kind, self.sub_kind, self.manufacturer)
+    }
+
+    /// Check that the preset was saved by the component `desc` describes.
+    /// Zero fields in `desc` match anything.
+    pub fn check_component(&self, desc: &AudioComponentDescriptionRef) -> Result<()> {
+        let desc: &ffi::AudioComponentDescription = unsafe { &*desc.as_ptr() };
+        let matches = |wanted, ours| wanted == 0 || wanted == ours;
+        if matches(desc.componentType, self.kind) &&
+            matches(desc.componentSubType, self.sub_kind) &&
+            matches(desc.componentManufacturer, self.manufacturer)
+        {
+            Ok(())
+        } else {
+            Err(PresetError::ComponentMismatch.into())
+        }
+    }
+}
+
+impl ::std::convert::From<i32> for PresetSelector<'static> {
+    fn from(number: i32) -> Self {
+        PresetSelector::Number(number)
+    }
+}
+
+impl<'a> ::std::convert::From<&'a str> for PresetSelector<'a> {
+    fn from(name: &'a str) -> Self {
+        PresetSelector::Name(name)
+    }
+}
+
+impl<'a> ::std::convert::From<&'a Preset> for PresetSelector<'a> {
+    fn from(preset: &'a Preset) -> Self {
+        PresetSelector::Preset(preset)
+    }
+}
+
+impl AudioUnitRef {
+    /// The unit's built-in presets. Units without any give an empty list.
+    pub fn factory_presets(&self) -> Result<Vec<Preset>> {
+        let array = match self.get(props::FactoryPresets, AudioUnitScope::Global, 0) {
+            Ok(array) => array,
+            Err(Error::Status(ffi::kAudioUnitErr_InvalidProperty)) => {
+                return Ok(Vec::new());
+            },
+            Err(e) => return Err(e),
+        };
+        unsafe {
+            // The array holds pointers to `AUPreset`s, not CF objects.
+            let presets = (0..ffi::CFArrayGetCount(array))
+                .map(|i| {
+                    let item = ffi::CFArrayGetValueAtIndex(array, i);
+                    let preset = &*(item as *const ffi::AUPreset);
+                    Preset {
+                        number: preset.presetNumber,
+                        name: if preset.presetName.is_null() {
+                            String::new()
+                        } else {
+                            cf::string(preset.presetName)
+                        },
+                    }
+                })
+                .collect();
+            ffi::CFRelease(array as ffi::CFTypeRef);
+            Ok(presets)
+        }
+    }
+
+    /// The preset the unit's state was last loaded from.
+    pub fn present_preset(&self) -> Result<Preset> {
+        let preset = self.get(props::PresentPreset, AudioUnitScope::Global, 0)?;
+        let name = if preset.presetName.is_null() {
+            String::new()
+        } else {
+            unsafe {
+                let name = cf::string(preset.presetName);
+                ffi::CFRelease(preset.presetName as ffi::CFTypeRef);
+                name
+            }
+        };
+        Ok(Preset {
+            number: preset.presetNumber,
+            name,
+        })
+    }
+
+    /// Select a preset. Selecting a factory preset loads its state into the
+    /// unit. Returns the preset selected.
+    pub fn set_present_preset<'a, S>(&self, preset: S) -> Result<Preset>
+    where
+        S: Into<PresetSelector<'a>>,
+    {
+        let preset = match preset.into() {
+            PresetSelector::Preset(preset) => preset.clone(),
+            PresetSelector::Number(n) => self.factory_preset(|p| p.number == n)?,
+            PresetSelector::Name(name) => self.factory_preset(|p| p.name == name)?,
+        };
+        let name = cf::create_string(&preset.name);
+        let value = ffi::AUPreset {
+            presetNumber: preset.number,
+            presetName: name,
+        };
+        let result = self.set(props::PresentPreset, AudioUnitScope::Global, 0, &value);
+        unsafe { ffi::CFRelease(name as ffi::CFTypeRef) };
+        result.map(|()| preset)
+    }
+
+    fn factory_preset<F>(&self, f: F) -> Result<Preset>
+    where
+        F: Fn(&Preset) -> bool,
+    {
+        self.factory_presets()?
+            .into_iter()
+            .find(f)
+            .ok_or(PresetError::NotFound.into())
+    }
+
+    /// The unit's complete state, read from `kAudioUnitProperty_ClassInfo`.
+    pub fn save_state(&self) -> Result<ClassInfo> {
+        let p = self.get(props::ClassInfo, AudioUnitScope::Global, 0)?;
+        if p.is_null() {
+            return Err(Error::Status(ffi::kAudioUnitErr_InvalidPropertyValue));
+        }
+        let value = unsafe {
+            let value = cf::property_list(p);
+            ffi::CFRelease(p);
+            value?
+        };
+        ClassInfo::from_plist(&value)
+    }
+
+    /// Put the unit back in a state `save_state` returned, then tell
+    /// parameter listeners that any of its parameters may have changed, as
+    /// hosts do after loading a preset. State saved by another component is
+    /// refused with `PresetError::ComponentMismatch`.
+    pub fn restore_state(&self, state: &ClassInfo) -> Result<()> {
+        // The unit's own class info names its component.
+        state.check_component(&self.save_state()?.description())?;
+        let p = cf::create_property_list(&state.to_plist())?;
+        let result = self.set(props::ClassInfo, AudioUnitScope::Global, 0, &p);
+        unsafe { ffi::CFRelease(p) };
+        result?;
+        let parameter = ffi::AudioUnitParameter {
+            mAudioUnit: self.as_ptr(),
+            mParameterID: ffi::kAUParameterListener_AnyParameter,
+            mScope: ffi::kAudioUnitScope_Global,
+            mElement: 0,
+        };
+        unsafe {
+            let backend = backend::get(self.as_ptr());
+            call::cvt_r(backend.parameter_listener_notify(self.as_ptr(), &parameter))
+        }
+    }
+}
+
+impl ::std::convert::From<PresetError> for Error {
+    fn from(e: PresetError) -> Self {
+        Error::Preset(e)
+    }
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PresetError::NotDictionary => write!(f, "preset is not a dictionary"),
+            PresetError::MissingKey(key) => write!(f, "preset has no \"{}\" key", key),
+            PresetError::InvalidValue(key) => {
+                write!(f, "preset \"{}\" has the wrong type of value", key)
+            },
+            PresetError::ComponentMismatch => {
+                write!(f, "preset belongs to a different component")
+            },
+            PresetError::NotFound => write!(f, "no such factory preset"),
+        }
+    }
+}
+
+impl error::Error for PresetError {
+    fn description(&self) -> &str {
+        "invalid preset"
+    }
+}
+
+#[cfg(all(test, any(feature = "sim", not(target_vendor = "apple"))))]
+mod tests {
+    use super::*;
+    use sim::{new_unit, take_parameter_notifications};
+
+    const GLOBAL: AudioUnitScope = AudioUnitScope::Global;
+
+    #[test]
+    fn state_round_trips_through_class_info() {
+        let unit = new_unit(ffi::kAudioUnitType_Effect,
+                            ffi::kAudioUnitSubType_NBandEQ);
+        unit.set_parameter(3000, GLOBAL, 0, 440.0).unwrap();
+        let saved = unit.save_state().unwrap();
+        assert_eq!(saved.kind, ffi::kAudioUnitType_Effect);
+        assert_eq!(saved.sub_kind, ffi::kAudioUnitSubType_NBandEQ);
+        assert!(saved.data.is_some());
+
+        // Through an .aupreset file and back.
+        let mut restored = ClassInfo::from_bytes(&saved.to_xml()).unwrap();
+        assert_eq!(restored, saved);
+        restored.name = "Recall".to_owned();
+        unit.set_parameter(3000, GLOBAL, 0, 880.0).unwrap();
+        take_parameter_notifications(&unit);
+        unit.restore_state(&restored).unwrap();
+        assert_eq!(unit.get_parameter(3000, GLOBAL, 0).unwrap(), 440.0);
+        assert_eq!(unit.save_state().unwrap().name, "Recall");
+        // Listeners hear that any parameter may have changed.
+        let any = ffi::kAUParameterListener_AnyParameter;
+        assert_eq!(take_parameter_notifications(&unit),
+                   vec![(any, ffi::kAudioUnitScope_Global, 0)]);
+    }
+
+    #[test]
+    fn state_names_the_component_it_belongs_to() {
+        use ffi::{kAudioUnitManufacturer_Apple as APPLE, kAudioUnitType_Effect as EFFECT};
+        let eq = new_unit(EFFECT, ffi::kAudioUnitSubType_NBandEQ);
+        let state = eq.save_state().unwrap();
+        let check = |kind, sub_kind, manufacturer| {
+            let desc = AudioComponentDescription::new(kind, sub_kind, manufacturer);
+            state.check_component(&desc).is_ok()
+        };
+        assert!(check(EFFECT, ffi::kAudioUnitSubType_NBandEQ, APPLE));
+        assert!(check(EFFECT, 0, 0));
+        assert!(check(0, 0, 0));
+        assert!(!check(EFFECT, ffi::kAudioUnitSubType_LowPassFilter, APPLE));
+        assert!(!check(ffi::kAudioUnitType_Mixer, 0, 0));
+        assert!(!check(0, 0, ::FourCC::new(b"Test").into()));
+    }
+
+    #[test]
+    fn state_is_refused_by_other_components() {
+        let eq = new_unit(ffi::kAudioUnitType_Effect, ffi::kAudioUnitSubType_NBandEQ);
+        let filter = new_unit(ffi::kAudioUnitType_Effect,
+                              ffi::kAudioUnitSubType_LowPassFilter);
+        let state = eq.save_state().unwrap();
+        let mismatch = Err(Error::Preset(PresetError::ComponentMismatch));
+        assert_eq!(filter.restore_state(&state), mismatch);
+        assert!(take_parameter_notifications(&filter).is_empty());
+    }
+
+    #[test]
+    fn factory_presets_are_listed_and_selected() {
+        let unit = new_unit(ffi::kAudioUnitType_Effect,
+                            ffi::kAudioUnitSubType_MatrixReverb);
+        let presets = unit.factory_presets().unwrap();
+        assert_eq!(presets.len(), 7);
+        assert_eq!(presets[2], Preset { number: 2, name: "Large Room".to_owned() });
+
+        let selected = unit.set_present_preset("Plate").unwrap();
+        assert_eq!(selected.number, 5);
+        assert_eq!(unit.present_preset().unwrap(), selected);
+        assert_eq!(unit.get_parameter(2, GLOBAL, 0).unwrap(), 0.005);
+        let missing = unit.set_present_preset(99);
+        assert_eq!(missing, Err(Error::Preset(PresetError::NotFound)));
+
+        let filter = new_unit(ffi::kAudioUnitType_Effect,
+                              ffi::kAudioUnitSubType_LowPassFilter);
+        assert!(filter.factory_presets().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+//! Typed descriptors for audio unit properties.
+//!
+//! Each property is a unit struct carrying its ID, the Rust type of its
+//! value, the scopes it lives in and whether it can be read and/or written:
+//!
+//! ```no_run
+//! # use audio_toolbox::{props, AudioUnitRef, AudioUnitScope, Result};
+//! # fn f(unit: &AudioUnitRef) -> Result<()> {
+//! let rate: f64 = unit.get(props::SampleRate, AudioUnitScope::Output, 0)?;
+//! unit.set(props::SampleRate, AudioUnitScope::Input, 0, &rate)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Writing a read-only property doesn't compile:
+//!
+//! ```compile_fail
+//! # use audio_toolbox::{props, AudioUnitRef, AudioUnitScope, Result};
+//! # fn f(unit: &AudioUnitRef) -> Result<()> {
+//! unit.set(props::Latency, AudioUnitScope::Global, 0, &0.0)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `Core Foundation` values are returned as raw references that follow the
+//! AudioToolbox ownership rules for the property in question.
+
+use {AudioUnitProperty, AudioUnitRef, AudioUnitScope, Result};
+use AudioUnitScope::*;
+use ffi;
+use std::os::raw::c_void;
+
+pub trait Property: Copy {
+    type Value: PropertyValue;
+    const ID: AudioUnitProperty;
+    /// The scopes the property can be addressed in.
+    const SCOPES: &'static [AudioUnitScope];
+}
+
+/// A property that can be read with `AudioUnitRef::get`.
+pub trait Readable: Property {}
+
+/// A property that can be written with `AudioUnitRef::set`.
+pub trait Writable: Property {}
+
+/// An in/out property read with `AudioUnitRef::query`: the caller fills in
+/// part of the value before the unit completes it.
+pub trait Queryable: Property {}
+
+/// How a property value is moved across the AudioUnit API.
+pub trait PropertyValue: Sized {
+    fn get(
+        unit: &AudioUnitRef,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: u32,
+    ) -> Result<Self>;
+
+    fn set(
+        &self,
+        unit: &AudioUnitRef,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: u32,
+    ) -> Result<()>;
+
+    /// Have the unit complete a value the caller partly filled in.
+    fn query(
+        &mut self,
+        unit: &AudioUnitRef,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: u32,
+    ) -> Result<()>;
+}
+
+macro_rules! fixed_size_values {
+    ($($t:ty),* $(,)*) => {
+        $(
+            impl PropertyValue for $t {
+                fn get(
+                    unit: &AudioUnitRef,
+                    id: AudioUnitProperty,
+                    scope: AudioUnitScope,
+                    element: u32,
+                ) -> Result<Self> {
+                    unit.get_property(id, scope, element)
+                }
+
+                fn set(
+                    &self,
+                    unit: &AudioUnitRef,
+                    id: AudioUnitProperty,
+                    scope: AudioUnitScope,
+                    element: u32,
+                ) -> Result<()> {
+                    unit.set_property(id, scope, element, self)
+                }
+
+                fn query(
+                    &mut self,
+                    unit: &AudioUnitRef,
+                    id: AudioUnitProperty,
+                    scope: AudioUnitScope,
+                    element: u32,
+                ) -> Result<()> {
+                    unit.query_property(id, scope, element, self)
+                }
+            }
+        )*
+    }
+}
+
+fixed_size_values! {
+    u32,
+    i32,
+    f32,
+    f64,
+    [u32; 2],
+    *mut c_void,
+    ffi::CFArrayRef,
+    ffi::CFStringRef,
+    ffi::CFTypeRef,
+    ffi::CFURLRef,
+    ffi::AudioComponentDescription,
+    ffi::AudioOutputUnitStartAtTimeParams,
+    ffi::AudioStreamBasicDescription,
+    ffi::AudioTimeStamp,
+    ffi::AudioUnitConnection,
+    ffi::AudioUnitExternalBuffer,
+    ffi::AudioUnitFrequencyResponseBin,
+    ffi::AudioUnitMeterClipping,
+    ffi::AudioUnitParameterHistoryInfo,
+    ffi::AudioUnitParameterInfo,
+    ffi::AudioUnitParameterNameInfo,
+    ffi::AudioUnitParameterStringFromValue,
+    ffi::AudioUnitParameterValueFromString,
+    ffi::AUChannelInfo,
+    ffi::AUDependentParameter,
+    ffi::AUHostVersionIdentifier,
+    ffi::AUInputSamplesInOutputCallbackStruct,
+    ffi::AUMIDIOutputCallbackStruct,
+    ffi::AUParameterMIDIMapping,
+    ffi::AUPreset,
+    ffi::AURenderCallbackStruct,
+    ffi::HostCallbackInfo,
+    ffi::MixerDistanceParams,
+    ffi::ScheduledAudioFileRegion,
+    ffi::ScheduledAudioSlice,
+}
+
+#[cfg(feature = "deprecated")]
+fixed_size_values! {
+    ffi::AudioUnitParameterValueName,
+}
+
+/// Arrays, and the raw bytes of variable-length values as `Vec<u8>`.
+impl<T: Copy> PropertyValue for Vec<T> {
+    fn get(
+        unit: &AudioUnitRef,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: u32,
+    ) -> Result<Self> {
+        unit.get_property_array(id, scope, element)
+    }
+
+    fn set(
+        &self,
+        unit: &AudioUnitRef,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: u32,
+    ) -> Result<()> {
+        unit.set_property_array(id, scope, element, self)
+    }
+
+    /// The unit fills in the array as sized by the caller, and it is
+    /// truncated to the items the unit wrote.
+    fn query(
+        &mut self,
+        unit: &AudioUnitRef,
+        id: AudioUnitProperty,
+        scope: AudioUnitScope,
+        element: u32,
+    ) -> Result<()> {
+        unit.query_property_array(id, scope, element, self)
+    }
+}
+
+const GLOBAL: &'static [AudioUnitScope] = &[Global];
+const INPUT: &'static [AudioUnitScope] = &[Input];
+const IO: &'static [AudioUnitScope] = &[Input, Output];
+const IO_GLOBAL: &'static [AudioUnitScope] = &[Global, Input, Output];
+const ANY: &'static [AudioUnitScope] =
+    &[Global, Input, Output, Group, Part, Note, Layer, LayerItem];
+
+macro_rules! access {
+    ($name:ident, Read) => {
+        impl Readable for $name {}
+    };
+    ($name:ident, Write) => {
+        impl Writable for $name {}
+    };
+    ($name:ident, ReadWrite) => {
+        impl Readable for $name {}
+        impl Writable for $name {}
+    };
+    ($name:ident, Query) => {
+        impl Queryable for $name {}
+    };
+}
+
+macro_rules! properties {
+    ($(
+        $(#[doc = $doc:expr])*
+        $(#[cfg($cfg:meta)])*
+        $name:ident: $value:ty = $id:ident, $scopes:ident, $access:ident;
+    )*) => {
+        $(
+            $(#[doc = $doc])*
+            $(#[cfg($cfg)])*
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct $name;
+
+            $(#[cfg($cfg)])*
+            impl Property for $name {
+                type Value = $value;
+                const ID: AudioUnitProperty = ffi::$id;
+                const SCOPES: &'static [AudioUnitScope] = $scopes;
+            }
+
+            $(#[cfg($cfg)])*
+            access!($name, $access);
+        )*
+    }
+}
+
+properties! {
+    /// The unit's state as a `CFPropertyList`, as saved in presets.
+    ClassInfo: ffi::CFPropertyListRef = kAudioUnitProperty_ClassInfo, GLOBAL, ReadWrite;
+    /// Connect an input element to another unit's output.
+    MakeConnection: ffi::AudioUnitConnection =
+        kAudioUnitProperty_MakeConnection, INPUT, ReadWrite;
+    SampleRate: f64 = kAudioUnitProperty_SampleRate, IO, ReadWrite;
+    ParameterList: Vec<ffi::AudioUnitParameterID> =
+        kAudioUnitProperty_ParameterList, ANY, Read;
+    /// Addressed by parameter ID in place of the element.
+    ParameterInfo: ffi::AudioUnitParameterInfo =
+        kAudioUnitProperty_ParameterInfo, ANY, Read;
+    CPULoad: f64 = kAudioUnitProperty_CPULoad, GLOBAL, ReadWrite;
+    StreamFormat: ffi::AudioStreamBasicDescription =
+        kAudioUnitProperty_StreamFormat, IO_GLOBAL, ReadWrite;
+    ElementCount: u32 = kAudioUnitProperty_ElementCount, ANY, ReadWrite;
+    /// Seconds.
+    Latency: f64 = kAudioUnitProperty_Latency, GLOBAL, Read;
+    SupportedNumChannels: Vec<ffi::AUChannelInfo> =
+        kAudioUnitProperty_SupportedNumChannels, GLOBAL, Read;
+    MaximumFramesPerSlice: u32 =
+        kAudioUnitProperty_MaximumFramesPerSlice, GLOBAL, ReadWrite;
+    /// Addressed by parameter ID in place of the element.
+    ParameterValueStrings: ffi::CFArrayRef =
+        kAudioUnitProperty_ParameterValueStrings, ANY, Read;
+    /// A variable-length `AudioChannelLayout`.
+    AudioChannelLayout: Vec<u8> = kAudioUnitProperty_AudioChannelLayout, IO, ReadWrite;
+    /// Seconds.
+    TailTime: f64 = kAudioUnitProperty_TailTime, GLOBAL, Read;
+    BypassEffect: u32 = kAudioUnitProperty_BypassEffect, GLOBAL, ReadWrite;
+    LastRenderError: ffi::OSStatus = kAudioUnitProperty_LastRenderError, GLOBAL, Read;
+    SetRenderCallback: ffi::AURenderCallbackStruct =
+        kAudioUnitProperty_SetRenderCallback, INPUT, Write;
+    /// A `CFArray` of `AUPreset`.
+    FactoryPresets: ffi::CFArrayRef = kAudioUnitProperty_FactoryPresets, GLOBAL, Read;
+    RenderQuality: u32 = kAudioUnitProperty_RenderQuality, GLOBAL, ReadWrite;
+    HostCallbacks: ffi::HostCallbackInfo =
+        kAudioUnitProperty_HostCallbacks, GLOBAL, Write;
+    InPlaceProcessing: u32 = kAudioUnitProperty_InPlaceProcessing, GLOBAL, ReadWrite;
+    ElementName: ffi::CFStringRef = kAudioUnitProperty_ElementName, ANY, ReadWrite;
+    SupportedChannelLayoutTags: Vec<u32> =
+        kAudioUnitProperty_SupportedChannelLayoutTags, IO, Read;
+    PresentPreset: ffi::AUPreset = kAudioUnitProperty_PresentPreset, GLOBAL, ReadWrite;
+    /// Addressed by parameter ID in place of the element.
+    DependentParameters: Vec<ffi::AUDependentParameter> =
+        kAudioUnitProperty_DependentParameters, ANY, Read;
+    InputSamplesInOutput: ffi::AUInputSamplesInOutputCallbackStruct =
+        kAudioUnitProperty_InputSamplesInOutput, GLOBAL, Write;
+    ShouldAllocateBuffer: u32 = kAudioUnitProperty_ShouldAllocateBuffer, IO, ReadWrite;
+    /// The caller fills in the frequencies of `kNumberOfResponseFrequencies`
+    /// bins.
+    FrequencyResponse: Vec<ffi::AudioUnitFrequencyResponseBin> =
+        kAudioUnitProperty_FrequencyResponse, GLOBAL, Query;
+    ParameterHistoryInfo: ffi::AudioUnitParameterHistoryInfo =
+        kAudioUnitProperty_ParameterHistoryInfo, GLOBAL, Read;
+    NickName: ffi::CFStringRef = kAudioUnitProperty_NickName, GLOBAL, ReadWrite;
+    OfflineRender: u32 = kAudioUnitProperty_OfflineRender, GLOBAL, ReadWrite;
+    ParameterIDName: ffi::AudioUnitParameterNameInfo =
+        kAudioUnitProperty_ParameterIDName, ANY, Query;
+    ParameterStringFromValue: ffi::AudioUnitParameterStringFromValue =
+        kAudioUnitProperty_ParameterStringFromValue, ANY, Query;
+    ParameterClumpName: ffi::AudioUnitParameterNameInfo =
+        kAudioUnitProperty_ParameterClumpName, ANY, Query;
+    ParameterValueFromString: ffi::AudioUnitParameterValueFromString =
+        kAudioUnitProperty_ParameterValueFromString, ANY, Query;
+    ContextName: ffi::CFStringRef = kAudioUnitProperty_ContextName, GLOBAL, ReadWrite;
+    /// Seconds.
+    PresentationLatency: f64 = kAudioUnitProperty_PresentationLatency, IO, ReadWrite;
+    ClassInfoFromDocument: ffi::CFPropertyListRef =
+        kAudioUnitProperty_ClassInfoFromDocument, GLOBAL, Write;
+    /// Takes an `AUHostViewControllerBlock`.
+    RequestViewController: *mut c_void =
+        kAudioUnitProperty_RequestViewController, GLOBAL, Write;
+    /// The caller sizes the array for the number of parameters wanted.
+    ParametersForOverview: Vec<ffi::AudioUnitParameterNameInfo> =
+        kAudioUnitProperty_ParametersForOverview, GLOBAL, Query;
+    SupportsMPE: u32 = kAudioUnitProperty_SupportsMPE, GLOBAL, Read;
+    /// The caller passes the selector of the function it wants.
+    FastDispatch: *mut c_void = kAudioUnitProperty_FastDispatch, GLOBAL, Query;
+    SetExternalBuffer: ffi::AudioUnitExternalBuffer =
+        kAudioUnitProperty_SetExternalBuffer, IO, Write;
+    GetUIComponentList: Vec<ffi::AudioComponentDescription> =
+        kAudioUnitProperty_GetUIComponentList, GLOBAL, Read;
+    /// A variable-length `AudioUnitCocoaViewInfo`.
+    CocoaUI: Vec<u8> = kAudioUnitProperty_CocoaUI, GLOBAL, Read;
+    IconLocation: ffi::CFURLRef = kAudioUnitProperty_IconLocation, GLOBAL, Read;
+    AUHostIdentifier: ffi::AUHostVersionIdentifier =
+        kAudioUnitProperty_AUHostIdentifier, GLOBAL, Write;
+    MIDIOutputCallbackInfo: ffi::CFArrayRef =
+        kAudioUnitProperty_MIDIOutputCallbackInfo, GLOBAL, Read;
+    MIDIOutputCallback: ffi::AUMIDIOutputCallbackStruct =
+        kAudioUnitProperty_MIDIOutputCallback, GLOBAL, Write;
+
+    // Output units
+    CurrentDevice: u32 = kAudioOutputUnitProperty_CurrentDevice, GLOBAL, ReadWrite;
+    IsRunning: u32 = kAudioOutputUnitProperty_IsRunning, GLOBAL, Read;
+    ChannelMap: Vec<i32> = kAudioOutputUnitProperty_ChannelMap, IO, ReadWrite;
+    EnableIO: u32 = kAudioOutputUnitProperty_EnableIO, IO, ReadWrite;
+    StartTime: ffi::AudioOutputUnitStartAtTimeParams =
+        kAudioOutputUnitProperty_StartTime, GLOBAL, Write;
+    SetInputCallback: ffi::AURenderCallbackStruct =
+        kAudioOutputUnitProperty_SetInputCallback, GLOBAL, Write;
+    HasIO: u32 = kAudioOutputUnitProperty_HasIO, IO, Read;
+    StartTimestampsAtZero: u32 =
+        kAudioOutputUnitProperty_StartTimestampsAtZero, GLOBAL, ReadWrite;
+
+    // MIDI mapping
+    AllParameterMIDIMappings: Vec<ffi::AUParameterMIDIMapping> =
+        kAudioUnitProperty_AllParameterMIDIMappings, GLOBAL, ReadWrite;
+    AddParameterMIDIMapping: Vec<ffi::AUParameterMIDIMapping> =
+        kAudioUnitProperty_AddParameterMIDIMapping, GLOBAL, Write;
+    RemoveParameterMIDIMapping: Vec<ffi::AUParameterMIDIMapping> =
+        kAudioUnitProperty_RemoveParameterMIDIMapping, GLOBAL, Write;
+    HotMapParameterMIDIMapping: ffi::AUParameterMIDIMapping =
+        kAudioUnitProperty_HotMapParameterMIDIMapping, GLOBAL, ReadWrite;
+
+    // Format converters
+    SampleRateConverterComplexity: u32 =
+        kAudioUnitProperty_SampleRateConverterComplexity, GLOBAL, ReadWrite;
+
+    // Mixers
+    MeteringMode: u32 = kAudioUnitProperty_MeteringMode, ANY, ReadWrite;
+    MatrixLevels: Vec<f32> = kAudioUnitProperty_MatrixLevels, GLOBAL, Read;
+    /// Number of input and output elements.
+    MatrixDimensions: [u32; 2] = kAudioUnitProperty_MatrixDimensions, GLOBAL, Read;
+    MeterClipping: ffi::AudioUnitMeterClipping =
+        kAudioUnitProperty_MeterClipping, ANY, Read;
+    InputAnchorTimeStamp: ffi::AudioTimeStamp =
+        kAudioUnitProperty_InputAnchorTimeStamp, INPUT, ReadWrite;
+    ReverbRoomType: u32 = kAudioUnitProperty_ReverbRoomType, GLOBAL, ReadWrite;
+    UsesInternalReverb: u32 = kAudioUnitProperty_UsesInternalReverb, GLOBAL, ReadWrite;
+    SpatializationAlgorithm: u32 =
+        kAudioUnitProperty_SpatializationAlgorithm, INPUT, ReadWrite;
+    SpatialMixerDistanceParams: ffi::MixerDistanceParams =
+        kAudioUnitProperty_SpatialMixerDistanceParams, INPUT, ReadWrite;
+    SpatialMixerAttenuationCurve: u32 =
+        kAudioUnitProperty_SpatialMixerAttenuationCurve, INPUT, ReadWrite;
+    SpatialMixerRenderingFlags: u32 =
+        kAudioUnitProperty_SpatialMixerRenderingFlags, INPUT, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    MixerDistanceParams: ffi::MixerDistanceParams =
+        kAudioUnitProperty_3DMixerDistanceParams, INPUT, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    MixerAttenuationCurve: u32 =
+        kAudioUnitProperty_3DMixerAttenuationCurve, INPUT, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    MixerRenderingFlags: u32 = kAudioUnitProperty_3DMixerRenderingFlags, INPUT, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    DopplerShift: u32 = kAudioUnitProperty_DopplerShift, INPUT, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    MixerDistanceAtten: f64 = kAudioUnitProperty_3DMixerDistanceAtten, INPUT, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    ReverbPreset: ffi::CFURLRef = kAudioUnitProperty_ReverbPreset, GLOBAL, ReadWrite;
+    #[cfg(feature = "deprecated")]
+    DistanceAttenuationData: Vec<u8> =
+        kAudioUnitProperty_DistanceAttenuationData, GLOBAL, ReadWrite;
+
+    // Scheduled sound and audio file players
+    ScheduleAudioSlice: ffi::ScheduledAudioSlice =
+        kAudioUnitProperty_ScheduleAudioSlice, GLOBAL, Write;
+    ScheduleStartTimeStamp: ffi::AudioTimeStamp =
+        kAudioUnitProperty_ScheduleStartTimeStamp, GLOBAL, Write;
+    CurrentPlayTime: ffi::AudioTimeStamp =
+        kAudioUnitProperty_CurrentPlayTime, GLOBAL, Read;
+    /// An array of `AudioFileID`.
+    ScheduledFileIDs: Vec<*mut ffi::OpaqueAudioFileID> =
+        kAudioUnitProperty_ScheduledFileIDs, GLOBAL, Write;
+    ScheduledFileRegion: ffi::ScheduledAudioFileRegion =
+        kAudioUnitProperty_ScheduledFileRegion, GLOBAL, Write;
+    ScheduledFilePrime: u32 = kAudioUnitProperty_ScheduledFilePrime, GLOBAL, Write;
+    ScheduledFileBufferSizeFrames: u32 =
+        kAudioUnitProperty_ScheduledFileBufferSizeFrames, GLOBAL, Write;
+    ScheduledFileNumberBuffers: u32 =
+        kAudioUnitProperty_ScheduledFileNumberBuffers, GLOBAL, Write;
+
+    // Deferred renderer
+    DeferredRendererPullSize: u32 =
+        kAudioUnitProperty_DeferredRendererPullSize, GLOBAL, ReadWrite;
+    DeferredRendererExtraLatency: u32 =
+        kAudioUnitProperty_DeferredRendererExtraLatency, GLOBAL, ReadWrite;
+    DeferredRendererWaitFrames: u32 =
+        kAudioUnitProperty_DeferredRendererWaitFrames, GLOBAL, ReadWrite;
+
+    // Deprecated
+    #[cfg(feature = "deprecated")]
+    SetInputCallbackDeprecated: ffi::AURenderCallbackStruct =
+        kAudioUnitProperty_SetInputCallback, GLOBAL, Write;
+    /// `ParameterStringFromValue` under its old name and struct.
+    #[cfg(feature = "deprecated")]
+    ParameterValueName: ffi::AudioUnitParameterValueName =
+        kAudioUnitProperty_ParameterValueName, ANY, Query;
+    SRCAlgorithm: u32 = kAudioUnitProperty_SRCAlgorithm, GLOBAL, ReadWrite;
+    MIDIControlMapping: Vec<u8> = kAudioUnitProperty_MIDIControlMapping, GLOBAL, Read;
+    CurrentPreset: ffi::AUPreset = kAudioUnitProperty_CurrentPreset, GLOBAL, ReadWrite;
+    PannerMode: u32 = kAudioUnitProperty_PannerMode, GLOBAL, ReadWrite;
+    SpeakerConfiguration: u32 =
+        kAudioUnitProperty_SpeakerConfiguration, GLOBAL, ReadWrite;
+}
@@ -0,0 +1,263 @@
+//! Core Foundation objects for the simulator.
+//!
+//! Only what the simulated units hand out is modelled: reference-counted
+//! values behind opaque pointers, with the same ownership rules as the real
+//! framework.
+
+use ffi;
+use plist;
+use std::collections::BTreeMap;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub enum Value {
+    String(String),
+    /// Owns one reference to each element.
+    Array(Vec<ffi::CFTypeRef>),
+    /// An array of `AUPreset`s rather than objects, as units hand out for
+    /// `kAudioUnitProperty_FactoryPresets`. Owns each preset's name.
+    Presets(Vec<ffi::AUPreset>),
+    Data(Vec<u8>),
+    /// Any other property list, such as a unit's `ClassInfo`, kept whole.
+    PropertyList(plist::Value),
+}
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        match *self {
+            Value::Array(ref items) => for &item in items {
+                unsafe { CFRelease(item) };
+            },
+            Value::Presets(ref presets) => for preset in presets {
+                unsafe { CFRelease(preset.presetName as ffi::CFTypeRef) };
+            },
+            _ => {},
+        }
+    }
+}
+
+struct Object {
+    refs: AtomicUsize,
+    value: Value,
+}
+
+/// A new object with a retain count of one, owned by the caller.
+pub fn create(value: Value) -> ffi::CFTypeRef {
+    let object = Box::new(Object {
+        refs: AtomicUsize::new(1),
+        value,
+    });
+    Box::into_raw(object) as ffi::CFTypeRef
+}
+
+pub fn string(s: &str) -> ffi::CFStringRef {
+    create(Value::String(s.to_owned())) as ffi::CFStringRef
+}
+
+/// An array taking over the caller's reference to each of `items`.
+pub fn array(items: Vec<ffi::CFTypeRef>) -> ffi::CFArrayRef {
+    create(Value::Array(items)) as ffi::CFArrayRef
+}
+
+/// An array of `presets`, taking over the caller's reference to each name.
+pub fn presets(presets: Vec<ffi::AUPreset>) -> ffi::CFArrayRef {
+    create(Value::Presets(presets)) as ffi::CFArrayRef
+}
+
+pub fn property_list(value: plist::Value) -> ffi::CFPropertyListRef {
+    create(Value::PropertyList(value))
+}
+
+unsafe fn value<'a>(cf: ffi::CFTypeRef) -> &'a Value {
+    assert!(!cf.is_null(), "null CFTypeRef");
+    &(*(cf as *const Object)).value
+}
+
+pub unsafe fn as_str<'a>(s: ffi::CFStringRef) -> &'a str {
+    match *value(s as ffi::CFTypeRef) {
+        Value::String(ref s) => s,
+        _ => panic!("not a CFString"),
+    }
+}
+
+
+/// Copy any object into a `plist::Value`.
+pub unsafe fn to_plist(cf: ffi::CFTypeRef) -> plist::Value {
+    match *value(cf) {
+        Value::String(ref s) => plist::Value::String(s.clone()),
+        Value::Array(ref items) => {
+            plist::Value::Array(items.iter().map(|&item| to_plist(item)).collect())
+        },
+        Value::Presets(_) => panic!("not a property list"),
+        Value::Data(ref data) => plist::Value::Data(data.clone()),
+        Value::PropertyList(ref value) => value.clone(),
+    }
+}
+
+pub unsafe fn CFRelease(cf: ffi::CFTypeRef) {
+    assert!(!cf.is_null(), "CFRelease called with NULL");
+    let object = cf as *mut Object;
+    if (*object).refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+        drop(Box::from_raw(object));
+    }
+}
+
+pub unsafe fn CFArrayGetCount(theArray: ffi::CFArrayRef) -> ffi::CFIndex {
+    match *value(theArray as ffi::CFTypeRef) {
+        Value::Array(ref items) => items.len() as ffi::CFIndex,
+        Value::Presets(ref presets) => presets.len() as ffi::CFIndex,
+        _ => panic!("not a CFArray"),
+    }
+}
+
+pub unsafe fn CFArrayGetValueAtIndex(
+    theArray: ffi::CFArrayRef,
+    idx: ffi::CFIndex,
+) -> *const c_void {
+    match *value(theArray as ffi::CFTypeRef) {
+        Value::Array(ref items) => items[idx as usize],
+        Value::Presets(ref presets) => {
+            &presets[idx as usize] as *const ffi::AUPreset as *const c_void
+        },
+        _ => panic!("not a CFArray"),
+    }
+}
+
+pub unsafe fn CFDataCreate(
+    _allocator: ffi::CFAllocatorRef,
+    bytes: *const u8,
+    length: ffi::CFIndex,
+) -> ffi::CFDataRef {
+    let data = slice::from_raw_parts(bytes, length as usize).to_vec();
+    create(Value::Data(data)) as ffi::CFDataRef
+}
+
+unsafe fn as_data<'a>(d: ffi::CFDataRef) -> &'a [u8] {
+    match *value(d as ffi::CFTypeRef) {
+        Value::Data(ref data) => data,
+        _ => panic!("not a CFData"),
+    }
+}
+
+pub unsafe fn CFDataGetLength(theData: ffi::CFDataRef) -> ffi::CFIndex {
+    as_data(theData).len() as ffi::CFIndex
+}
+
+pub unsafe fn CFDataGetBytePtr(theData: ffi::CFDataRef) -> *const u8 {
+    as_data(theData).as_ptr()
+}
+
+unsafe fn as_dictionary<'a>(
+    d: ffi::CFDictionaryRef,
+) -> &'a mut BTreeMap<String, plist::Value> {
+    match (*(d as *mut Object)).value {
+        Value::PropertyList(plist::Value::Dictionary(ref mut dict)) => dict,
+        _ => panic!("not a CFDictionary"),
+    }
+}
+
+pub unsafe fn CFDictionaryCreateMutableCopy(
+    _allocator: ffi::CFAllocatorRef,
+    _capacity: ffi::CFIndex,
+    theDict: ffi::CFDictionaryRef,
+) -> ffi::CFMutableDictionaryRef {
+    let dict = as_dictionary(theDict).clone();
+    property_list(plist::Value::Dictionary(dict)) as ffi::CFMutableDictionaryRef
+}
+
+/// Keys must be `CFString`s.
+pub unsafe fn CFDictionaryRemoveValue(
+    theDict: ffi::CFMutableDictionaryRef,
+    key: *const c_void,
+) {
+    as_dictionary(theDict).remove(as_str(key as ffi::CFStringRef));
+}
+
+/// Only the binary format is supported; errors aren't reported through
+/// `error`.
+pub unsafe fn CFPropertyListCreateData(
+    _allocator: ffi::CFAllocatorRef,
+    propertyList: ffi::CFPropertyListRef,
+    format: ffi::CFPropertyListFormat,
+    _options: ffi::CFOptionFlags,
+    error: *mut ffi::CFErrorRef,
+) -> ffi::CFDataRef {
+    assert_eq!(format, ffi::kCFPropertyListBinaryFormat_v1_0);
+    if !error.is_null() {
+        *error = ptr::null();
+    }
+    let data = plist::to_binary(&to_plist(propertyList));
+    create(Value::Data(data)) as ffi::CFDataRef
+}
+
+pub unsafe fn CFPropertyListCreateWithData(
+    _allocator: ffi::CFAllocatorRef,
+    data: ffi::CFDataRef,
+    _options: ffi::CFOptionFlags,
+    format: *mut ffi::CFPropertyListFormat,
+    error: *mut ffi::CFErrorRef,
+) -> ffi::CFPropertyListRef {
+    if !error.is_null() {
+        *error = ptr::null();
+    }
+    let bytes = as_data(data);
+    match plist::from_bytes(bytes) {
+        Ok(value) => {
+            if !format.is_null() {
+                *format = if bytes.starts_with(b"bplist") {
+                    ffi::kCFPropertyListBinaryFormat_v1_0
+                } else {
+                    ffi::kCFPropertyListXMLFormat_v1_0
+                };
+            }
+            property_list(value)
+        },
+        Err(_) => ptr::null(),
+    }
+}
+
+pub unsafe fn CFStringCreateWithBytes(
+    _alloc: ffi::CFAllocatorRef,
+    bytes: *const u8,
+    numBytes: ffi::CFIndex,
+    encoding: ffi::CFStringEncoding,
+    _isExternalRepresentation: ffi::Boolean,
+) -> ffi::CFStringRef {
+    assert_eq!(encoding, ffi::kCFStringEncodingUTF8);
+    match str::from_utf8(slice::from_raw_parts(bytes, numBytes as usize)) {
+        Ok(s) => string(s),
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Length in UTF-16 code units, as Core Foundation counts it.
+pub unsafe fn CFStringGetLength(theString: ffi::CFStringRef) -> ffi::CFIndex {
+    as_str(theString).encode_utf16().count() as ffi::CFIndex
+}
+
+pub unsafe fn CFStringGetMaximumSizeForEncoding(
+    length: ffi::CFIndex,
+    encoding: ffi::CFStringEncoding,
+) -> ffi::CFIndex {
+    assert_eq!(encoding, ffi::kCFStringEncodingUTF8);
+    length * 3
+}
+
+pub unsafe fn CFStringGetCString(
+    theString: ffi::CFStringRef,
+    buffer: *mut c_char,
+    bufferSize: ffi::CFIndex,
+    encoding: ffi::CFStringEncoding,
+) -> ffi::Boolean {
+    assert_eq!(encoding, ffi::kCFStringEncodingUTF8);
+    let s = as_str(theString);
+    if buffer.is_null() || s.len() as ffi::CFIndex >= bufferSize {
+        return 0;
+    }
+    ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buffer, s.len());
+    *buffer.offset(s.len() as isize) = 0;
+    1
+}
@@ -0,0 +1,528 @@
+//! Component registry for the simulator.
+
+use component_description_matches;
+use ffi;
+use plist;
+use super::unit::{DEFAULT_CHANNELS, SUPPORTED_LAYOUT_TAGS};
+use std::collections::BTreeMap;
+use std::ptr;
+use std::sync::Mutex;
+
+/// Description of a parameter published by a simulated unit.
+#[derive(Clone, Copy, Debug)]
+pub struct ParameterSpec {
+    pub id: ffi::AudioUnitParameterID,
+    pub scope: ffi::AudioUnitScope,
+    pub name: &'static str,
+    pub unit: ffi::AudioUnitParameterUnit,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub flags: u32,
+    /// Names for each value of an indexed parameter, starting at `min`.
+    pub value_strings: &'static [&'static str],
+}
+
+impl ParameterSpec {
+    /// The text the unit displays for `value`.
+    pub fn display(&self, value: f32) -> String {
+        let index = (value - self.min).round();
+        match self.value_strings.get(index as usize) {
+            Some(s) if index >= 0.0 => s.to_string(),
+            _ => format!("{:.2}", value),
+        }
+    }
+
+    /// The value for text as displayed, or typed in by a user.
+    pub fn parse(&self, text: &str) -> Option<f32> {
+        match self.value_strings.iter().position(|&s| s == text) {
+            Some(index) => Some(self.min + index as f32),
+            None => text.trim().parse().ok(),
+        }
+    }
+}
+
+/// A factory preset: global parameter values that differ from the
+/// defaults.
+#[derive(Clone, Copy, Debug)]
+pub struct FactoryPreset {
+    pub name: &'static str,
+    pub values: &'static [(ffi::AudioUnitParameterID, f32)],
+}
+
+/// A component known to the simulator. Components are never removed from
+/// the registry, so a `*const SimComponent` handed out as an
+/// `AudioComponent` stays valid for the life of the process.
+pub struct SimComponent {
+    pub desc: ffi::AudioComponentDescription,
+    /// "Manufacturer: Name", as `AudioComponentCopyName` returns it.
+    pub name: String,
+    pub version: u32,
+    pub parameters: Vec<ParameterSpec>,
+    pub presets: Vec<FactoryPreset>,
+    /// Published as `kAudioUnitProperty_SupportedNumChannels` if not empty.
+    pub channel_configs: Vec<ffi::AUChannelInfo>,
+    /// `kAudioUnitProperty_Latency` and `kAudioUnitProperty_TailTime`, in
+    /// seconds.
+    pub latency: f64,
+    pub tail_time: f64,
+    /// The result of the last `AudioComponentValidate`, if there was one.
+    pub validation: Mutex<Option<ffi::AudioComponentValidationResult>>,
+}
+
+impl SimComponent {
+    pub fn as_ptr(&self) -> ffi::AudioComponent {
+        self as *const SimComponent as *mut SimComponent as ffi::AudioComponent
+    }
+
+    pub unsafe fn from_ptr<'a>(ptr: ffi::AudioComponent) -> &'a SimComponent {
+        &*(ptr as *const SimComponent)
+    }
+
+    pub fn kind(&self) -> ffi::OSType {
+        self.desc.componentType
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.kind() == ffi::kAudioUnitType_Output
+    }
+
+    /// Output units that talk to a device and so have an input element.
+    pub fn has_device_io(&self) -> bool {
+        self.is_output() && match self.desc.componentSubType {
+            ffi::kAudioUnitSubType_HALOutput | ffi::kAudioUnitSubType_VoiceProcessingIO => {
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Number of (input, output) elements a fresh instance starts with.
+    pub fn initial_buses(&self) -> (u32, u32) {
+        match self.kind() {
+            _ if self.has_device_io() => (2, 2),
+            ffi::kAudioUnitType_Mixer => (8, 1),
+            ffi::kAudioUnitType_Generator | ffi::kAudioUnitType_MusicDevice => (0, 1),
+            _ => (1, 1),
+        }
+    }
+
+    pub fn input_bus_count_writable(&self) -> bool {
+        self.kind() == ffi::kAudioUnitType_Mixer
+    }
+
+    /// Whether rendering pulls audio from the input elements.
+    pub fn pulls_input(&self) -> bool {
+        match self.kind() {
+            ffi::kAudioUnitType_Generator | ffi::kAudioUnitType_MusicDevice => false,
+            _ => true,
+        }
+    }
+
+    /// Whether rendering without a connected input is an error rather than
+    /// silence.
+    pub fn requires_input(&self) -> bool {
+        match self.kind() {
+            ffi::kAudioUnitType_Effect |
+            ffi::kAudioUnitType_MusicEffect |
+            ffi::kAudioUnitType_FormatConverter |
+            ffi::kAudioUnitType_OfflineEffect |
+            ffi::kAudioUnitType_Panner => true,
+            _ => false,
+        }
+    }
+
+    /// The channel configurations `initialize` accepts, if it checks them.
+    /// Effects that publish none need matching input and output counts.
+    pub fn accepted_channel_configs(&self) -> Option<Vec<ffi::AUChannelInfo>> {
+        if !self.channel_configs.is_empty() {
+            Some(self.channel_configs.clone())
+        } else if self.requires_input() {
+            Some(vec![ffi::AUChannelInfo { inChannels: -1, outChannels: -1 }])
+        } else {
+            None
+        }
+    }
+
+    /// The dictionary `AudioComponentCopyConfigurationInfo` returns.
+    pub fn configuration_info(&self) -> plist::Value {
+        use plist::Value::{Array, Boolean, Dictionary, Integer};
+        let (inputs, outputs) = self.initial_buses();
+        let channels = |buses| integers((0..buses).map(|_| DEFAULT_CHANNELS as i64));
+        let tags = || integers(SUPPORTED_LAYOUT_TAGS.iter().map(|&tag| tag as i64));
+
+        let mut info = BTreeMap::new();
+        info.insert(ffi::kAudioUnitConfigurationInfo_HasCustomView.to_owned(),
+                    Boolean(false));
+        if let Some(configs) = self.accepted_channel_configs() {
+            let configs = configs
+                .iter()
+                .map(|c| integers(vec![c.inChannels as i64, c.outChannels as i64]))
+                .collect();
+            info.insert(ffi::kAudioUnitConfigurationInfo_ChannelConfigurations.to_owned(),
+                        Array(configs));
+        }
+        info.insert(ffi::kAudioUnitConfigurationInfo_InitialInputs.to_owned(),
+                    channels(inputs));
+        info.insert(ffi::kAudioUnitConfigurationInfo_InitialOutputs.to_owned(),
+                    channels(outputs));
+        // Global, input and output scopes.
+        let writable = vec![Boolean(false), Boolean(self.input_bus_count_writable()),
+                            Boolean(false)];
+        info.insert(ffi::kAudioUnitConfigurationInfo_BusCountWritable.to_owned(),
+                    Array(writable));
+        let mut layouts = BTreeMap::new();
+        if inputs > 0 {
+            layouts.insert("Input".to_owned(), tags());
+        }
+        layouts.insert("Output".to_owned(), tags());
+        let key = ffi::kAudioUnitConfigurationInfo_SupportedChannelLayoutTags;
+        info.insert(key.to_owned(), Dictionary(layouts));
+        if let Some(result) = *self.validation.lock().unwrap() {
+            info.insert(ffi::kAudioComponentConfigurationInfo_ValidationResult.to_owned(),
+                        Integer(result as i64));
+        }
+        Dictionary(info)
+    }
+
+    pub fn matches(&self, desc: &ffi::AudioComponentDescription) -> bool {
+        component_description_matches(desc, &self.desc)
+    }
+}
+
+fn integers<I: IntoIterator<Item = i64>>(values: I) -> plist::Value {
+    plist::Value::Array(values.into_iter().map(plist::Value::Integer).collect())
+}
+
+lazy_static! {
+    static ref REGISTRY: Mutex<Vec<Box<SimComponent>>> = Mutex::new(builtin());
+}
+
+/// Find the first component after `after` (or from the start, if null)
+/// that matches `desc`.
+pub fn find_next(
+    after: ffi::AudioComponent,
+    desc: &ffi::AudioComponentDescription,
+) -> ffi::AudioComponent {
+    let registry = REGISTRY.lock().unwrap();
+    let start = if after.is_null() {
+        0
+    } else {
+        match registry.iter().position(|c| c.as_ptr() == after) {
+            Some(i) => i + 1,
+            None => return ptr::null_mut(),
+        }
+    };
+    registry[start..]
+        .iter()
+        .find(|c| c.matches(desc))
+        .map_or(ptr::null_mut(), |c| c.as_ptr())
+}
+
+pub fn count(desc: &ffi::AudioComponentDescription) -> usize {
+    REGISTRY.lock().unwrap().iter().filter(|c| c.matches(desc)).count()
+}
+
+/// Add a third-party effect taking `channel_configs` to the catalog, for
+/// tests that need a component no other test touches.
+#[cfg(test)]
+pub fn register_effect(channel_configs: &[(i16, i16)]) -> ffi::AudioComponent {
+    let mut component = with_channel_configs(
+        apple(ffi::kAudioUnitType_Effect, ::FourCC::new(b"test").into(), "Test"),
+        channel_configs,
+    );
+    component.desc.componentManufacturer = ::FourCC::new(b"Test").into();
+    component.name = "Test: Test".to_owned();
+    let component = Box::new(component);
+    let ptr = component.as_ptr();
+    REGISTRY.lock().unwrap().push(component);
+    ptr
+}
+
+//==============================================================================
+// Built-in catalog, modelled on the units Apple ships with macOS.
+
+const VERSION: u32 = 0x0001_0000;
+
+const READ_WRITE: u32 = ffi::kAudioUnitParameterFlag_IsReadable |
+    ffi::kAudioUnitParameterFlag_IsWritable;
+
+macro_rules! param {
+    ($id:expr, $scope:ident, $name:expr, $unit:ident, $min:expr, $max:expr, $default:expr) => {
+        ParameterSpec {
+            id: $id,
+            scope: ffi::$scope,
+            name: $name,
+            unit: ffi::$unit,
+            min: $min,
+            max: $max,
+            default: $default,
+            flags: READ_WRITE,
+            value_strings: &[],
+        }
+    };
+    ($id:expr, $scope:ident, $name:expr, $value_strings:expr) => {
+        ParameterSpec {
+            id: $id,
+            scope: ffi::$scope,
+            name: $name,
+            unit: ffi::kAudioUnitParameterUnit_Indexed,
+            min: 0.0,
+            max: ($value_strings.len() - 1) as f32,
+            default: 0.0,
+            flags: READ_WRITE | ffi::kAudioUnitParameterFlag_ValuesHaveStrings,
+            value_strings: $value_strings,
+        }
+    };
+}
+
+fn apple(kind: ffi::OSType, sub_kind: ffi::OSType, name: &str) -> SimComponent {
+    SimComponent {
+        desc: ffi::AudioComponentDescription {
+            componentType: kind,
+            componentSubType: sub_kind,
+            componentManufacturer: ffi::kAudioUnitManufacturer_Apple,
+            ..Default::default()
+        },
+        name: format!("Apple: {}", name),
+        version: VERSION,
+        parameters: Vec::new(),
+        presets: Vec::new(),
+        channel_configs: Vec::new(),
+        latency: 0.0,
+        tail_time: 0.0,
+        validation: Mutex::new(None),
+    }
+}
+
+fn with_parameters(mut component: SimComponent, params: &[ParameterSpec]) -> SimComponent {
+    component.parameters = params.to_vec();
+    component
+}
+
+fn with_presets(mut component: SimComponent, presets: &[FactoryPreset]) -> SimComponent {
+    component.presets = presets.to_vec();
+    component
+}
+
+fn with_channel_configs(
+    mut component: SimComponent,
+    configs: &[(i16, i16)],
+) -> SimComponent {
+    component.channel_configs = configs
+        .iter()
+        .map(|&(i, o)| ffi::AUChannelInfo { inChannels: i, outChannels: o })
+        .collect();
+    component
+}
+
+fn with_timing(mut component: SimComponent, latency: f64, tail_time: f64) -> SimComponent {
+    component.latency = latency;
+    component.tail_time = tail_time;
+    component
+}
+
+fn builtin() -> Vec<Box<SimComponent>> {
+    use ffi::*;
+
+    let output_volume = [
+        param!(kHALOutputParam_Volume, kAudioUnitScope_Global, "Volume",
+               kAudioUnitParameterUnit_LinearGain, 0.0, 1.0, 1.0),
+    ];
+    let filter = [
+        param!(0, kAudioUnitScope_Global, "Cutoff Frequency",
+               kAudioUnitParameterUnit_Hertz, 10.0, 22050.0, 6900.0),
+        param!(1, kAudioUnitScope_Global, "Resonance",
+               kAudioUnitParameterUnit_Decibels, -20.0, 40.0, 0.0),
+    ];
+    let peak_limiter = [
+        param!(0, kAudioUnitScope_Global, "Attack Time",
+               kAudioUnitParameterUnit_Seconds, 0.001, 0.03, 0.012),
+        param!(1, kAudioUnitScope_Global, "Decay Time",
+               kAudioUnitParameterUnit_Seconds, 0.001, 0.06, 0.024),
+        param!(2, kAudioUnitScope_Global, "Pre Gain",
+               kAudioUnitParameterUnit_Decibels, -40.0, 40.0, 0.0),
+    ];
+    let dynamics = [
+        param!(0, kAudioUnitScope_Global, "Threshold",
+               kAudioUnitParameterUnit_Decibels, -40.0, 20.0, -20.0),
+        param!(1, kAudioUnitScope_Global, "Head Room",
+               kAudioUnitParameterUnit_Decibels, 0.1, 40.0, 5.0),
+        param!(2, kAudioUnitScope_Global, "Expansion Ratio",
+               kAudioUnitParameterUnit_Rate, 1.0, 50.0, 2.0),
+        param!(3, kAudioUnitScope_Global, "Expansion Threshold",
+               kAudioUnitParameterUnit_Decibels, -120.0, 0.0, -100.0),
+        param!(4, kAudioUnitScope_Global, "Attack Time",
+               kAudioUnitParameterUnit_Seconds, 0.0001, 0.2, 0.001),
+        param!(5, kAudioUnitScope_Global, "Release Time",
+               kAudioUnitParameterUnit_Seconds, 0.01, 3.0, 0.05),
+        param!(6, kAudioUnitScope_Global, "Master Gain",
+               kAudioUnitParameterUnit_Decibels, -40.0, 40.0, 0.0),
+    ];
+    let delay = [
+        param!(0, kAudioUnitScope_Global, "Dry/Wet Mix",
+               kAudioUnitParameterUnit_EqualPowerCrossfade, 0.0, 100.0, 50.0),
+        param!(1, kAudioUnitScope_Global, "Delay Time",
+               kAudioUnitParameterUnit_Seconds, 0.0, 2.0, 1.0),
+        param!(2, kAudioUnitScope_Global, "Feedback",
+               kAudioUnitParameterUnit_Percent, -99.9, 99.9, 50.0),
+        param!(3, kAudioUnitScope_Global, "Lowpass Cutoff Frequency",
+               kAudioUnitParameterUnit_Hertz, 10.0, 22050.0, 15000.0),
+    ];
+    let reverb = [
+        param!(0, kAudioUnitScope_Global, "Dry/Wet Mix",
+               kAudioUnitParameterUnit_EqualPowerCrossfade, 0.0, 100.0, 100.0),
+        param!(1, kAudioUnitScope_Global, "Small/Large Mix",
+               kAudioUnitParameterUnit_EqualPowerCrossfade, 0.0, 100.0, 50.0),
+        param!(2, kAudioUnitScope_Global, "Small Size",
+               kAudioUnitParameterUnit_Seconds, 0.0001, 0.05, 0.03),
+        param!(3, kAudioUnitScope_Global, "Large Size",
+               kAudioUnitParameterUnit_Seconds, 0.005, 0.15, 0.06),
+    ];
+    let reverb_presets = [
+        FactoryPreset { name: "Small Room", values: &[(1, 0.0), (2, 0.01)] },
+        FactoryPreset { name: "Medium Room", values: &[(1, 25.0), (2, 0.02)] },
+        FactoryPreset { name: "Large Room", values: &[(1, 50.0), (3, 0.08)] },
+        FactoryPreset { name: "Medium Hall", values: &[(1, 75.0), (3, 0.1)] },
+        FactoryPreset { name: "Large Hall", values: &[(1, 100.0), (3, 0.15)] },
+        FactoryPreset { name: "Plate", values: &[(0, 50.0), (2, 0.005)] },
+        FactoryPreset { name: "Cathedral", values: &[(1, 100.0), (3, 0.15), (0, 80.0)] },
+    ];
+    let dynamics_presets = [
+        FactoryPreset { name: "Fast and Smooth", values: &[(4, 0.0005), (5, 0.02)] },
+        FactoryPreset { name: "Hard", values: &[(0, -30.0), (1, 1.0)] },
+        FactoryPreset { name: "Light", values: &[(0, -10.0), (1, 10.0)] },
+    ];
+    // kAUNBandEQParam_GlobalGain and the first band's kAUNBandEQParam_*.
+    let nband_eq = [
+        param!(0, kAudioUnitScope_Global, "Global Gain",
+               kAudioUnitParameterUnit_Decibels, -96.0, 24.0, 0.0),
+        param!(1000, kAudioUnitScope_Global, "Bypass Band",
+               kAudioUnitParameterUnit_Boolean, 0.0, 1.0, 1.0),
+        param!(2000, kAudioUnitScope_Global, "Filter Type", &[
+            "Parametric",
+            "2nd Order Butterworth Low Pass",
+            "2nd Order Butterworth High Pass",
+            "Resonant Low Pass",
+            "Resonant High Pass",
+            "Band Pass",
+            "Band Stop",
+            "Low Shelf",
+            "High Shelf",
+            "Resonant Low Shelf",
+            "Resonant High Shelf",
+        ]),
+        param!(3000, kAudioUnitScope_Global, "Frequency",
+               kAudioUnitParameterUnit_Hertz, 20.0, 22050.0, 1000.0),
+        param!(4000, kAudioUnitScope_Global, "Gain",
+               kAudioUnitParameterUnit_Decibels, -96.0, 24.0, 0.0),
+        param!(5000, kAudioUnitScope_Global, "Bandwidth",
+               kAudioUnitParameterUnit_Octaves, 0.05, 5.0, 0.5),
+    ];
+    let mixer = [
+        param!(0, kAudioUnitScope_Input, "Volume",
+               kAudioUnitParameterUnit_LinearGain, 0.0, 1.0, 1.0),
+        param!(1, kAudioUnitScope_Input, "Enable",
+               kAudioUnitParameterUnit_Boolean, 0.0, 1.0, 1.0),
+        param!(2, kAudioUnitScope_Input, "Pan",
+               kAudioUnitParameterUnit_Pan, -1.0, 1.0, 0.0),
+        param!(0, kAudioUnitScope_Output, "Volume",
+               kAudioUnitParameterUnit_LinearGain, 0.0, 1.0, 1.0),
+    ];
+
+    let components = vec![
+        apple(kAudioUnitType_Output, kAudioUnitSubType_GenericOutput, "AUGenericOutput"),
+        with_parameters(
+            apple(kAudioUnitType_Output, kAudioUnitSubType_HALOutput, "AUHAL"),
+            &output_volume,
+        ),
+        with_parameters(
+            apple(kAudioUnitType_Output, kAudioUnitSubType_DefaultOutput,
+                  "DefaultOutputUnit"),
+            &output_volume,
+        ),
+        with_parameters(
+            apple(kAudioUnitType_Output, kAudioUnitSubType_SystemOutput,
+                  "SystemOutputUnit"),
+            &output_volume,
+        ),
+        apple(kAudioUnitType_Output, kAudioUnitSubType_VoiceProcessingIO,
+              "AUVoiceProcessor"),
+        apple(kAudioUnitType_MusicDevice, kAudioUnitSubType_DLSSynth, "DLSMusicDevice"),
+        apple(kAudioUnitType_MusicDevice, kAudioUnitSubType_Sampler, "AUSampler"),
+        apple(kAudioUnitType_FormatConverter, kAudioUnitSubType_AUConverter,
+              "AUConverter"),
+        apple(kAudioUnitType_FormatConverter, kAudioUnitSubType_Varispeed, "AUVarispeed"),
+        apple(kAudioUnitType_FormatConverter, kAudioUnitSubType_NewTimePitch,
+              "AUNewTimePitch"),
+        with_timing(
+            with_parameters(
+                apple(kAudioUnitType_Effect, kAudioUnitSubType_PeakLimiter,
+                      "AUPeakLimiter"),
+                &peak_limiter,
+            ),
+            0.012,
+            0.0,
+        ),
+        with_presets(
+            with_parameters(
+                apple(kAudioUnitType_Effect, kAudioUnitSubType_DynamicsProcessor,
+                      "AUDynamicsProcessor"),
+                &dynamics,
+            ),
+            &dynamics_presets,
+        ),
+        with_parameters(
+            apple(kAudioUnitType_Effect, kAudioUnitSubType_LowPassFilter,
+                  "AULowpass"),
+            &filter,
+        ),
+        with_parameters(
+            apple(kAudioUnitType_Effect, kAudioUnitSubType_HighPassFilter,
+                  "AUHipass"),
+            &filter,
+        ),
+        with_timing(
+            with_channel_configs(
+                with_parameters(
+                    apple(kAudioUnitType_Effect, kAudioUnitSubType_Delay, "AUDelay"),
+                    &delay,
+                ),
+                &[(-1, -1)],
+            ),
+            0.0,
+            2.0,
+        ),
+        with_timing(
+            with_channel_configs(
+                with_presets(
+                    with_parameters(
+                        apple(kAudioUnitType_Effect, kAudioUnitSubType_MatrixReverb,
+                              "AUMatrixReverb"),
+                        &reverb,
+                    ),
+                    &reverb_presets,
+                ),
+                &[(1, 1), (1, 2), (2, 2)],
+            ),
+            0.0,
+            2.5,
+        ),
+        apple(kAudioUnitType_Effect, kAudioUnitSubType_SampleDelay, "AUSampleDelay"),
+        with_parameters(
+            apple(kAudioUnitType_Effect, kAudioUnitSubType_NBandEQ, "AUNBandEQ"),
+            &nband_eq,
+        ),
+        with_parameters(
+            apple(kAudioUnitType_Mixer, kAudioUnitSubType_MultiChannelMixer,
+                  "AUMultiChannelMixer"),
+            &mixer,
+        ),
+        apple(kAudioUnitType_Mixer, kAudioUnitSubType_MatrixMixer, "AUMatrixMixer"),
+        apple(kAudioUnitType_Generator, kAudioUnitSubType_ScheduledSoundPlayer,
+              "AUScheduledSoundPlayer"),
+        apple(kAudioUnitType_Generator, kAudioUnitSubType_AudioFilePlayer,
+              "AUAudioFilePlayer"),
+    ];
+    components.into_iter().map(Box::new).collect()
+}
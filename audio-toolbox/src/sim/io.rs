@@ -0,0 +1,146 @@
+//! The simulated device: a thread that drives an output unit in real time,
+//! reporting captured input and pulling output.
+
+use super::unit::{buffers, SimUnit};
+use ffi;
+use std::mem;
+use std::os::raw::c_void;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// An owned `AudioBufferList` laid out for a stream format.
+pub struct BufferList {
+    // `AudioBufferList` ends in a variable-length array of `AudioBuffer`;
+    // `u64` storage keeps the header suitably aligned.
+    header: Vec<u64>,
+    data: Vec<Vec<u8>>,
+}
+
+impl BufferList {
+    pub fn new(format: &ffi::AudioStreamBasicDescription, frames: u32) -> BufferList {
+        let non_interleaved = format.mFormatFlags & ffi::kAudioFormatFlagIsNonInterleaved != 0;
+        let (count, channels) = if non_interleaved {
+            (format.mChannelsPerFrame, 1)
+        } else {
+            (1, format.mChannelsPerFrame)
+        };
+        let sizes = vec![(channels, frames * format.mBytesPerFrame); count as usize];
+        BufferList::with_sizes(&sizes)
+    }
+
+    /// A list with the same shape as `other`.
+    pub unsafe fn like(other: *mut ffi::AudioBufferList) -> BufferList {
+        let sizes: Vec<(u32, u32)> = buffers(other)
+            .iter()
+            .map(|b| (b.mNumberChannels, b.mDataByteSize))
+            .collect();
+        BufferList::with_sizes(&sizes)
+    }
+
+    fn with_sizes(sizes: &[(u32, u32)]) -> BufferList {
+        let bytes = mem::size_of::<ffi::AudioBufferList>() +
+            sizes.len().saturating_sub(1) * mem::size_of::<ffi::AudioBuffer>();
+        let words = (bytes + mem::size_of::<u64>() - 1) / mem::size_of::<u64>();
+        let mut list = BufferList {
+            header: vec![0; words],
+            data: sizes.iter().map(|&(_, size)| vec![0; size as usize]).collect(),
+        };
+        unsafe {
+            let ptr = list.as_mut_ptr();
+            (*ptr).mNumberBuffers = sizes.len() as u32;
+            for (b, (&(channels, size), data)) in
+                buffers(ptr).iter_mut().zip(sizes.iter().zip(list.data.iter_mut()))
+            {
+                b.mNumberChannels = channels;
+                b.mDataByteSize = size;
+                b.mData = data.as_mut_ptr() as *mut c_void;
+            }
+        }
+        list
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut ffi::AudioBufferList {
+        self.header.as_mut_ptr() as *mut ffi::AudioBufferList
+    }
+}
+
+pub struct RenderLoop {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RenderLoop {
+    /// Start pulling output element 0 of `unit` in slices of `frames`.
+    /// The unit must outlive the loop; `SimUnit` joins it before it goes
+    /// away.
+    pub fn start(
+        unit: &SimUnit,
+        format: ffi::AudioStreamBasicDescription,
+        frames: u32,
+    ) -> RenderLoop {
+        let stop = Arc::new(AtomicBool::new(false));
+        let unit = unit as *const SimUnit as usize;
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let unit = unsafe { &*(unit as *const SimUnit) };
+                run(unit, &format, frames, &stop);
+            })
+        };
+        RenderLoop { stop, thread }
+    }
+
+    /// Ask the thread to finish once the current slice is done.
+    pub fn signal(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether this is the loop's own thread, as it is when a callback
+    /// stops its unit. That thread can't wait for itself, so the loop has
+    /// to be joined later from elsewhere.
+    pub fn is_current(&self) -> bool {
+        self.thread.thread().id() == thread::current().id()
+    }
+
+    /// Wait for the thread to finish. Call `signal` first.
+    pub fn join(self) {
+        let _ = self.thread.join();
+    }
+}
+
+fn run(unit: &SimUnit, format: &ffi::AudioStreamBasicDescription, frames: u32, stop: &AtomicBool) {
+    let nanos = (1e9 * frames as f64 / format.mSampleRate) as u64;
+    let period = Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32);
+    let start = Instant::now();
+    let mut deadline = start;
+    let mut sample_time = 0.0;
+    let mut list = BufferList::new(format, frames);
+
+    while !stop.load(Ordering::SeqCst) {
+        let host_time = start.elapsed();
+        let mut time_stamp: ffi::AudioTimeStamp = unsafe { mem::zeroed() };
+        time_stamp.mSampleTime = sample_time;
+        time_stamp.mHostTime = host_time.as_secs() * 1_000_000_000 +
+            host_time.subsec_nanos() as u64;
+        time_stamp.mRateScalar = 1.0;
+        time_stamp.mFlags = ffi::kAudioTimeStampSampleTimeValid |
+            ffi::kAudioTimeStampHostTimeValid;
+
+        unsafe {
+            unit.capture(&time_stamp, frames);
+            if unit.renders_output() {
+                let mut flags = 0;
+                unit.render(&mut flags, &time_stamp, 0, frames, list.as_mut_ptr());
+            }
+        }
+        sample_time += frames as f64;
+
+        deadline += period;
+        let now = Instant::now();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+    }
+}
@@ -0,0 +1,629 @@
+//! A pure-Rust stand-in for AudioToolbox.
+//!
+//! Every function here has the same name and signature as its counterpart
+//! in `audio_toolbox_sys`, and `ffi` substitutes them when AudioToolbox
+//! isn't available. Components come from an in-process registry seeded
+//! with Apple's stock units, each instance keeps its own property and
+//! parameter stores, and started output units are pulled in real time by a
+//! render thread standing in for the audio device.
+
+#![allow(non_snake_case, non_upper_case_globals)]
+
+mod cf;
+mod component;
+mod io;
+mod unit;
+
+pub use self::cf::{CFArrayGetCount, CFArrayGetValueAtIndex, CFDataCreate,
+                   CFDataGetBytePtr, CFDataGetLength, CFDictionaryCreateMutableCopy,
+                   CFDictionaryRemoveValue, CFPropertyListCreateData,
+                   CFPropertyListCreateWithData, CFRelease, CFStringCreateWithBytes,
+                   CFStringGetCString, CFStringGetLength,
+                   CFStringGetMaximumSizeForEncoding};
+
+use ffi;
+use self::component::SimComponent;
+use self::unit::SimUnit;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+/// `kAudio_ParamError`
+const PARAM_ERROR: ffi::OSStatus = -50;
+
+unsafe fn instance<'a>(unit: ffi::AudioUnit) -> Result<&'a SimUnit, ffi::OSStatus> {
+    if unit.is_null() {
+        Err(PARAM_ERROR)
+    } else {
+        Ok(SimUnit::from_ptr(unit))
+    }
+}
+
+macro_rules! try_status {
+    ($e:expr) => {
+        match $e {
+            Ok(v) => v,
+            Err(e) => return e,
+        }
+    }
+}
+
+/// A new instance of the first simulated component of `kind` and
+/// `sub_kind`, for tests elsewhere in the crate.
+#[cfg(test)]
+pub fn new_unit(kind: ffi::OSType, sub_kind: ffi::OSType) -> ::AudioUnit {
+    let desc = ffi::AudioComponentDescription {
+        componentType: kind,
+        componentSubType: sub_kind,
+        ..Default::default()
+    };
+    let mut unit = ptr::null_mut();
+    unsafe {
+        let component = AudioComponentFindNext(ptr::null_mut(), &desc);
+        assert_eq!(AudioComponentInstanceNew(component, &mut unit), 0);
+        ::AudioUnit::from_ptr(unit as ffi::AudioUnit)
+    }
+}
+
+/// A new third-party effect taking `channel_configs`. An effect that
+/// doesn't take stereo fails to initialize, and so to validate, as a fresh
+/// instance is stereo.
+#[cfg(test)]
+pub fn register_effect(channel_configs: &[(i16, i16)]) -> ::AudioComponent {
+    component::register_effect(channel_configs).into()
+}
+
+/// The parameters `AUParameterListenerNotify` was called for on `unit`
+/// since the last call, as (ID, scope, element).
+#[cfg(test)]
+pub fn take_parameter_notifications(unit: &::AudioUnitRef) -> Vec<(u32, u32, u32)> {
+    unsafe { SimUnit::from_ptr(unit.as_ptr()) }.take_parameter_notifications()
+}
+
+/// Replace the validation result a component has cached.
+#[cfg(test)]
+pub fn cache_validation_result(
+    component: &::AudioComponent,
+    result: Option<ffi::AudioComponentValidationResult>,
+) {
+    let component = unsafe { SimComponent::from_ptr(component.as_ptr()) };
+    *component.validation.lock().unwrap() = result;
+}
+
+//==============================================================================
+// AudioComponent
+
+pub unsafe fn AudioComponentFindNext(
+    inComponent: ffi::AudioComponent,
+    inDesc: *const ffi::AudioComponentDescription,
+) -> ffi::AudioComponent {
+    let desc = if inDesc.is_null() {
+        ffi::AudioComponentDescription::default()
+    } else {
+        *inDesc
+    };
+    component::find_next(inComponent, &desc)
+}
+
+pub unsafe fn AudioComponentCount(inDesc: *const ffi::AudioComponentDescription) -> u32 {
+    let desc = if inDesc.is_null() {
+        ffi::AudioComponentDescription::default()
+    } else {
+        *inDesc
+    };
+    component::count(&desc) as u32
+}
+
+pub unsafe fn AudioComponentCopyName(
+    inComponent: ffi::AudioComponent,
+    outName: *mut ffi::CFStringRef,
+) -> ffi::OSStatus {
+    if inComponent.is_null() || outName.is_null() {
+        return PARAM_ERROR;
+    }
+    *outName = cf::string(&SimComponent::from_ptr(inComponent).name);
+    0
+}
+
+pub unsafe fn AudioComponentGetDescription(
+    inComponent: ffi::AudioComponent,
+    outDesc: *mut ffi::AudioComponentDescription,
+) -> ffi::OSStatus {
+    if inComponent.is_null() || outDesc.is_null() {
+        return PARAM_ERROR;
+    }
+    *outDesc = SimComponent::from_ptr(inComponent).desc;
+    0
+}
+
+pub unsafe fn AudioComponentGetVersion(
+    inComponent: ffi::AudioComponent,
+    outVersion: *mut u32,
+) -> ffi::OSStatus {
+    if inComponent.is_null() || outVersion.is_null() {
+        return PARAM_ERROR;
+    }
+    *outVersion = SimComponent::from_ptr(inComponent).version;
+    0
+}
+
+pub unsafe fn AudioComponentCopyConfigurationInfo(
+    inComponent: ffi::AudioComponent,
+    outConfigurationInfo: *mut ffi::CFDictionaryRef,
+) -> ffi::OSStatus {
+    if inComponent.is_null() || outConfigurationInfo.is_null() {
+        return PARAM_ERROR;
+    }
+    let info = SimComponent::from_ptr(inComponent).configuration_info();
+    *outConfigurationInfo = cf::property_list(info) as ffi::CFDictionaryRef;
+    0
+}
+
+/// Validation opens and initializes an instance of the unit. The result is
+/// kept, and returned again unless `ForceValidation` is set.
+pub unsafe fn AudioComponentValidate(
+    inComponent: ffi::AudioComponent,
+    inValidationParameters: ffi::CFDictionaryRef,
+    outValidationResult: *mut ffi::AudioComponentValidationResult,
+) -> ffi::OSStatus {
+    if inComponent.is_null() || outValidationResult.is_null() {
+        return PARAM_ERROR;
+    }
+    let component = SimComponent::from_ptr(inComponent);
+    let key = ffi::kAudioComponentValidationParameter_ForceValidation;
+    let force = !inValidationParameters.is_null() &&
+        cf::to_plist(inValidationParameters as ffi::CFTypeRef)
+            .as_dictionary()
+            .and_then(|params| params.get(key))
+            .and_then(|force| force.as_boolean())
+            .unwrap_or(false);
+    let mut validation = component.validation.lock().unwrap();
+    let result = match *validation {
+        Some(result) if !force => result,
+        _ => {
+            let unit = SimUnit::new(component);
+            if unit.initialize() == 0 {
+                ffi::kAudioComponentValidationResult_Passed
+            } else {
+                ffi::kAudioComponentValidationResult_Failed
+            }
+        },
+    };
+    *validation = Some(result);
+    *outValidationResult = result;
+    0
+}
+
+pub unsafe fn AudioComponentInstanceNew(
+    inComponent: ffi::AudioComponent,
+    outInstance: *mut ffi::AudioComponentInstance,
+) -> ffi::OSStatus {
+    if inComponent.is_null() || outInstance.is_null() {
+        return PARAM_ERROR;
+    }
+    let unit = Box::new(SimUnit::new(SimComponent::from_ptr(inComponent)));
+    *outInstance = Box::into_raw(unit) as ffi::AudioComponentInstance;
+    0
+}
+
+pub unsafe fn AudioComponentInstanceDispose(
+    inInstance: ffi::AudioComponentInstance,
+) -> ffi::OSStatus {
+    if inInstance.is_null() {
+        return PARAM_ERROR;
+    }
+    drop(Box::from_raw(inInstance as *mut SimUnit));
+    0
+}
+
+//==============================================================================
+// AudioUnit
+
+pub unsafe fn AudioUnitInitialize(inUnit: ffi::AudioUnit) -> ffi::OSStatus {
+    try_status!(instance(inUnit)).initialize()
+}
+
+pub unsafe fn AudioUnitUninitialize(inUnit: ffi::AudioUnit) -> ffi::OSStatus {
+    try_status!(instance(inUnit)).uninitialize()
+}
+
+pub unsafe fn AudioUnitGetPropertyInfo(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitPropertyID,
+    inScope: ffi::AudioUnitScope,
+    inElement: ffi::AudioUnitElement,
+    outDataSize: *mut u32,
+    outWritable: *mut ffi::Boolean,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    let (size, writable) = try_status!(unit.property_info(inID, inScope, inElement));
+    if !outDataSize.is_null() {
+        *outDataSize = size;
+    }
+    if !outWritable.is_null() {
+        *outWritable = writable as ffi::Boolean;
+    }
+    0
+}
+
+pub unsafe fn AudioUnitGetProperty(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitPropertyID,
+    inScope: ffi::AudioUnitScope,
+    inElement: ffi::AudioUnitElement,
+    outData: *mut c_void,
+    ioDataSize: *mut u32,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    if outData.is_null() || ioDataSize.is_null() {
+        return PARAM_ERROR;
+    }
+    // Some properties take part of their value as input.
+    let input = slice::from_raw_parts(outData as *const u8, *ioDataSize as usize);
+    let data = try_status!(unit.get_property(inID, inScope, inElement, input));
+    if (*ioDataSize as usize) < data.len() {
+        return ffi::kAudioUnitErr_InvalidPropertyValue;
+    }
+    ptr::copy_nonoverlapping(data.as_ptr(), outData as *mut u8, data.len());
+    *ioDataSize = data.len() as u32;
+    0
+}
+
+pub unsafe fn AudioUnitSetProperty(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitPropertyID,
+    inScope: ffi::AudioUnitScope,
+    inElement: ffi::AudioUnitElement,
+    inData: *const c_void,
+    inDataSize: u32,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    let data = if inData.is_null() {
+        &[][..]
+    } else {
+        slice::from_raw_parts(inData as *const u8, inDataSize as usize)
+    };
+    unit.set_property(inID, inScope, inElement, data)
+}
+
+pub unsafe fn AudioUnitAddPropertyListener(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitPropertyID,
+    inProc: Option<ffi::AudioUnitPropertyListenerProc>,
+    inProcUserData: *mut c_void,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    match inProc {
+        Some(proc_) => unit.add_property_listener(inID, proc_, inProcUserData),
+        None => PARAM_ERROR,
+    }
+}
+
+pub unsafe fn AudioUnitRemovePropertyListenerWithUserData(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitPropertyID,
+    inProc: Option<ffi::AudioUnitPropertyListenerProc>,
+    inProcUserData: *mut c_void,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    match inProc {
+        Some(proc_) => unit.remove_property_listener(inID, proc_, inProcUserData),
+        None => PARAM_ERROR,
+    }
+}
+
+pub unsafe fn AudioUnitAddRenderNotify(
+    inUnit: ffi::AudioUnit,
+    inProc: Option<ffi::AURenderCallback>,
+    inProcUserData: *mut c_void,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    match inProc {
+        Some(proc_) => unit.add_render_notify(proc_, inProcUserData),
+        None => PARAM_ERROR,
+    }
+}
+
+pub unsafe fn AudioUnitRemoveRenderNotify(
+    inUnit: ffi::AudioUnit,
+    inProc: Option<ffi::AURenderCallback>,
+    inProcUserData: *mut c_void,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    match inProc {
+        Some(proc_) => unit.remove_render_notify(proc_, inProcUserData),
+        None => PARAM_ERROR,
+    }
+}
+
+pub unsafe fn AudioUnitGetParameter(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitParameterID,
+    inScope: ffi::AudioUnitScope,
+    inElement: ffi::AudioUnitElement,
+    outValue: *mut ffi::AudioUnitParameterValue,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    if outValue.is_null() {
+        return PARAM_ERROR;
+    }
+    *outValue = try_status!(unit.get_parameter(inID, inScope, inElement));
+    0
+}
+
+pub unsafe fn AudioUnitSetParameter(
+    inUnit: ffi::AudioUnit,
+    inID: ffi::AudioUnitParameterID,
+    inScope: ffi::AudioUnitScope,
+    inElement: ffi::AudioUnitElement,
+    inValue: ffi::AudioUnitParameterValue,
+    _inBufferOffsetInFrames: u32,
+) -> ffi::OSStatus {
+    try_status!(instance(inUnit)).set_parameter(inID, inScope, inElement, inValue)
+}
+
+pub unsafe fn AudioUnitScheduleParameters(
+    inUnit: ffi::AudioUnit,
+    inParameterEvent: *const ffi::AudioUnitParameterEvent,
+    inNumParamEvents: u32,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    if inNumParamEvents == 0 {
+        return 0;
+    }
+    if inParameterEvent.is_null() {
+        return PARAM_ERROR;
+    }
+    // There is no DSP to automate, so each event lands on its final value.
+    let events = slice::from_raw_parts(inParameterEvent, inNumParamEvents as usize);
+    for event in events {
+        let mut event = *event;
+        let value = match event.eventType {
+            ffi::kParameterEvent_Immediate => (*event.immediate()).value,
+            ffi::kParameterEvent_Ramped => (*event.ramp()).endValue,
+            _ => return PARAM_ERROR,
+        };
+        let status = unit.set_parameter(event.parameter, event.scope, event.element, value);
+        if status != 0 {
+            return status;
+        }
+    }
+    0
+}
+
+pub unsafe fn AudioUnitRender(
+    inUnit: ffi::AudioUnit,
+    ioActionFlags: *mut ffi::AudioUnitRenderActionFlags,
+    inTimeStamp: *const ffi::AudioTimeStamp,
+    inOutputBusNumber: u32,
+    inNumberFrames: u32,
+    ioData: *mut ffi::AudioBufferList,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    if inTimeStamp.is_null() || ioData.is_null() {
+        return PARAM_ERROR;
+    }
+    unit.render(ioActionFlags, inTimeStamp, inOutputBusNumber, inNumberFrames, ioData)
+}
+
+pub unsafe fn AudioUnitProcess(
+    inUnit: ffi::AudioUnit,
+    _ioActionFlags: *mut ffi::AudioUnitRenderActionFlags,
+    inTimeStamp: *const ffi::AudioTimeStamp,
+    _inNumberFrames: u32,
+    ioData: *mut ffi::AudioBufferList,
+) -> ffi::OSStatus {
+    let unit = try_status!(instance(inUnit));
+    if inTimeStamp.is_null() || ioData.is_null() {
+        return PARAM_ERROR;
+    }
+    if !unit.is_initialized() {
+        return ffi::kAudioUnitErr_Uninitialized;
+    }
+    // The simulated effects pass audio straight through, so processing in
+    // place leaves `ioData` as it is.
+    0
+}
+
+pub unsafe fn AudioUnitReset(
+    inUnit: ffi::AudioUnit,
+    inScope: ffi::AudioUnitScope,
+    inElement: ffi::AudioUnitElement,
+) -> ffi::OSStatus {
+    try_status!(instance(inUnit)).reset(inScope, inElement)
+}
+
+//==============================================================================
+// AudioOutputUnit
+
+pub unsafe fn AudioOutputUnitStart(ci: ffi::AudioUnit) -> ffi::OSStatus {
+    let unit = try_status!(instance(ci));
+    if !unit.component().is_output() {
+        return ffi::kAudioUnitErr_InvalidProperty;
+    }
+    unit.start()
+}
+
+pub unsafe fn AudioOutputUnitStop(ci: ffi::AudioUnit) -> ffi::OSStatus {
+    let unit = try_status!(instance(ci));
+    if !unit.component().is_output() {
+        return ffi::kAudioUnitErr_InvalidProperty;
+    }
+    unit.stop()
+}
+
+//==============================================================================
+// AudioUnitUtilities
+
+/// Parameter listeners can't be created in the simulator, so there is never
+/// anyone to deliver the notification to. The unit records it instead.
+pub unsafe fn AUParameterListenerNotify(
+    _inSendingListener: ffi::AUParameterListenerRef,
+    _inSendingObject: *mut c_void,
+    inParameter: *const ffi::AudioUnitParameter,
+) -> ffi::OSStatus {
+    if inParameter.is_null() {
+        return PARAM_ERROR;
+    }
+    let unit = try_status!(instance((*inParameter).mAudioUnit));
+    unit.notify_parameter(&*inParameter);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    unsafe fn new_instance(kind: ffi::OSType, sub_kind: ffi::OSType) -> ffi::AudioUnit {
+        let desc = ffi::AudioComponentDescription {
+            componentType: kind,
+            componentSubType: sub_kind,
+            ..Default::default()
+        };
+        let component = AudioComponentFindNext(ptr::null_mut(), &desc);
+        assert!(!component.is_null());
+        let mut unit = ptr::null_mut();
+        assert_eq!(AudioComponentInstanceNew(component, &mut unit), 0);
+        unit as ffi::AudioUnit
+    }
+
+    unsafe fn dispose(unit: ffi::AudioUnit) {
+        assert_eq!(AudioComponentInstanceDispose(unit as ffi::AudioComponentInstance), 0);
+    }
+
+    unsafe fn get<T: Copy>(
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+    ) -> Result<T, ffi::OSStatus> {
+        let mut value: T = mem::zeroed();
+        let mut size = mem::size_of::<T>() as u32;
+        let data = &mut value as *mut T as *mut c_void;
+        match AudioUnitGetProperty(unit, id, scope, 0, data, &mut size) {
+            0 => Ok(value),
+            status => Err(status),
+        }
+    }
+
+    unsafe fn set<T>(
+        unit: ffi::AudioUnit,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        value: &T,
+    ) -> ffi::OSStatus {
+        let size = mem::size_of::<T>() as u32;
+        AudioUnitSetProperty(unit, id, scope, 0, value as *const T as *const c_void, size)
+    }
+
+    struct Pull {
+        unit: ffi::AudioUnit,
+        calls: AtomicUsize,
+        stop_after: usize,
+    }
+
+    extern "C" fn pull_input(
+        ref_con: *mut c_void,
+        _: *mut ffi::AudioUnitRenderActionFlags,
+        _: *const ffi::AudioTimeStamp,
+        _: u32,
+        _: u32,
+        _: *mut ffi::AudioBufferList,
+    ) -> ffi::OSStatus {
+        let pull = unsafe { &*(ref_con as *const Pull) };
+        if pull.calls.fetch_add(1, Ordering::SeqCst) + 1 == pull.stop_after {
+            unsafe { AudioOutputUnitStop(pull.unit) };
+        }
+        0
+    }
+
+    unsafe fn pull_from(unit: ffi::AudioUnit, pull: &Pull) {
+        let callback = ffi::AURenderCallbackStruct {
+            inputProc: Some(pull_input),
+            inputProcRefCon: pull as *const Pull as *mut c_void,
+        };
+        let id = ffi::kAudioUnitProperty_SetRenderCallback;
+        assert_eq!(set(unit, id, ffi::kAudioUnitScope_Input, &callback), 0);
+    }
+
+    fn wait_for(pull: &Pull, calls: usize) {
+        for _ in 0..500 {
+            if pull.calls.load(Ordering::SeqCst) >= calls {
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("render loop made {} calls", pull.calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn instances_start_uninitialized_with_default_properties() {
+        unsafe {
+            let unit = new_instance(ffi::kAudioUnitType_Effect,
+                                    ffi::kAudioUnitSubType_Delay);
+            let id = ffi::kAudioUnitProperty_StreamFormat;
+            let format: ffi::AudioStreamBasicDescription =
+                get(unit, id, ffi::kAudioUnitScope_Output).unwrap();
+            assert_eq!(format.mChannelsPerFrame, 2);
+            assert_eq!(format.mBytesPerFrame, 4);
+
+            let id = ffi::kAudioUnitProperty_MaximumFramesPerSlice;
+            assert_eq!(set(unit, id, ffi::kAudioUnitScope_Global, &256u32), 0);
+            assert_eq!(get::<u32>(unit, id, ffi::kAudioUnitScope_Global), Ok(256));
+            assert_eq!(
+                get::<u32>(unit, 0xdead, ffi::kAudioUnitScope_Global),
+                Err(ffi::kAudioUnitErr_InvalidProperty)
+            );
+
+            let mut list = io::BufferList::new(&format, 64);
+            let time_stamp: ffi::AudioTimeStamp = mem::zeroed();
+            let status = AudioUnitRender(unit, ptr::null_mut(), &time_stamp, 0, 64,
+                                         list.as_mut_ptr());
+            assert_eq!(status, ffi::kAudioUnitErr_Uninitialized);
+            dispose(unit);
+        }
+    }
+
+    #[test]
+    fn render_loop_pulls_until_stopped() {
+        unsafe {
+            let unit = new_instance(ffi::kAudioUnitType_Output,
+                                    ffi::kAudioUnitSubType_GenericOutput);
+            let pull = Pull { unit, calls: AtomicUsize::new(0), stop_after: 0 };
+            pull_from(unit, &pull);
+            assert_eq!(AudioOutputUnitStart(unit), ffi::kAudioUnitErr_Uninitialized);
+            assert_eq!(AudioUnitInitialize(unit), 0);
+            assert_eq!(AudioOutputUnitStart(unit), 0);
+            let running = ffi::kAudioOutputUnitProperty_IsRunning;
+            assert_eq!(get::<u32>(unit, running, ffi::kAudioUnitScope_Global), Ok(1));
+            wait_for(&pull, 2);
+
+            assert_eq!(AudioOutputUnitStop(unit), 0);
+            assert_eq!(get::<u32>(unit, running, ffi::kAudioUnitScope_Global), Ok(0));
+            let calls = pull.calls.load(Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(30));
+            assert_eq!(pull.calls.load(Ordering::SeqCst), calls);
+            dispose(unit);
+        }
+    }
+
+    #[test]
+    fn dispose_waits_for_a_loop_stopped_by_its_callback() {
+        unsafe {
+            let unit = new_instance(ffi::kAudioUnitType_Output,
+                                    ffi::kAudioUnitSubType_GenericOutput);
+            let pull = Pull { unit, calls: AtomicUsize::new(0), stop_after: 2 };
+            pull_from(unit, &pull);
+            assert_eq!(AudioUnitInitialize(unit), 0);
+            assert_eq!(AudioOutputUnitStart(unit), 0);
+            wait_for(&pull, 2);
+            // Disposing returns only once the render thread is gone, so
+            // nothing touches the unit or `pull` afterwards.
+            dispose(unit);
+            assert_eq!(pull.calls.load(Ordering::SeqCst), 2);
+        }
+    }
+}
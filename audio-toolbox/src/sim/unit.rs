@@ -0,0 +1,1348 @@
+//! Simulated audio unit instances: property and parameter stores, listeners
+//! and rendering.
+
+use super::cf;
+use super::component::{ParameterSpec, SimComponent};
+use super::io::RenderLoop;
+use {channel_configs_support, ClassInfo};
+use ffi;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+type Key = (ffi::AudioUnitPropertyID, ffi::AudioUnitScope, ffi::AudioUnitElement);
+
+/// Frames per slice used until the host says otherwise; the same default
+/// Apple's units report.
+pub const DEFAULT_MAXIMUM_FRAMES_PER_SLICE: u32 = 1156;
+pub const DEFAULT_SAMPLE_RATE: f64 = 44100.0;
+pub const DEFAULT_CHANNELS: u32 = 2;
+
+/// Identifier reported for the one device the simulator pretends to have.
+pub const SIM_DEVICE: u32 = 1;
+
+/// Layouts every simulated element accepts, besides ones given by bitmap or
+/// channel descriptions.
+pub const SUPPORTED_LAYOUT_TAGS: &[ffi::AudioChannelLayoutTag] = &[
+    ffi::kAudioChannelLayoutTag_Mono,
+    ffi::kAudioChannelLayoutTag_Stereo,
+    ffi::kAudioChannelLayoutTag_Quadraphonic,
+    ffi::kAudioChannelLayoutTag_MPEG_5_0_B,
+    ffi::kAudioChannelLayoutTag_MPEG_5_1_A,
+    ffi::kAudioChannelLayoutTag_AudioUnit_6_0,
+    ffi::kAudioChannelLayoutTag_MPEG_6_1_A,
+    ffi::kAudioChannelLayoutTag_AudioUnit_7_0,
+    ffi::kAudioChannelLayoutTag_MPEG_7_1_A,
+    ffi::kAudioChannelLayoutTag_MPEG_7_1_C,
+];
+
+/// Offset of `mChannelDescriptions` in an `AudioChannelLayout`.
+const LAYOUT_HEADER_SIZE: usize = 12;
+
+/// Name of the state a unit starts in, as Apple's units report it.
+const DEFAULT_PRESET_NAME: &str = "Untitled";
+
+struct Property {
+    data: Vec<u8>,
+    writable: bool,
+    /// Fixed-size properties reject writes of any other size.
+    fixed_size: bool,
+}
+
+struct Listener {
+    id: ffi::AudioUnitPropertyID,
+    proc_: ffi::AudioUnitPropertyListenerProc,
+    ref_con: usize,
+}
+
+#[derive(Clone, Copy)]
+struct RenderNotify {
+    proc_: ffi::AURenderCallback,
+    ref_con: usize,
+}
+
+struct State {
+    initialized: bool,
+    inputs: u32,
+    outputs: u32,
+    properties: HashMap<Key, Property>,
+    parameters: HashMap<Key, f32>,
+    // `AUParameterListenerNotify` calls about the unit, oldest first.
+    parameter_notifications: Vec<Key>,
+    preset_number: i32,
+    preset_name: String,
+    element_names: HashMap<(ffi::AudioUnitScope, ffi::AudioUnitElement), String>,
+    listeners: Vec<Listener>,
+    render_notify: Vec<RenderNotify>,
+    // Unit-owned buffers handed out when a caller renders with null `mData`.
+    scratch: HashMap<u32, Vec<u8>>,
+    io: Option<RenderLoop>,
+    // Loops stopped from their own thread, which are joined later.
+    stopped: Vec<RenderLoop>,
+}
+
+/// Held while a thread calls into client callbacks, so that removing one
+/// can wait until no other thread may still be calling it. The thread
+/// holding it may enter again, as an input callback does when it renders.
+struct Gate {
+    owner: Mutex<Option<(ThreadId, usize)>>,
+    free: Condvar,
+}
+
+struct Entered<'a>(&'a Gate);
+
+impl Gate {
+    fn new() -> Gate {
+        Gate { owner: Mutex::new(None), free: Condvar::new() }
+    }
+
+    fn enter(&self) -> Entered {
+        let current = thread::current().id();
+        let mut owner = self.owner.lock().unwrap();
+        loop {
+            match *owner {
+                Some((id, ref mut depth)) if id == current => {
+                    *depth += 1;
+                    break;
+                },
+                Some(_) => owner = self.free.wait(owner).unwrap(),
+                None => {
+                    *owner = Some((current, 1));
+                    break;
+                },
+            }
+        }
+        Entered(self)
+    }
+}
+
+impl<'a> Drop for Entered<'a> {
+    fn drop(&mut self) {
+        let mut owner = self.0.owner.lock().unwrap();
+        let done = match *owner {
+            Some((_, ref mut depth)) => {
+                *depth -= 1;
+                *depth == 0
+            },
+            None => false,
+        };
+        if done {
+            *owner = None;
+            self.0.free.notify_all();
+        }
+    }
+}
+
+pub struct SimUnit {
+    component: &'static SimComponent,
+    state: Mutex<State>,
+    // Never entered while `state` is locked. Render and capture cycles
+    // hold `rendering`; listener notifications hold `notifying`.
+    rendering: Gate,
+    notifying: Gate,
+}
+
+pub fn bytes_of<T>(value: &T) -> Vec<u8> {
+    unsafe {
+        slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()).to_vec()
+    }
+}
+
+pub fn read<T: Copy>(data: &[u8]) -> T {
+    assert!(data.len() >= mem::size_of::<T>());
+    unsafe { ptr::read_unaligned(data.as_ptr() as *const T) }
+}
+
+/// Read the input half of an in/out property value.
+fn read_input<T: Copy>(input: &[u8]) -> Result<T, ffi::OSStatus> {
+    if input.len() < mem::size_of::<T>() {
+        return Err(ffi::kAudioUnitErr_InvalidPropertyValue);
+    }
+    Ok(read(input))
+}
+
+pub fn canonical_format(sample_rate: f64, channels: u32) -> ffi::AudioStreamBasicDescription {
+    ffi::AudioStreamBasicDescription {
+        mSampleRate: sample_rate,
+        mFormatID: ffi::kAudioFormatLinearPCM,
+        mFormatFlags: ffi::kAudioFormatFlagIsFloat | ffi::kAudioFormatFlagIsPacked |
+            ffi::kAudioFormatFlagIsNonInterleaved,
+        mBytesPerPacket: 4,
+        mFramesPerPacket: 1,
+        mBytesPerFrame: 4,
+        mChannelsPerFrame: channels,
+        mBitsPerChannel: 32,
+        mReserved: 0,
+    }
+}
+
+impl State {
+    fn elements(&self, scope: ffi::AudioUnitScope) -> Option<u32> {
+        match scope {
+            ffi::kAudioUnitScope_Global => Some(1),
+            ffi::kAudioUnitScope_Input => Some(self.inputs),
+            ffi::kAudioUnitScope_Output => Some(self.outputs),
+            _ => None,
+        }
+    }
+
+    fn check_element(
+        &self,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) -> Result<(), ffi::OSStatus> {
+        match self.elements(scope) {
+            None => Err(ffi::kAudioUnitErr_InvalidScope),
+            Some(n) if element >= n => Err(ffi::kAudioUnitErr_InvalidElement),
+            _ => Ok(()),
+        }
+    }
+
+    fn define<T>(&mut self, key: Key, value: &T, writable: bool) {
+        self.properties.insert(
+            key,
+            Property {
+                data: bytes_of(value),
+                writable,
+                fixed_size: true,
+            },
+        );
+    }
+
+    fn define_variable(&mut self, key: Key, data: Vec<u8>, writable: bool) {
+        self.properties.insert(
+            key,
+            Property {
+                data,
+                writable,
+                fixed_size: false,
+            },
+        );
+    }
+
+    fn lookup(&self, key: Key) -> Result<&Property, ffi::OSStatus> {
+        let (id, scope, element) = key;
+        if let Some(p) = self.properties.get(&key) {
+            return Ok(p);
+        }
+        if !self.properties.keys().any(|k| k.0 == id) {
+            return Err(ffi::kAudioUnitErr_InvalidProperty);
+        }
+        self.check_element(scope, element)?;
+        Err(ffi::kAudioUnitErr_InvalidScope)
+    }
+
+    fn io_enabled(&self, scope: ffi::AudioUnitScope, element: ffi::AudioUnitElement) -> bool {
+        self.properties
+            .get(&(ffi::kAudioOutputUnitProperty_EnableIO, scope, element))
+            .map_or(false, |p| read::<u32>(&p.data) != 0)
+    }
+
+    fn stream_format(
+        &self,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) -> Option<ffi::AudioStreamBasicDescription> {
+        self.properties
+            .get(&(ffi::kAudioUnitProperty_StreamFormat, scope, element))
+            .map(|p| read(&p.data))
+    }
+}
+
+impl SimUnit {
+    pub fn new(component: &'static SimComponent) -> SimUnit {
+        let (inputs, outputs) = component.initial_buses();
+        let state = State {
+            initialized: false,
+            inputs: 0,
+            outputs: 0,
+            properties: HashMap::new(),
+            parameters: HashMap::new(),
+            parameter_notifications: Vec::new(),
+            preset_number: -1,
+            preset_name: DEFAULT_PRESET_NAME.to_owned(),
+            element_names: HashMap::new(),
+            listeners: Vec::new(),
+            render_notify: Vec::new(),
+            scratch: HashMap::new(),
+            io: None,
+            stopped: Vec::new(),
+        };
+        let unit = SimUnit {
+            component,
+            state: Mutex::new(state),
+            rendering: Gate::new(),
+            notifying: Gate::new(),
+        };
+        {
+            let mut state = unit.state.lock().unwrap();
+            unit.define_globals(&mut state);
+            unit.resize(&mut state, ffi::kAudioUnitScope_Input, inputs);
+            unit.resize(&mut state, ffi::kAudioUnitScope_Output, outputs);
+        }
+        unit
+    }
+
+    pub fn as_ptr(&self) -> ffi::AudioUnit {
+        self as *const SimUnit as *mut SimUnit as ffi::AudioUnit
+    }
+
+    pub unsafe fn from_ptr<'a>(ptr: ffi::AudioUnit) -> &'a SimUnit {
+        &*(ptr as *const SimUnit)
+    }
+
+    pub fn component(&self) -> &'static SimComponent {
+        self.component
+    }
+
+    fn define_globals(&self, state: &mut State) {
+        use ffi::*;
+        let global = kAudioUnitScope_Global;
+
+        state.define(
+            (kAudioUnitProperty_MaximumFramesPerSlice, global, 0),
+            &DEFAULT_MAXIMUM_FRAMES_PER_SLICE,
+            true,
+        );
+        state.define((kAudioUnitProperty_Latency, global, 0), &self.component.latency, false);
+        state.define((kAudioUnitProperty_LastRenderError, global, 0), &0i32, false);
+        state.define((kAudioUnitProperty_RenderQuality, global, 0), &kRenderQuality_High, true);
+
+        if !self.component.channel_configs.is_empty() {
+            state.define_variable((kAudioUnitProperty_SupportedNumChannels, global, 0),
+                                  bytes_of_slice(&self.component.channel_configs), false);
+        }
+
+        self.define_parameter_list(state, global);
+        for p in self.component.parameters.iter().filter(|p| p.scope == global) {
+            state.parameters.insert((p.id, global, 0), p.default);
+        }
+
+        if !self.component.is_output() {
+            state.define(
+                (kAudioUnitProperty_TailTime, global, 0),
+                &self.component.tail_time,
+                false,
+            );
+            state.define((kAudioUnitProperty_BypassEffect, global, 0), &0u32, true);
+            state.define((kAudioUnitProperty_InPlaceProcessing, global, 0), &1u32, true);
+            return;
+        }
+
+        state.define((kAudioOutputUnitProperty_CurrentDevice, global, 0), &SIM_DEVICE, true);
+        state.define((kAudioOutputUnitProperty_IsRunning, global, 0), &0u32, false);
+        state.define((kAudioOutputUnitProperty_StartTimestampsAtZero, global, 0), &1u32, true);
+        state.define(
+            (kAudioOutputUnitProperty_StartTime, global, 0),
+            &AudioOutputUnitStartAtTimeParams::default(),
+            true,
+        );
+        if self.component.has_device_io() {
+            state.define(
+                (kAudioOutputUnitProperty_SetInputCallback, global, 0),
+                &AURenderCallbackStruct::default(),
+                true,
+            );
+        }
+    }
+
+    fn define_parameter_list(&self, state: &mut State, scope: ffi::AudioUnitScope) {
+        let ids: Vec<ffi::AudioUnitParameterID> = self.component
+            .parameters
+            .iter()
+            .filter(|p| p.scope == scope)
+            .map(|p| p.id)
+            .collect();
+        state.define_variable((ffi::kAudioUnitProperty_ParameterList, scope, 0),
+                              bytes_of_slice(&ids), false);
+
+        // ParameterInfo is addressed by parameter ID in the element slot.
+        for p in self.component.parameters.iter().filter(|p| p.scope == scope) {
+            let mut info = ffi::AudioUnitParameterInfo::default();
+            for (dst, &src) in info.name.iter_mut().zip(p.name.as_bytes()) {
+                *dst = src as c_char;
+            }
+            info.unit = p.unit;
+            info.minValue = p.min;
+            info.maxValue = p.max;
+            info.defaultValue = p.default;
+            info.flags = p.flags;
+            state.define((ffi::kAudioUnitProperty_ParameterInfo, scope, p.id), &info, false);
+        }
+    }
+
+    /// Grow or shrink `scope` to `count` elements, giving new elements the
+    /// default per-element properties.
+    fn resize(&self, state: &mut State, scope: ffi::AudioUnitScope, count: u32) {
+        use ffi::*;
+        let device_io = self.component.has_device_io();
+        let old = match scope {
+            kAudioUnitScope_Input => mem::replace(&mut state.inputs, count),
+            _ => mem::replace(&mut state.outputs, count),
+        };
+        for element in count..old {
+            state.properties.retain(|k, _| {
+                k.1 != scope || k.2 != element || k.0 == kAudioUnitProperty_ParameterInfo
+            });
+            state.parameters.retain(|k, _| k.1 != scope || k.2 != element);
+            state.element_names.remove(&(scope, element));
+        }
+        if count > 0 && old == 0 {
+            self.define_parameter_list(state, scope);
+        }
+        for element in old..count {
+            let format = canonical_format(DEFAULT_SAMPLE_RATE, DEFAULT_CHANNELS);
+            state.define((kAudioUnitProperty_StreamFormat, scope, element), &format, true);
+            state.define((kAudioUnitProperty_PresentationLatency, scope, element), &0f64, true);
+            state.define_variable((kAudioOutputUnitProperty_ChannelMap, scope, element),
+                                  Vec::new(), self.component.is_output());
+            let layout = AudioChannelLayout {
+                mChannelLayoutTag: kAudioChannelLayoutTag_Stereo,
+                ..Default::default()
+            };
+            state.define_variable((kAudioUnitProperty_AudioChannelLayout, scope, element),
+                                  bytes_of(&layout)[..LAYOUT_HEADER_SIZE].to_vec(), true);
+            state.define_variable(
+                (kAudioUnitProperty_SupportedChannelLayoutTags, scope, element),
+                bytes_of_slice(SUPPORTED_LAYOUT_TAGS),
+                false,
+            );
+            if scope == kAudioUnitScope_Input && self.component.pulls_input() &&
+                !(device_io && element != 0)
+            {
+                state.define(
+                    (kAudioUnitProperty_SetRenderCallback, scope, element),
+                    &AURenderCallbackStruct::default(),
+                    true,
+                );
+            }
+            if self.component.is_output() {
+                // On I/O units element 1 is the device input and element 0
+                // the device output; only the output is enabled by default.
+                let has_io = if device_io { 1u32 } else { (element == 0) as u32 };
+                let enabled = (element == 0) as u32;
+                state.define((kAudioOutputUnitProperty_HasIO, scope, element), &has_io, false);
+                state.define((kAudioOutputUnitProperty_EnableIO, scope, element), &enabled,
+                             device_io);
+            }
+            for p in self.component.parameters.iter().filter(|p| p.scope == scope) {
+                state.parameters.insert((p.id, scope, element), p.default);
+            }
+        }
+    }
+
+    pub fn initialize(&self) -> ffi::OSStatus {
+        let mut state = self.state.lock().unwrap();
+        if self.component.has_device_io() {
+            let output = state.properties[&(ffi::kAudioOutputUnitProperty_EnableIO,
+                                            ffi::kAudioUnitScope_Output, 0)].data.clone();
+            let input = state.properties[&(ffi::kAudioOutputUnitProperty_EnableIO,
+                                           ffi::kAudioUnitScope_Input, 1)].data.clone();
+            if read::<u32>(&output) == 0 && read::<u32>(&input) == 0 {
+                return ffi::kAudioUnitErr_FailedInitialization;
+            }
+        }
+        if let Some(configs) = self.component.accepted_channel_configs() {
+            let input = state.stream_format(ffi::kAudioUnitScope_Input, 0);
+            let output = state.stream_format(ffi::kAudioUnitScope_Output, 0);
+            if let (Some(input), Some(output)) = (input, output) {
+                let channels = (input.mChannelsPerFrame, output.mChannelsPerFrame);
+                if !channel_configs_support(&configs, channels.0, channels.1) {
+                    return ffi::kAudioUnitErr_FormatNotSupported;
+                }
+            }
+        }
+        state.initialized = true;
+        0
+    }
+
+    pub fn uninitialize(&self) -> ffi::OSStatus {
+        let io = {
+            let mut state = self.state.lock().unwrap();
+            state.initialized = false;
+            state.io.take()
+        };
+        if let Some(io) = io {
+            self.finish(io);
+            self.set_running(false);
+        }
+        0
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.state.lock().unwrap().initialized
+    }
+
+    fn parameter(
+        &self,
+        scope: ffi::AudioUnitScope,
+        id: ffi::AudioUnitParameterID,
+    ) -> Result<&'static ParameterSpec, ffi::OSStatus> {
+        self.component
+            .parameters
+            .iter()
+            .find(|p| p.scope == scope && p.id == id)
+            .ok_or(ffi::kAudioUnitErr_InvalidParameter)
+    }
+
+    /// The unit's `ClassInfo`. Parameter values are saved in `data` as
+    /// big-endian (scope, element, id, value bits) records.
+    fn class_info(&self, state: &State) -> ClassInfo {
+        let mut keys: Vec<&Key> = state.parameters.keys().collect();
+        keys.sort();
+        let mut data = Vec::with_capacity(keys.len() * 16);
+        for key in keys {
+            let value = state.parameters[key].to_bits();
+            for &word in &[key.1, key.2, key.0, value] {
+                data.extend((0..4).rev().map(|i| (word >> (i * 8)) as u8));
+            }
+        }
+        let desc = &self.component.desc;
+        ClassInfo {
+            version: 0,
+            kind: desc.componentType,
+            sub_kind: desc.componentSubType,
+            manufacturer: desc.componentManufacturer,
+            name: state.preset_name.clone(),
+            data: Some(data),
+            extra: BTreeMap::new(),
+        }
+    }
+
+    fn restore(&self, state: &mut State, info: &ClassInfo) -> Result<(), ffi::OSStatus> {
+        let desc = &self.component.desc;
+        if (info.kind, info.sub_kind, info.manufacturer) !=
+            (desc.componentType, desc.componentSubType, desc.componentManufacturer)
+        {
+            return Err(ffi::kAudioUnitErr_InvalidPropertyValue);
+        }
+        let data = info.data.as_ref().map_or(&[][..], |data| &data[..]);
+        if data.len() % 16 != 0 {
+            return Err(ffi::kAudioUnitErr_InvalidPropertyValue);
+        }
+        for record in data.chunks(16) {
+            let word = |i: usize| {
+                record[i * 4..i * 4 + 4].iter().fold(0, |n, &b| n << 8 | b as u32)
+            };
+            // Values for elements the unit no longer has are dropped.
+            if let Some(value) = state.parameters.get_mut(&(word(2), word(0), word(1))) {
+                *value = f32::from_bits(word(3));
+            }
+        }
+        state.preset_number = -1;
+        state.preset_name = info.name.clone();
+        Ok(())
+    }
+
+    /// Make `preset` the present preset. Factory presets (those numbered
+    /// from zero) also load their parameter values; user presets only
+    /// change the name.
+    fn select_preset(
+        &self,
+        state: &mut State,
+        preset: &ffi::AUPreset,
+    ) -> Result<(), ffi::OSStatus> {
+        if preset.presetNumber < 0 {
+            if preset.presetName.is_null() {
+                return Err(ffi::kAudioUnitErr_InvalidPropertyValue);
+            }
+            state.preset_number = preset.presetNumber;
+            state.preset_name = unsafe { cf::as_str(preset.presetName) }.to_owned();
+            return Ok(());
+        }
+        let factory = self.component
+            .presets
+            .get(preset.presetNumber as usize)
+            .ok_or(ffi::kAudioUnitErr_InvalidPropertyValue)?;
+        let global = ffi::kAudioUnitScope_Global;
+        for p in self.component.parameters.iter().filter(|p| p.scope == global) {
+            state.parameters.insert((p.id, global, 0), p.default);
+        }
+        for &(id, value) in factory.values {
+            state.parameters.insert((id, global, 0), value);
+        }
+        state.preset_number = preset.presetNumber;
+        state.preset_name = factory.name.to_owned();
+        Ok(())
+    }
+
+    pub fn property_info(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) -> Result<(u32, bool), ffi::OSStatus> {
+        let state = self.state.lock().unwrap();
+        match id {
+            ffi::kAudioUnitProperty_SampleRate => {
+                state.check_element(scope, element)?;
+                Ok((mem::size_of::<f64>() as u32, scope != ffi::kAudioUnitScope_Global))
+            },
+            ffi::kAudioUnitProperty_ElementCount => {
+                if state.elements(scope).is_none() {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                let writable = scope == ffi::kAudioUnitScope_Input &&
+                    self.component.input_bus_count_writable();
+                Ok((mem::size_of::<u32>() as u32, writable))
+            },
+            ffi::kAudioUnitProperty_ElementName => {
+                state.check_element(scope, element)?;
+                Ok((mem::size_of::<ffi::CFStringRef>() as u32, true))
+            },
+            ffi::kAudioUnitProperty_ParameterValueStrings => {
+                if self.parameter(scope, element)?.value_strings.is_empty() {
+                    return Err(ffi::kAudioUnitErr_InvalidProperty);
+                }
+                Ok((mem::size_of::<ffi::CFArrayRef>() as u32, false))
+            },
+            ffi::kAudioUnitProperty_ParameterStringFromValue => {
+                state.check_element(scope, element)?;
+                Ok((mem::size_of::<ffi::AudioUnitParameterStringFromValue>() as u32, false))
+            },
+            ffi::kAudioUnitProperty_ParameterValueFromString => {
+                state.check_element(scope, element)?;
+                Ok((mem::size_of::<ffi::AudioUnitParameterValueFromString>() as u32, false))
+            },
+            ffi::kAudioUnitProperty_ClassInfo => {
+                if scope != ffi::kAudioUnitScope_Global {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                Ok((mem::size_of::<ffi::CFPropertyListRef>() as u32, true))
+            },
+            ffi::kAudioUnitProperty_FactoryPresets => {
+                if scope != ffi::kAudioUnitScope_Global {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                if self.component.presets.is_empty() {
+                    return Err(ffi::kAudioUnitErr_InvalidProperty);
+                }
+                Ok((mem::size_of::<ffi::CFArrayRef>() as u32, false))
+            },
+            ffi::kAudioUnitProperty_PresentPreset => {
+                if scope != ffi::kAudioUnitScope_Global {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                Ok((mem::size_of::<ffi::AUPreset>() as u32, true))
+            },
+            _ => {
+                let p = state.lookup((id, scope, element))?;
+                Ok((p.data.len() as u32, p.writable))
+            },
+        }
+    }
+
+    pub fn get_property(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        input: &[u8],
+    ) -> Result<Vec<u8>, ffi::OSStatus> {
+        let state = self.state.lock().unwrap();
+        match id {
+            ffi::kAudioUnitProperty_SampleRate => {
+                state.check_element(scope, element)?;
+                let format = match scope {
+                    ffi::kAudioUnitScope_Global => state.stream_format(ffi::kAudioUnitScope_Output, 0),
+                    _ => state.stream_format(scope, element),
+                };
+                let rate = format.map_or(DEFAULT_SAMPLE_RATE, |f| f.mSampleRate);
+                Ok(bytes_of(&rate))
+            },
+            ffi::kAudioUnitProperty_ElementCount => match state.elements(scope) {
+                Some(n) => Ok(bytes_of(&n)),
+                None => Err(ffi::kAudioUnitErr_InvalidScope),
+            },
+            ffi::kAudioUnitProperty_ElementName => {
+                state.check_element(scope, element)?;
+                match state.element_names.get(&(scope, element)) {
+                    // Retained for the caller, as AUBase does.
+                    Some(name) => Ok(bytes_of(&cf::string(name))),
+                    None => Err(ffi::kAudioUnitErr_PropertyNotInUse),
+                }
+            },
+            ffi::kAudioUnitProperty_ParameterInfo => {
+                // Like Apple's units, hand out a fresh name for the caller to
+                // release alongside the C string one.
+                let data = &state.lookup((id, scope, element))?.data;
+                let mut info: ffi::AudioUnitParameterInfo = read(data);
+                let name = unsafe { CStr::from_ptr(info.name.as_ptr()) };
+                info.cfNameString = cf::string(&name.to_string_lossy());
+                info.flags |= ffi::kAudioUnitParameterFlag_HasCFNameString |
+                    ffi::kAudioUnitParameterFlag_CFNameRelease;
+                Ok(bytes_of(&info))
+            },
+            // The strings and arrays below are created for the caller, who
+            // releases them.
+            ffi::kAudioUnitProperty_ParameterValueStrings => {
+                let strings = self.parameter(scope, element)?.value_strings;
+                if strings.is_empty() {
+                    return Err(ffi::kAudioUnitErr_InvalidProperty);
+                }
+                let items = strings.iter().map(|s| cf::string(s) as ffi::CFTypeRef).collect();
+                Ok(bytes_of(&cf::array(items)))
+            },
+            ffi::kAudioUnitProperty_ParameterStringFromValue => {
+                state.check_element(scope, element)?;
+                let mut query: ffi::AudioUnitParameterStringFromValue = read_input(input)?;
+                let param = self.parameter(scope, query.inParamID)?;
+                let value = if query.inValue.is_null() {
+                    state.parameters
+                        .get(&(param.id, scope, element))
+                        .cloned()
+                        .unwrap_or(param.default)
+                } else {
+                    unsafe { *query.inValue }
+                };
+                query.outString = cf::string(&param.display(value));
+                Ok(bytes_of(&query))
+            },
+            ffi::kAudioUnitProperty_ParameterValueFromString => {
+                state.check_element(scope, element)?;
+                let mut query: ffi::AudioUnitParameterValueFromString = read_input(input)?;
+                let param = self.parameter(scope, query.inParamID)?;
+                if query.inString.is_null() {
+                    return Err(ffi::kAudioUnitErr_InvalidPropertyValue);
+                }
+                let text = unsafe { cf::as_str(query.inString) };
+                query.outValue = param.parse(text).ok_or(ffi::kAudioUnitErr_InvalidPropertyValue)?;
+                Ok(bytes_of(&query))
+            },
+            ffi::kAudioUnitProperty_ClassInfo => {
+                if scope != ffi::kAudioUnitScope_Global {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                Ok(bytes_of(&cf::property_list(self.class_info(&state).to_plist())))
+            },
+            ffi::kAudioUnitProperty_FactoryPresets => {
+                if scope != ffi::kAudioUnitScope_Global {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                if self.component.presets.is_empty() {
+                    return Err(ffi::kAudioUnitErr_InvalidProperty);
+                }
+                let presets = self.component
+                    .presets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| ffi::AUPreset {
+                        presetNumber: i as i32,
+                        presetName: cf::string(p.name),
+                    })
+                    .collect();
+                Ok(bytes_of(&cf::presets(presets)))
+            },
+            ffi::kAudioUnitProperty_PresentPreset => {
+                if scope != ffi::kAudioUnitScope_Global {
+                    return Err(ffi::kAudioUnitErr_InvalidScope);
+                }
+                Ok(bytes_of(&ffi::AUPreset {
+                    presetNumber: state.preset_number,
+                    presetName: cf::string(&state.preset_name),
+                }))
+            },
+            _ => state.lookup((id, scope, element)).map(|p| p.data.clone()),
+        }
+    }
+
+    pub fn set_property(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data: &[u8],
+    ) -> ffi::OSStatus {
+        let replaces_callback = id == ffi::kAudioUnitProperty_SetRenderCallback ||
+            id == ffi::kAudioOutputUnitProperty_SetInputCallback;
+        let status = if replaces_callback {
+            // The caller may free the callback it replaces once this
+            // returns, so wait for any cycle that could still call it.
+            let _rendering = self.rendering.enter();
+            self.store_property(id, scope, element, data)
+        } else {
+            self.store_property(id, scope, element, data)
+        };
+        if status == 0 {
+            self.notify_listeners(id, scope, element);
+        }
+        status
+    }
+
+    fn store_property(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        data: &[u8],
+    ) -> ffi::OSStatus {
+        use ffi::*;
+        let mut state = self.state.lock().unwrap();
+        match id {
+            kAudioUnitProperty_SampleRate => {
+                if let Err(e) = state.check_element(scope, element) {
+                    return e;
+                }
+                if data.len() != mem::size_of::<f64>() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                let rate: f64 = read(data);
+                if !(rate > 0.0) {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                let key = (kAudioUnitProperty_StreamFormat, scope, element);
+                match state.properties.get_mut(&key) {
+                    Some(p) => {
+                        let mut format: AudioStreamBasicDescription = read(&p.data);
+                        format.mSampleRate = rate;
+                        p.data = bytes_of(&format);
+                        0
+                    },
+                    None => kAudioUnitErr_InvalidScope,
+                }
+            },
+            kAudioUnitProperty_ElementCount => {
+                if scope != kAudioUnitScope_Input || !self.component.input_bus_count_writable() {
+                    return kAudioUnitErr_PropertyNotWritable;
+                }
+                if data.len() != mem::size_of::<u32>() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                if state.initialized {
+                    return kAudioUnitErr_Initialized;
+                }
+                self.resize(&mut state, scope, read(data));
+                0
+            },
+            kAudioUnitProperty_ElementName => {
+                if let Err(e) = state.check_element(scope, element) {
+                    return e;
+                }
+                if data.len() != mem::size_of::<CFStringRef>() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                let name: CFStringRef = read(data);
+                if name.is_null() {
+                    state.element_names.remove(&(scope, element));
+                } else {
+                    let name = unsafe { cf::as_str(name) }.to_owned();
+                    state.element_names.insert((scope, element), name);
+                }
+                0
+            },
+            kAudioUnitProperty_ClassInfo => {
+                if scope != kAudioUnitScope_Global {
+                    return kAudioUnitErr_InvalidScope;
+                }
+                if data.len() != mem::size_of::<CFPropertyListRef>() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                let p: CFPropertyListRef = read(data);
+                if p.is_null() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                let info = match ClassInfo::from_plist(&unsafe { cf::to_plist(p) }) {
+                    Ok(info) => info,
+                    Err(_) => return kAudioUnitErr_InvalidPropertyValue,
+                };
+                match self.restore(&mut state, &info) {
+                    Ok(()) => 0,
+                    Err(e) => e,
+                }
+            },
+            kAudioUnitProperty_PresentPreset => {
+                if scope != kAudioUnitScope_Global {
+                    return kAudioUnitErr_InvalidScope;
+                }
+                if data.len() != mem::size_of::<AUPreset>() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                match self.select_preset(&mut state, &read(data)) {
+                    Ok(()) => 0,
+                    Err(e) => e,
+                }
+            },
+            _ => {
+                if let Err(e) = state.lookup((id, scope, element)) {
+                    return e;
+                }
+                let initialized = state.initialized;
+                let format = state.stream_format(scope, element);
+                let channels = format.map(|f| f.mChannelsPerFrame);
+                let p = state.properties.get_mut(&(id, scope, element)).unwrap();
+                if !p.writable {
+                    return kAudioUnitErr_PropertyNotWritable;
+                }
+                if p.fixed_size && data.len() != p.data.len() {
+                    return kAudioUnitErr_InvalidPropertyValue;
+                }
+                match id {
+                    kAudioUnitProperty_MaximumFramesPerSlice if initialized => {
+                        return kAudioUnitErr_Initialized;
+                    },
+                    kAudioUnitProperty_AudioChannelLayout => {
+                        let layout = layout_channels(data);
+                        if layout.is_none() || layout != channels {
+                            return kAudioUnitErr_InvalidPropertyValue;
+                        }
+                    },
+                    kAudioUnitProperty_StreamFormat => {
+                        let format: AudioStreamBasicDescription = read(data);
+                        if format.mFormatID != kAudioFormatLinearPCM ||
+                            !(format.mSampleRate > 0.0) ||
+                            format.mChannelsPerFrame == 0 ||
+                            format.mBytesPerFrame == 0
+                        {
+                            return kAudioUnitErr_FormatNotSupported;
+                        }
+                    },
+                    _ => {},
+                }
+                p.data = data.to_vec();
+                if id == kAudioUnitProperty_StreamFormat {
+                    self.match_layout(&mut state, scope, element);
+                }
+                0
+            },
+        }
+    }
+
+    /// Replace an element's channel layout with an unknown one if it no
+    /// longer matches the stream format's channel count.
+    fn match_layout(
+        &self,
+        state: &mut State,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) {
+        let channels = match state.stream_format(scope, element) {
+            Some(format) => format.mChannelsPerFrame,
+            None => return,
+        };
+        let key = (ffi::kAudioUnitProperty_AudioChannelLayout, scope, element);
+        if let Some(p) = state.properties.get_mut(&key) {
+            if layout_channels(&p.data) != Some(channels) {
+                let layout = ffi::AudioChannelLayout {
+                    mChannelLayoutTag: ffi::kAudioChannelLayoutTag_Unknown | channels,
+                    ..Default::default()
+                };
+                p.data = bytes_of(&layout)[..LAYOUT_HEADER_SIZE].to_vec();
+            }
+        }
+    }
+
+    /// Update a property the unit itself owns, bypassing writability.
+    fn update<T>(&self, id: ffi::AudioUnitPropertyID, scope: ffi::AudioUnitScope,
+                 element: ffi::AudioUnitElement, value: &T) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(p) = state.properties.get_mut(&(id, scope, element)) {
+                p.data = bytes_of(value);
+            }
+        }
+        self.notify_listeners(id, scope, element);
+    }
+
+    fn set_running(&self, running: bool) {
+        self.update(ffi::kAudioOutputUnitProperty_IsRunning, ffi::kAudioUnitScope_Global, 0,
+                    &(running as u32));
+    }
+
+    fn notify_listeners(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) {
+        // Listeners may call back into the unit, so don't hold the lock
+        // while they run.
+        let _notifying = self.notifying.enter();
+        let listeners: Vec<(ffi::AudioUnitPropertyListenerProc, usize)> = {
+            let state = self.state.lock().unwrap();
+            state.listeners
+                .iter()
+                .filter(|l| l.id == id)
+                .map(|l| (l.proc_, l.ref_con))
+                .collect()
+        };
+        for (proc_, ref_con) in listeners {
+            proc_(ref_con as *mut c_void, self.as_ptr(), id, scope, element);
+        }
+    }
+
+    pub fn add_property_listener(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        proc_: ffi::AudioUnitPropertyListenerProc,
+        ref_con: *mut c_void,
+    ) -> ffi::OSStatus {
+        let mut state = self.state.lock().unwrap();
+        state.listeners.push(Listener {
+            id,
+            proc_,
+            ref_con: ref_con as usize,
+        });
+        0
+    }
+
+    pub fn remove_property_listener(
+        &self,
+        id: ffi::AudioUnitPropertyID,
+        proc_: ffi::AudioUnitPropertyListenerProc,
+        ref_con: *mut c_void,
+    ) -> ffi::OSStatus {
+        let _notifying = self.notifying.enter();
+        let mut state = self.state.lock().unwrap();
+        state.listeners.retain(|l| {
+            !(l.id == id && l.proc_ as usize == proc_ as usize && l.ref_con == ref_con as usize)
+        });
+        0
+    }
+
+    pub fn add_render_notify(
+        &self,
+        proc_: ffi::AURenderCallback,
+        ref_con: *mut c_void,
+    ) -> ffi::OSStatus {
+        let mut state = self.state.lock().unwrap();
+        state.render_notify.push(RenderNotify {
+            proc_,
+            ref_con: ref_con as usize,
+        });
+        0
+    }
+
+    pub fn remove_render_notify(
+        &self,
+        proc_: ffi::AURenderCallback,
+        ref_con: *mut c_void,
+    ) -> ffi::OSStatus {
+        let _rendering = self.rendering.enter();
+        let mut state = self.state.lock().unwrap();
+        state.render_notify.retain(|n| {
+            !(n.proc_ as usize == proc_ as usize && n.ref_con == ref_con as usize)
+        });
+        0
+    }
+
+    pub fn get_parameter(
+        &self,
+        id: ffi::AudioUnitParameterID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+    ) -> Result<f32, ffi::OSStatus> {
+        let state = self.state.lock().unwrap();
+        state.check_element(scope, element)?;
+        state.parameters
+            .get(&(id, scope, element))
+            .cloned()
+            .ok_or(ffi::kAudioUnitErr_InvalidParameter)
+    }
+
+    pub fn set_parameter(
+        &self,
+        id: ffi::AudioUnitParameterID,
+        scope: ffi::AudioUnitScope,
+        element: ffi::AudioUnitElement,
+        value: f32,
+    ) -> ffi::OSStatus {
+        let mut state = self.state.lock().unwrap();
+        if let Err(e) = state.check_element(scope, element) {
+            return e;
+        }
+        match state.parameters.get_mut(&(id, scope, element)) {
+            Some(v) => {
+                *v = value;
+                0
+            },
+            None => ffi::kAudioUnitErr_InvalidParameter,
+        }
+    }
+
+    pub fn notify_parameter(&self, parameter: &ffi::AudioUnitParameter) {
+        let key = (parameter.mParameterID, parameter.mScope, parameter.mElement);
+        self.state.lock().unwrap().parameter_notifications.push(key);
+    }
+
+    /// The parameters `AUParameterListenerNotify` was called for since the
+    /// last call, as (ID, scope, element).
+    #[cfg(test)]
+    pub fn take_parameter_notifications(&self) -> Vec<Key> {
+        mem::replace(&mut self.state.lock().unwrap().parameter_notifications, Vec::new())
+    }
+
+    pub fn reset(&self, scope: ffi::AudioUnitScope, element: ffi::AudioUnitElement) -> ffi::OSStatus {
+        let state = self.state.lock().unwrap();
+        match state.check_element(scope, element) {
+            Ok(()) => 0,
+            Err(e) => e,
+        }
+    }
+
+    /// Render `frames` frames of output element `bus` into `data`.
+    pub unsafe fn render(
+        &self,
+        action: *mut ffi::AudioUnitRenderActionFlags,
+        time_stamp: *const ffi::AudioTimeStamp,
+        bus: u32,
+        frames: u32,
+        data: *mut ffi::AudioBufferList,
+    ) -> ffi::OSStatus {
+        use ffi::*;
+
+        let mut flags = if action.is_null() { 0 } else { *action };
+        let _rendering = self.rendering.enter();
+        let (notify, inputs, capture) = {
+            let mut state = self.state.lock().unwrap();
+            if !state.initialized {
+                return kAudioUnitErr_Uninitialized;
+            }
+            if bus >= state.outputs {
+                return kAudioUnitErr_InvalidElement;
+            }
+            let max: u32 = read(&state.properties[&(kAudioUnitProperty_MaximumFramesPerSlice,
+                                                    kAudioUnitScope_Global, 0)].data);
+            if frames > max {
+                return kAudioUnitErr_TooManyFramesToProcess;
+            }
+            // The output scope of an I/O unit's input element holds what
+            // the device captured.
+            let capture = self.component.has_device_io() && bus == 1;
+            if capture && !state.io_enabled(kAudioUnitScope_Input, 1) {
+                return kAudioUnitErr_NoConnection;
+            }
+            let format = state.stream_format(kAudioUnitScope_Output, bus)
+                .unwrap_or_else(|| canonical_format(DEFAULT_SAMPLE_RATE, DEFAULT_CHANNELS));
+            provide_buffers(&mut state.scratch, bus, &format, frames, data);
+
+            let inputs: Vec<(u32, AURenderCallbackStruct)> = (0..state.inputs)
+                .filter_map(|i| {
+                    state.properties
+                        .get(&(kAudioUnitProperty_SetRenderCallback, kAudioUnitScope_Input, i))
+                        .map(|p| (i, read::<AURenderCallbackStruct>(&p.data)))
+                })
+                .filter(|&(_, cb)| cb.inputProc.is_some())
+                .collect();
+            (state.render_notify.clone(), inputs, capture)
+        };
+
+        for n in &notify {
+            let mut pre = flags | kAudioUnitRenderAction_PreRender;
+            (n.proc_)(n.ref_con as *mut c_void, &mut pre, time_stamp, bus, frames, data);
+        }
+
+        let mut status = 0;
+        if capture {
+            // The simulated device records silence.
+            silence(data);
+            flags |= kAudioUnitRenderAction_OutputIsSilence;
+        } else if inputs.is_empty() || !self.component.pulls_input() {
+            if self.component.requires_input() {
+                status = kAudioUnitErr_NoConnection;
+            } else {
+                silence(data);
+                flags |= kAudioUnitRenderAction_OutputIsSilence;
+            }
+        } else if inputs.len() == 1 {
+            let (element, cb) = inputs[0];
+            status = (cb.inputProc.unwrap())(cb.inputProcRefCon, &mut flags, time_stamp,
+                                             element, frames, data);
+        } else {
+            status = mix_inputs(&inputs, &mut flags, time_stamp, frames, data);
+        }
+
+        for n in &notify {
+            let mut post = flags | kAudioUnitRenderAction_PostRender;
+            if status != 0 {
+                post |= kAudioUnitRenderAction_PostRenderError;
+            }
+            (n.proc_)(n.ref_con as *mut c_void, &mut post, time_stamp, bus, frames, data);
+        }
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(p) = state.properties
+                .get_mut(&(kAudioUnitProperty_LastRenderError, kAudioUnitScope_Global, 0))
+            {
+                p.data = bytes_of(&status);
+            }
+        }
+        if !action.is_null() {
+            *action = flags;
+        }
+        status
+    }
+
+    /// Whether the device loop should pull output element 0.
+    pub fn renders_output(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !self.component.has_device_io() || state.io_enabled(ffi::kAudioUnitScope_Output, 0)
+    }
+
+    /// Tell the input callback, if there is one, that the device has
+    /// captured `frames` frames.
+    pub unsafe fn capture(&self, time_stamp: *const ffi::AudioTimeStamp, frames: u32) {
+        use ffi::*;
+        let _rendering = self.rendering.enter();
+        let callback = {
+            let state = self.state.lock().unwrap();
+            if !state.io_enabled(kAudioUnitScope_Input, 1) {
+                return;
+            }
+            match state.properties.get(&(kAudioOutputUnitProperty_SetInputCallback,
+                                         kAudioUnitScope_Global, 0)) {
+                Some(p) => read::<AURenderCallbackStruct>(&p.data),
+                None => return,
+            }
+        };
+        if let Some(proc_) = callback.inputProc {
+            let mut flags = 0;
+            proc_(callback.inputProcRefCon, &mut flags, time_stamp, 1, frames,
+                  ptr::null_mut());
+        }
+    }
+
+    pub fn start(&self) -> ffi::OSStatus {
+        {
+            let mut state = self.state.lock().unwrap();
+            if !state.initialized {
+                return ffi::kAudioUnitErr_Uninitialized;
+            }
+            if state.io.is_some() {
+                return 0;
+            }
+            let format = state.stream_format(ffi::kAudioUnitScope_Output, 0)
+                .unwrap_or_else(|| canonical_format(DEFAULT_SAMPLE_RATE, DEFAULT_CHANNELS));
+            let frames: u32 = read(&state.properties[&(ffi::kAudioUnitProperty_MaximumFramesPerSlice,
+                                                       ffi::kAudioUnitScope_Global, 0)].data);
+            state.io = Some(RenderLoop::start(self, format, frames.min(512)));
+        }
+        self.set_running(true);
+        0
+    }
+
+    pub fn stop(&self) -> ffi::OSStatus {
+        let io = self.state.lock().unwrap().io.take();
+        if let Some(io) = io {
+            self.finish(io);
+            self.set_running(false);
+        }
+        0
+    }
+
+    /// Stop a render loop, waiting for it unless this is its own thread.
+    fn finish(&self, io: RenderLoop) {
+        io.signal();
+        if io.is_current() {
+            self.state.lock().unwrap().stopped.push(io);
+        } else {
+            io.join();
+        }
+    }
+}
+
+impl Drop for SimUnit {
+    /// Every render thread still holds a pointer to the unit, so all of
+    /// them, including those a callback stopped, are joined here. As with
+    /// AudioToolbox, a unit can't be disposed from its own render thread.
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        for io in state.io.take().into_iter().chain(state.stopped.drain(..)) {
+            io.signal();
+            io.join();
+        }
+    }
+}
+
+/// The number of channels in a serialized `AudioChannelLayout`, if it is
+/// well formed and uses a supported tag.
+fn layout_channels(data: &[u8]) -> Option<u32> {
+    use ffi::*;
+    if data.len() < LAYOUT_HEADER_SIZE {
+        return None;
+    }
+    let tag: AudioChannelLayoutTag = read(data);
+    let bitmap: AudioChannelBitmap = read(&data[4..]);
+    let descriptions: u32 = read(&data[8..]);
+    let size = mem::size_of::<AudioChannelDescription>();
+    if data.len() < LAYOUT_HEADER_SIZE + descriptions as usize * size {
+        return None;
+    }
+    match tag {
+        kAudioChannelLayoutTag_UseChannelDescriptions => Some(descriptions),
+        kAudioChannelLayoutTag_UseChannelBitmap => Some(bitmap.count_ones()),
+        _ if SUPPORTED_LAYOUT_TAGS.contains(&tag) => Some(tag & 0xFFFF),
+        _ => None,
+    }
+}
+
+pub fn bytes_of_slice<T>(values: &[T]) -> Vec<u8> {
+    unsafe {
+        slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * mem::size_of::<T>())
+            .to_vec()
+    }
+}
+
+pub unsafe fn buffers<'a>(data: *mut ffi::AudioBufferList) -> &'a mut [ffi::AudioBuffer] {
+    slice::from_raw_parts_mut((*data).mBuffers.as_mut_ptr(), (*data).mNumberBuffers as usize)
+}
+
+unsafe fn provide_buffers(
+    scratch: &mut HashMap<u32, Vec<u8>>,
+    bus: u32,
+    format: &ffi::AudioStreamBasicDescription,
+    frames: u32,
+    data: *mut ffi::AudioBufferList,
+) {
+    let buffers = buffers(data);
+    if buffers.iter().all(|b| !b.mData.is_null()) {
+        return;
+    }
+    let bytes = (frames * format.mBytesPerFrame) as usize;
+    let storage = scratch.entry(bus).or_insert_with(Vec::new);
+    storage.resize(bytes * buffers.len(), 0);
+    for (i, b) in buffers.iter_mut().enumerate() {
+        b.mData = storage.as_mut_ptr().offset((i * bytes) as isize) as *mut c_void;
+        b.mDataByteSize = bytes as u32;
+    }
+}
+
+unsafe fn silence(data: *mut ffi::AudioBufferList) {
+    for b in buffers(data) {
+        ptr::write_bytes(b.mData as *mut u8, 0, b.mDataByteSize as usize);
+    }
+}
+
+/// Pull every connected input and sum them into `data`. Like the real mixer
+/// this assumes the canonical float format.
+unsafe fn mix_inputs(
+    inputs: &[(u32, ffi::AURenderCallbackStruct)],
+    flags: &mut ffi::AudioUnitRenderActionFlags,
+    time_stamp: *const ffi::AudioTimeStamp,
+    frames: u32,
+    data: *mut ffi::AudioBufferList,
+) -> ffi::OSStatus {
+    let mut mix = super::io::BufferList::like(data);
+    silence(data);
+    for &(element, cb) in inputs {
+        let status = (cb.inputProc.unwrap())(cb.inputProcRefCon, flags, time_stamp, element,
+                                              frames, mix.as_mut_ptr());
+        if status != 0 {
+            return status;
+        }
+        for (out, input) in buffers(data).iter_mut().zip(buffers(mix.as_mut_ptr()).iter()) {
+            let n = (out.mDataByteSize.min(input.mDataByteSize) / 4) as usize;
+            let out = slice::from_raw_parts_mut(out.mData as *mut f32, n);
+            let input = slice::from_raw_parts(input.mData as *const f32, n);
+            for (o, i) in out.iter_mut().zip(input) {
+                *o += *i;
+            }
+        }
+    }
+    0
+}
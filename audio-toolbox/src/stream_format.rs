@@ -0,0 +1,459 @@
+use {Error, Result};
+use ffi;
+use std::{error, fmt};
+
+bitflags! {
+    pub struct FormatFlags: u32 {
+        const IS_FLOAT = ffi::kAudioFormatFlagIsFloat;
+        const IS_BIG_ENDIAN = ffi::kAudioFormatFlagIsBigEndian;
+        const IS_SIGNED_INTEGER = ffi::kAudioFormatFlagIsSignedInteger;
+        const IS_PACKED = ffi::kAudioFormatFlagIsPacked;
+        const IS_ALIGNED_HIGH = ffi::kAudioFormatFlagIsAlignedHigh;
+        const IS_NON_INTERLEAVED = ffi::kAudioFormatFlagIsNonInterleaved;
+        const IS_NON_MIXABLE = ffi::kAudioFormatFlagIsNonMixable;
+    }
+}
+
+impl FormatFlags {
+    /// `IS_BIG_ENDIAN` on big-endian targets, empty elsewhere.
+    pub fn native_endian() -> FormatFlags {
+        if cfg!(target_endian = "big") {
+            FormatFlags::IS_BIG_ENDIAN
+        } else {
+            FormatFlags::empty()
+        }
+    }
+}
+
+/// An `AudioStreamBasicDescription`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamFormat {
+    pub sample_rate: f64,
+    pub format_id: ffi::OSType,
+    pub flags: FormatFlags,
+    pub bytes_per_packet: u32,
+    pub frames_per_packet: u32,
+    pub bytes_per_frame: u32,
+    pub channels_per_frame: u32,
+    pub bits_per_channel: u32,
+}
+
+impl StreamFormat {
+    /// Linear PCM with one frame per packet and `bytes_per_sample` bytes
+    /// holding each `bits`-bit sample. Fails if a frame doesn't fit in 32
+    /// bits.
+    pub fn linear_pcm(
+        sample_rate: f64,
+        channels: u32,
+        bits: u32,
+        bytes_per_sample: u32,
+        flags: FormatFlags,
+    ) -> Result<StreamFormat> {
+        let bytes_per_frame = if flags.contains(FormatFlags::IS_NON_INTERLEAVED) {
+            bytes_per_sample
+        } else {
+            match bytes_per_sample.checked_mul(channels) {
+                Some(bytes) => bytes,
+                None => return Err(StreamFormatError::TooLarge.into()),
+            }
+        };
+        Ok(StreamFormat {
+            sample_rate,
+            format_id: ffi::kAudioFormatLinearPCM,
+            flags: flags | FormatFlags::native_endian(),
+            bytes_per_packet: bytes_per_frame,
+            frames_per_packet: 1,
+            bytes_per_frame,
+            channels_per_frame: channels,
+            bits_per_channel: bits,
+        })
+    }
+
+    /// A builder for linear PCM, starting from the canonical format.
+    pub fn builder(sample_rate: f64, channels: u32) -> StreamFormatBuilder {
+        StreamFormatBuilder::new(sample_rate, channels)
+    }
+
+    /// The canonical AudioUnit format: native-endian float32, one buffer
+    /// per channel.
+    pub fn float32(sample_rate: f64, channels: u32) -> Result<StreamFormat> {
+        StreamFormat::builder(sample_rate, channels).build()
+    }
+
+    pub fn float32_interleaved(sample_rate: f64, channels: u32) -> Result<StreamFormat> {
+        StreamFormat::builder(sample_rate, channels).interleaved(true).build()
+    }
+
+    /// Interleaved signed 16-bit integers.
+    pub fn int16(sample_rate: f64, channels: u32) -> Result<StreamFormat> {
+        StreamFormat::builder(sample_rate, channels)
+            .signed_integer(16)
+            .interleaved(true)
+            .build()
+    }
+
+    /// Interleaved signed 24-bit integers packed into three bytes.
+    pub fn int24_packed(sample_rate: f64, channels: u32) -> Result<StreamFormat> {
+        StreamFormat::builder(sample_rate, channels)
+            .signed_integer(24)
+            .interleaved(true)
+            .build()
+    }
+
+    /// Interleaved signed 32-bit integers.
+    pub fn int32(sample_rate: f64, channels: u32) -> Result<StreamFormat> {
+        StreamFormat::builder(sample_rate, channels)
+            .signed_integer(32)
+            .interleaved(true)
+            .build()
+    }
+
+    pub fn is_linear_pcm(&self) -> bool {
+        self.format_id == ffi::kAudioFormatLinearPCM
+    }
+
+    pub fn is_float(&self) -> bool {
+        self.flags.contains(FormatFlags::IS_FLOAT)
+    }
+
+    pub fn is_interleaved(&self) -> bool {
+        !self.flags.contains(FormatFlags::IS_NON_INTERLEAVED)
+    }
+
+    /// Check that the sizes in a linear PCM format agree with each other.
+    /// Other formats are passed through unchecked.
+    pub fn validate(&self) -> Result<()> {
+        use self::StreamFormatError::*;
+
+        if !self.is_linear_pcm() {
+            return Ok(());
+        }
+        if !(self.sample_rate > 0.0 && self.sample_rate.is_finite()) {
+            return Err(SampleRate.into());
+        }
+        if self.channels_per_frame == 0 {
+            return Err(NoChannels.into());
+        }
+        if self.frames_per_packet != 1 {
+            return Err(FramesPerPacket(self.frames_per_packet).into());
+        }
+        let bits = self.bits_per_channel;
+        if bits == 0 || (self.is_float() && bits != 32 && bits != 64) {
+            return Err(BitsPerChannel(bits).into());
+        }
+
+        // Each buffer holds one channel if non-interleaved, all of them
+        // otherwise.
+        let samples_per_frame = if self.is_interleaved() {
+            self.channels_per_frame
+        } else {
+            1
+        };
+        let bytes_per_sample = bits / 8 + (bits % 8 != 0) as u32;
+        let expected = match samples_per_frame.checked_mul(bytes_per_sample) {
+            Some(expected) => expected,
+            None => return Err(TooLarge.into()),
+        };
+        let fits = if self.flags.contains(FormatFlags::IS_PACKED) {
+            self.bytes_per_frame == expected
+        } else {
+            self.bytes_per_frame >= expected &&
+                self.bytes_per_frame % samples_per_frame == 0
+        };
+        if !fits {
+            return Err(BytesPerFrame {
+                expected,
+                actual: self.bytes_per_frame,
+            }.into());
+        }
+        if self.bytes_per_packet != self.bytes_per_frame * self.frames_per_packet {
+            return Err(BytesPerPacket {
+                expected: self.bytes_per_frame * self.frames_per_packet,
+                actual: self.bytes_per_packet,
+            }.into());
+        }
+        Ok(())
+    }
+}
+
+/// Builds a linear PCM `StreamFormat` from its sample type and layout,
+/// working out the byte counts. It starts out as the canonical format,
+/// non-interleaved float32.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamFormatBuilder {
+    sample_rate: f64,
+    channels: u32,
+    bits: u32,
+    bytes_per_sample: u32,
+    flags: FormatFlags,
+}
+
+impl StreamFormatBuilder {
+    pub fn new(sample_rate: f64, channels: u32) -> Self {
+        StreamFormatBuilder {
+            sample_rate,
+            channels,
+            bits: 32,
+            bytes_per_sample: 4,
+            flags: FormatFlags::IS_FLOAT | FormatFlags::IS_PACKED |
+                FormatFlags::IS_NON_INTERLEAVED,
+        }
+    }
+
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn channels(mut self, channels: u32) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Packed floating-point samples of `bits` bits.
+    pub fn float(self, bits: u32) -> Self {
+        self.sample_type(FormatFlags::IS_FLOAT, bits)
+    }
+
+    /// Packed signed integer samples of `bits` bits.
+    pub fn signed_integer(self, bits: u32) -> Self {
+        self.sample_type(FormatFlags::IS_SIGNED_INTEGER, bits)
+    }
+
+    fn sample_type(mut self, kind: FormatFlags, bits: u32) -> Self {
+        self.flags.remove(
+            FormatFlags::IS_FLOAT | FormatFlags::IS_SIGNED_INTEGER |
+                FormatFlags::IS_ALIGNED_HIGH,
+        );
+        self.flags.insert(kind | FormatFlags::IS_PACKED);
+        self.bits = bits;
+        self.bytes_per_sample = bits / 8 + (bits % 8 != 0) as u32;
+        self
+    }
+
+    /// Hold each sample in `bytes` bytes, in the high bits if
+    /// `aligned_high` and the low ones otherwise. Call this after choosing
+    /// the sample type, which resets it.
+    pub fn unpacked(mut self, bytes: u32, aligned_high: bool) -> Self {
+        self.flags.remove(FormatFlags::IS_PACKED);
+        self.flags.set(FormatFlags::IS_ALIGNED_HIGH, aligned_high);
+        self.bytes_per_sample = bytes;
+        self
+    }
+
+    /// All channels in one buffer, rather than a buffer per channel.
+    pub fn interleaved(mut self, interleaved: bool) -> Self {
+        self.flags.set(FormatFlags::IS_NON_INTERLEAVED, !interleaved);
+        self
+    }
+
+    /// The format, if it passes `StreamFormat::validate`.
+    pub fn build(self) -> Result<StreamFormat> {
+        let format = StreamFormat::linear_pcm(
+            self.sample_rate,
+            self.channels,
+            self.bits,
+            self.bytes_per_sample,
+            self.flags,
+        )?;
+        format.validate()?;
+        Ok(format)
+    }
+}
+
+impl ::std::convert::From<ffi::AudioStreamBasicDescription> for StreamFormat {
+    fn from(asbd: ffi::AudioStreamBasicDescription) -> Self {
+        StreamFormat {
+            sample_rate: asbd.mSampleRate,
+            format_id: asbd.mFormatID,
+            // Keep bits without a name, such as linear PCM's sample
+            // fraction, so that a format read from a unit can be written back
+            // unchanged.
+            flags: unsafe { FormatFlags::from_bits_unchecked(asbd.mFormatFlags) },
+            bytes_per_packet: asbd.mBytesPerPacket,
+            frames_per_packet: asbd.mFramesPerPacket,
+            bytes_per_frame: asbd.mBytesPerFrame,
+            channels_per_frame: asbd.mChannelsPerFrame,
+            bits_per_channel: asbd.mBitsPerChannel,
+        }
+    }
+}
+
+impl ::std::convert::Into<ffi::AudioStreamBasicDescription> for StreamFormat {
+    fn into(self) -> ffi::AudioStreamBasicDescription {
+        ffi::AudioStreamBasicDescription {
+            mSampleRate: self.sample_rate,
+            mFormatID: self.format_id,
+            mFormatFlags: self.flags.bits(),
+            mBytesPerPacket: self.bytes_per_packet,
+            mFramesPerPacket: self.frames_per_packet,
+            mBytesPerFrame: self.bytes_per_frame,
+            mChannelsPerFrame: self.channels_per_frame,
+            mBitsPerChannel: self.bits_per_channel,
+            mReserved: 0,
+        }
+    }
+}
+
+/// Why `StreamFormat::validate` rejected a format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamFormatError {
+    SampleRate,
+    NoChannels,
+    /// Linear PCM always has one frame per packet.
+    FramesPerPacket(u32),
+    BitsPerChannel(u32),
+    BytesPerFrame { expected: u32, actual: u32 },
+    BytesPerPacket { expected: u32, actual: u32 },
+    /// A frame of the format would need more than `u32::MAX` bytes.
+    TooLarge,
+}
+
+impl ::std::convert::From<StreamFormatError> for Error {
+    fn from(e: StreamFormatError) -> Self {
+        Error::StreamFormat(e)
+    }
+}
+
+impl fmt::Display for StreamFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::StreamFormatError::*;
+        match *self {
+            SampleRate => write!(f, "sample rate must be positive"),
+            NoChannels => write!(f, "format has no channels"),
+            FramesPerPacket(n) => {
+                write!(f, "linear PCM needs 1 frame per packet, not {}", n)
+            },
+            BitsPerChannel(n) => write!(f, "{} bits per channel is not supported", n),
+            BytesPerFrame { expected, actual } => write!(
+                f,
+                "{} bytes per frame doesn't match the channels and bit depth \
+                 (expected {})",
+                actual,
+                expected
+            ),
+            BytesPerPacket { expected, actual } => write!(
+                f,
+                "{} bytes per packet should be bytes per frame times frames per \
+                 packet ({})",
+                actual,
+                expected
+            ),
+            TooLarge => write!(f, "frames are too large to describe"),
+        }
+    }
+}
+
+impl error::Error for StreamFormatError {
+    fn description(&self) -> &str {
+        "invalid stream format"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::StreamFormatError::*;
+
+    fn rejects(format: StreamFormat, error: StreamFormatError) {
+        assert_eq!(format.validate(), Err(Error::StreamFormat(error)));
+    }
+
+    #[test]
+    fn constructors_describe_their_layout() {
+        let float = StreamFormat::float32(44100.0, 2).unwrap();
+        assert_eq!((float.bytes_per_frame, float.bytes_per_packet), (4, 4));
+        assert!(float.is_float() && !float.is_interleaved());
+
+        let interleaved = StreamFormat::float32_interleaved(48000.0, 2).unwrap();
+        assert_eq!(interleaved.bytes_per_frame, 8);
+        assert_eq!(StreamFormat::int16(44100.0, 2).unwrap().bytes_per_frame, 4);
+        assert_eq!(StreamFormat::int24_packed(44100.0, 2).unwrap().bytes_per_frame, 6);
+        let int32 = StreamFormat::int32(44100.0, 6).unwrap();
+        assert_eq!((int32.bytes_per_frame, int32.bits_per_channel), (24, 32));
+        assert!(!int32.is_float());
+        assert!(int32.flags.contains(FormatFlags::IS_SIGNED_INTEGER));
+        let endian = int32.flags & FormatFlags::IS_BIG_ENDIAN;
+        assert_eq!(endian, FormatFlags::native_endian());
+    }
+
+    #[test]
+    fn builder_matches_constructors() {
+        let built = StreamFormat::builder(8000.0, 1)
+            .signed_integer(16)
+            .interleaved(true)
+            .sample_rate(44100.0)
+            .channels(2)
+            .build()
+            .unwrap();
+        assert_eq!(built, StreamFormat::int16(44100.0, 2).unwrap());
+        let double = StreamFormat::builder(44100.0, 2).float(64).build().unwrap();
+        assert_eq!(double.bytes_per_frame, 8);
+    }
+
+    #[test]
+    fn builder_unpacks_samples() {
+        let format = StreamFormat::builder(44100.0, 2)
+            .signed_integer(24)
+            .unpacked(4, true)
+            .interleaved(true)
+            .build()
+            .unwrap();
+        assert_eq!((format.bits_per_channel, format.bytes_per_frame), (24, 8));
+        assert!(!format.flags.contains(FormatFlags::IS_PACKED));
+        assert!(format.flags.contains(FormatFlags::IS_ALIGNED_HIGH));
+        let repacked = StreamFormat::builder(44100.0, 2)
+            .unpacked(8, false)
+            .signed_integer(16)
+            .build()
+            .unwrap();
+        assert_eq!(repacked.bytes_per_frame, 2);
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_sizes() {
+        let format = StreamFormat::int16(44100.0, 2).unwrap();
+        rejects(StreamFormat { sample_rate: 0.0, ..format }, SampleRate);
+        rejects(StreamFormat { channels_per_frame: 0, ..format }, NoChannels);
+        rejects(StreamFormat { frames_per_packet: 2, ..format }, FramesPerPacket(2));
+        rejects(
+            StreamFormat { bytes_per_frame: 6, bytes_per_packet: 6, ..format },
+            BytesPerFrame { expected: 4, actual: 6 },
+        );
+        rejects(
+            StreamFormat { bytes_per_packet: 8, ..format },
+            BytesPerPacket { expected: 4, actual: 8 },
+        );
+        let float = StreamFormat::float32(44100.0, 2).unwrap();
+        rejects(StreamFormat { bits_per_channel: 16, ..float }, BitsPerChannel(16));
+        let aac = ::FourCC::new(b"aac ").into();
+        let compressed = StreamFormat { format_id: aac, ..format };
+        assert!(compressed.validate().is_ok());
+    }
+
+    #[test]
+    fn unnamed_flags_survive_a_round_trip() {
+        let int32 = StreamFormat::int32(44100.0, 2).unwrap();
+        let mut asbd: ffi::AudioStreamBasicDescription = int32.into();
+        // 8.24 fixed point: 24 in kLinearPCMFormatFlagsSampleFractionMask.
+        asbd.mFormatFlags |= 24 << 7;
+        let format = StreamFormat::from(asbd);
+        assert!(format.flags.contains(FormatFlags::IS_SIGNED_INTEGER));
+        assert!(format != int32);
+        let back: ffi::AudioStreamBasicDescription = format.into();
+        assert_eq!(back.mFormatFlags, asbd.mFormatFlags);
+    }
+
+    #[test]
+    fn oversized_frames_are_errors() {
+        let error = Error::StreamFormat(TooLarge);
+        assert_eq!(StreamFormat::int32(44100.0, 0x4000_0000).unwrap_err(), error);
+        let format = StreamFormat::int32(44100.0, 2).unwrap();
+        let bits = u32::max_value();
+        rejects(
+            StreamFormat { bits_per_channel: bits, channels_per_frame: 8, ..format },
+            TooLarge,
+        );
+        assert!(StreamFormat::builder(44100.0, 1).float(24).build().is_err());
+    }
+}
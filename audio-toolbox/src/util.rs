@@ -1,9 +1,96 @@
-use audio_toolbox_sys as ffi;
+use backend;
+use ffi;
 use call;
+use std::mem;
+use std::time::Duration;
 
 pub unsafe fn component_instance_dispose(instance: ffi::AudioComponentInstance) {
-    call::cvt_r(ffi::AudioComponentInstanceDispose(instance)).expect("Disposing \
-                                                                      component \
-                                                                      instance should \
-                                                                      succeed.");
+    call::cvt_r(backend::dispose(instance))
+        .expect("Disposing component instance should succeed.");
+}
+
+/// Offset of the trailing array in a C struct `T` that ends in a one element
+/// array of `E` standing in for a variable-length one. Like the
+/// AudioToolbox structs, `T` must have no padding after the array.
+pub fn trailing_offset<T, E>() -> usize {
+    assert!(mem::size_of::<T>() >= mem::size_of::<E>());
+    mem::size_of::<T>() - mem::size_of::<E>()
+}
+
+/// Zeroed storage for `size` bytes of property data, aligned for any of the
+/// AudioToolbox structs.
+pub fn property_buffer(size: usize) -> Vec<u64> {
+    vec![0; (size + mem::size_of::<u64>() - 1) / mem::size_of::<u64>()]
+}
+
+/// A time in seconds, as AudioToolbox reports latencies, as a `Duration`.
+/// Negative and NaN times become zero and times too long for a `Duration`
+/// saturate.
+pub fn seconds_to_duration(seconds: f64) -> Duration {
+    if !(seconds > 0.0) {
+        return Duration::new(0, 0);
+    }
+    let whole = seconds.trunc();
+    if whole >= u64::max_value() as f64 {
+        return Duration::new(u64::max_value(), 999_999_999);
+    }
+    Duration::new(whole as u64, ((seconds - whole) * 1e9).round() as u32)
+}
+
+pub fn duration_to_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+/// The number of frames `duration` spans at `rate`, rounded up. Whole rates
+/// are multiplied out in integer nanoseconds, so exact counts stay exact.
+pub fn duration_to_frames(duration: Duration, rate: f64) -> u64 {
+    let nanos = duration.as_secs() as u128 * 1_000_000_000 +
+        duration.subsec_nanos() as u128;
+    if rate >= 0.0 && rate.fract() == 0.0 && rate <= u64::max_value() as f64 {
+        match nanos.checked_mul(rate as u128) {
+            Some(n) => {
+                let frames = (n + 999_999_999) / 1_000_000_000;
+                frames.min(u64::max_value() as u128) as u64
+            },
+            None => u64::max_value(),
+        }
+    } else {
+        (nanos as f64 * rate / 1e9).ceil() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_become_durations() {
+        assert_eq!(seconds_to_duration(1.5), Duration::new(1, 500_000_000));
+        assert_eq!(seconds_to_duration(0.012), Duration::from_millis(12));
+        assert_eq!(seconds_to_duration(1e-10), Duration::new(0, 0));
+        // Nanoseconds that round up to a whole second carry.
+        assert_eq!(seconds_to_duration(2.9999999999), Duration::new(3, 0));
+    }
+
+    #[test]
+    fn seconds_out_of_range_are_clamped() {
+        let max = Duration::new(u64::max_value(), 999_999_999);
+        assert_eq!(seconds_to_duration(::std::f64::NAN), Duration::new(0, 0));
+        assert_eq!(seconds_to_duration(-1.0), Duration::new(0, 0));
+        assert_eq!(seconds_to_duration(::std::f64::NEG_INFINITY), Duration::new(0, 0));
+        assert_eq!(seconds_to_duration(::std::f64::INFINITY), max);
+        assert_eq!(seconds_to_duration(1e30), max);
+    }
+
+    #[test]
+    fn frames_round_up_only_when_inexact() {
+        assert_eq!(duration_to_frames(Duration::from_millis(70), 44100.0), 3087);
+        assert_eq!(duration_to_frames(Duration::new(0, 1), 44100.0), 1);
+        assert_eq!(duration_to_frames(Duration::new(0, 0), 44100.0), 0);
+        assert_eq!(duration_to_frames(Duration::from_secs(2), 48000.0), 96000);
+        assert_eq!(duration_to_frames(Duration::from_millis(10), 22050.5), 221);
+        let forever = Duration::new(u64::max_value(), 0);
+        assert_eq!(duration_to_frames(forever, 1e18), u64::max_value());
+        assert_eq!(duration_to_frames(Duration::from_secs(1), ::std::f64::NAN), 0);
+    }
 }
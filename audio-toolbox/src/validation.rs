@@ -0,0 +1,188 @@
+use {AudioComponent, ConfigurationInfo, Result};
+use call;
+use cf;
+use ffi;
+use plist;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use util;
+
+/// The outcome of validating a component, as `auval` would.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationResult {
+    /// Not validated, or a result this crate doesn't know.
+    Unknown,
+    Passed,
+    Failed,
+    TimedOut,
+    /// The component couldn't be opened without the user's authorization.
+    UnauthorizedOpen,
+    /// The component couldn't be initialized without the user's
+    /// authorization.
+    UnauthorizedInit,
+}
+
+impl ValidationResult {
+    pub fn passed(&self) -> bool {
+        *self == ValidationResult::Passed
+    }
+}
+
+impl ::std::convert::From<ffi::AudioComponentValidationResult> for ValidationResult {
+    fn from(result: ffi::AudioComponentValidationResult) -> Self {
+        use self::ValidationResult::*;
+        match result {
+            ffi::kAudioComponentValidationResult_Passed => Passed,
+            ffi::kAudioComponentValidationResult_Failed => Failed,
+            ffi::kAudioComponentValidationResult_TimedOut => TimedOut,
+            ffi::kAudioComponentValidationResult_UnauthorizedError_Open => {
+                UnauthorizedOpen
+            },
+            ffi::kAudioComponentValidationResult_UnauthorizedError_Init => {
+                UnauthorizedInit
+            },
+            _ => Unknown,
+        }
+    }
+}
+
+impl ::std::convert::From<ValidationResult> for ffi::AudioComponentValidationResult {
+    fn from(result: ValidationResult) -> Self {
+        use self::ValidationResult::*;
+        match result {
+            Unknown => ffi::kAudioComponentValidationResult_Unknown,
+            Passed => ffi::kAudioComponentValidationResult_Passed,
+            Failed => ffi::kAudioComponentValidationResult_Failed,
+            TimedOut => ffi::kAudioComponentValidationResult_TimedOut,
+            UnauthorizedOpen => {
+                ffi::kAudioComponentValidationResult_UnauthorizedError_Open
+            },
+            UnauthorizedInit => {
+                ffi::kAudioComponentValidationResult_UnauthorizedError_Init
+            },
+        }
+    }
+}
+
+/// The name AudioToolbox files results for the running architecture under.
+fn architecture() -> &'static str {
+    match ::std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        "x86" => "i386",
+        arch => arch,
+    }
+}
+
+impl ConfigurationInfo {
+    /// The cached result of the last validation, if the component has been
+    /// validated. AudioToolbox keeps one result per architecture.
+    pub fn validation_result(&self) -> Option<ValidationResult> {
+        let key = ffi::kAudioComponentConfigurationInfo_ValidationResult;
+        let value = self.extra.get(key)?;
+        let result = match value.as_dictionary() {
+            Some(results) => results.get(architecture())?.as_integer()?,
+            None => value.as_integer()?,
+        };
+        Some(ValidationResult::from(result as ffi::AudioComponentValidationResult))
+    }
+}
+
+impl AudioComponent {
+    /// Validate the component, waiting up to `timeout` (AudioToolbox's
+    /// default if `None`). Unless `force` is set, a component that has
+    /// already been validated returns its cached result.
+    pub fn validate(
+        &self,
+        timeout: Option<Duration>,
+        force: bool,
+    ) -> Result<ValidationResult> {
+        let mut params = BTreeMap::new();
+        if let Some(timeout) = timeout {
+            let key = ffi::kAudioComponentValidationParameter_TimeOut;
+            let seconds = util::duration_to_seconds(timeout);
+            params.insert(key.to_owned(), plist::Value::Real(seconds));
+        }
+        let key = ffi::kAudioComponentValidationParameter_ForceValidation;
+        params.insert(key.to_owned(), plist::Value::Boolean(force));
+        let params = cf::create_property_list(&plist::Value::Dictionary(params))?;
+
+        let mut result = ffi::kAudioComponentValidationResult_Unknown;
+        let status = unsafe {
+            ffi::AudioComponentValidate(
+                self.as_ptr(),
+                params as ffi::CFDictionaryRef,
+                &mut result,
+            )
+        };
+        unsafe { ffi::CFRelease(params) };
+        call::cvt_r(status)?;
+        Ok(result.into())
+    }
+
+    /// The cached validation result, without validating the component.
+    pub fn validation_result(&self) -> Result<Option<ValidationResult>> {
+        Ok(self.configuration_info()?.validation_result())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(result: plist::Value) -> ConfigurationInfo {
+        let mut info = ConfigurationInfo::default();
+        let key = ffi::kAudioComponentConfigurationInfo_ValidationResult;
+        info.extra.insert(key.to_owned(), result);
+        info
+    }
+
+    #[test]
+    fn cached_result_is_read_for_this_architecture() {
+        let mut results = BTreeMap::new();
+        results.insert(architecture().to_owned(), plist::Value::Integer(2));
+        results.insert("ppc".to_owned(), plist::Value::Integer(1));
+        let info = info(plist::Value::Dictionary(results));
+        assert_eq!(info.validation_result(), Some(ValidationResult::Failed));
+        assert_eq!(ConfigurationInfo::default().validation_result(), None);
+    }
+
+    #[test]
+    fn results_round_trip() {
+        for code in 0..6 {
+            let result = ValidationResult::from(code);
+            assert_eq!(ffi::AudioComponentValidationResult::from(result), code);
+        }
+        assert_eq!(ValidationResult::from(99), ValidationResult::Unknown);
+        let info = info(plist::Value::Integer(1));
+        assert!(info.validation_result().map_or(false, |r| r.passed()));
+    }
+
+    #[cfg(any(feature = "sim", not(target_vendor = "apple")))]
+    mod sim {
+        use super::*;
+        use sim::{cache_validation_result, register_effect};
+        use ValidationResult::{Failed, Passed};
+
+        #[test]
+        fn results_are_cached_until_forced() {
+            let effect = register_effect(&[(-1, -1)]);
+            assert_eq!(effect.validation_result().unwrap(), None);
+            assert_eq!(effect.validate(None, false).unwrap(), Passed);
+            assert_eq!(effect.validation_result().unwrap(), Some(Passed));
+
+            let stale = Some(ffi::kAudioComponentValidationResult_Failed);
+            cache_validation_result(&effect, stale);
+            let timeout = Some(Duration::from_secs(10));
+            assert_eq!(effect.validate(timeout, false).unwrap(), Failed);
+            assert_eq!(effect.validate(timeout, true).unwrap(), Passed);
+            assert_eq!(effect.validation_result().unwrap(), Some(Passed));
+        }
+
+        #[test]
+        fn components_that_fail_to_initialize_fail() {
+            let mono = register_effect(&[(1, 1)]);
+            assert_eq!(mono.validate(None, false).unwrap(), Failed);
+            assert_eq!(mono.validation_result().unwrap(), Some(Failed));
+        }
+    }
+}